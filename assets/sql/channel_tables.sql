@@ -7,12 +7,18 @@ CREATE TABLE IF NOT EXISTS CHANNEL_{{ CHANNEL_ID }} (
 	message     TEXT
 );
 
-CREATE TABLE IF NOT EXISTS CHANNEl_{{ CHANNEL_ID }}_MARKOV (
-	id INTEGER PRIMARY KEY,
-	word TEXT NOT NULL,
-	succ TEXT NOT NULL
+-- every pair of consecutive words is saved only once,
+-- along with how many times it has been seen
+CREATE TABLE IF NOT EXISTS CHANNEL_{{ CHANNEL_ID }}_MARKOV (
+	word  TEXT NOT NULL,
+	succ  TEXT NOT NULL,
+	count INTEGER NOT NULL DEFAULT 1,
+	PRIMARY KEY (word, succ)
 );
 
+CREATE INDEX IF NOT EXISTS CHANNEL_{{ CHANNEL_ID }}_MARKOV_WORD
+	ON CHANNEL_{{ CHANNEL_ID }}_MARKOV (word COLLATE NOCASE);
+
 CREATE TABLE IF NOT EXISTS CHANNEL_{{ CHANNEL_ID }}_OFFLINERS (
 	id          INTEGER PRIMARY KEY,
 	offliner_id INTEGER NOT NULL UNIQUE,
@@ -24,7 +30,14 @@ CREATE TABLE IF NOT EXISTS CHANNEL_{{ CHANNEL_ID }}_COMMANDS (
 	name        TEXT UNIQUE,
 	type        TEXT NOT NULL,
 	expression  TEXT NOT NULL,
-	metadata    INTEGER DEFAULT 0
+	-- the count of `incr` commands
+	metadata    INTEGER DEFAULT 0,
+	uses        INTEGER NOT NULL DEFAULT 0,
+	creator     TEXT NOT NULL DEFAULT '',
+	created_at  TEXT,
+	permission  TEXT NOT NULL DEFAULT 'everyone',
+	cooldown_s  INTEGER NOT NULL DEFAULT 0,
+	last_used   TEXT
 );
 
 CREATE TABLE IF NOT EXISTS CHANNEL_{{ CHANNEL_ID }}_HOOKS (
@@ -32,5 +45,11 @@ CREATE TABLE IF NOT EXISTS CHANNEL_{{ CHANNEL_ID }}_HOOKS (
 	name           TEXT UNIQUE,
 	type           TEXT NOT NULL,
 	capture_string TEXT NOT NULL,
-	content        TEXT NOT NULL
+	content        TEXT NOT NULL,
+	-- (users and badges are separated by commas)
+	cooldown_s     INTEGER NOT NULL DEFAULT 0,
+	users          TEXT NOT NULL DEFAULT '',
+	badges         TEXT NOT NULL DEFAULT '',
+	enabled        INTEGER NOT NULL DEFAULT 1,
+	count          INTEGER NOT NULL DEFAULT 0
 );
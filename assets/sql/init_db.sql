@@ -44,6 +44,76 @@ CREATE TABLE IF NOT EXISTS lurkers (
 	timestamp TEXT NOT NULL
 );
 
+CREATE TABLE IF NOT EXISTS user_names (
+	id        INTEGER PRIMARY KEY,
+	user_id   INTEGER NOT NULL UNIQUE,
+	user_name TEXT NOT NULL,
+	last_seen TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS user_name_changes (
+	id        INTEGER PRIMARY KEY,
+	user_id   INTEGER NOT NULL,
+	old_name  TEXT NOT NULL,
+	new_name  TEXT NOT NULL,
+	timestamp TEXT NOT NULL
+);
+
+-- the channels whose logs the names above were picked up from already
+CREATE TABLE IF NOT EXISTS user_names_from_logs (
+	channel_id INTEGER PRIMARY KEY
+);
+
+CREATE TABLE IF NOT EXISTS markov_opt_outs (
+	user_id   INTEGER PRIMARY KEY,
+	timestamp TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS markov_sharing (
+	channel_id INTEGER PRIMARY KEY,
+	shared     INTEGER NOT NULL
+);
+
+-- the channels whose markov indexes are being rebuilt
+-- (the rebuilds check in with every batch)
+CREATE TABLE IF NOT EXISTS markov_rebuilds (
+	channel_id INTEGER PRIMARY KEY,
+	heartbeat  TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS ongoing_trivia_games (
+	channel_id     TEXT PRIMARY KEY,
+	question       TEXT NOT NULL,
+	correct_answer TEXT NOT NULL,
+	wrong_answers  TEXT NOT NULL,
+	difficulty     TEXT NOT NULL DEFAULT 'medium',
+	round          TEXT
+);
+
+-- the local trivia question bank (channel 0 = asked everywhere);
+-- categories, difficulties and types are named the way opentdb names them
+CREATE TABLE IF NOT EXISTS trivia_questions (
+	id             INTEGER PRIMARY KEY,
+	channel_id     INTEGER NOT NULL DEFAULT 0,
+	category       TEXT,
+	difficulty     TEXT NOT NULL,
+	type           TEXT NOT NULL,
+	question       TEXT NOT NULL,
+	correct_answer TEXT NOT NULL,
+	wrong_answers  TEXT NOT NULL,
+	added_by       INTEGER,
+	UNIQUE (channel_id, question)
+);
+
+CREATE TABLE IF NOT EXISTS trivia_scores (
+	channel_id INTEGER NOT NULL,
+	user_id    INTEGER NOT NULL,
+	user_name  TEXT NOT NULL,
+	points     INTEGER NOT NULL DEFAULT 0,
+	answers    INTEGER NOT NULL DEFAULT 0,
+	PRIMARY KEY (channel_id, user_id)
+);
+
 INSERT INTO
 	explanations (code, message)
 	VALUES
@@ -0,0 +1,25 @@
+-- commands used to be just a name, a type and an expression,
+-- now they keep who made them when, how many times they've been used,
+-- and who may use them how often
+ALTER TABLE CHANNEL_{{ CHANNEL_ID }}_COMMANDS
+	ADD COLUMN uses INTEGER NOT NULL DEFAULT 0;
+
+-- (the count used to go up with every use of any command)
+UPDATE CHANNEL_{{ CHANNEL_ID }}_COMMANDS
+	SET
+		uses=IFNULL(metadata, 0);
+
+ALTER TABLE CHANNEL_{{ CHANNEL_ID }}_COMMANDS
+	ADD COLUMN creator TEXT NOT NULL DEFAULT '';
+
+ALTER TABLE CHANNEL_{{ CHANNEL_ID }}_COMMANDS
+	ADD COLUMN created_at TEXT;
+
+ALTER TABLE CHANNEL_{{ CHANNEL_ID }}_COMMANDS
+	ADD COLUMN permission TEXT NOT NULL DEFAULT 'everyone';
+
+ALTER TABLE CHANNEL_{{ CHANNEL_ID }}_COMMANDS
+	ADD COLUMN cooldown_s INTEGER NOT NULL DEFAULT 0;
+
+ALTER TABLE CHANNEL_{{ CHANNEL_ID }}_COMMANDS
+	ADD COLUMN last_used TEXT;
@@ -0,0 +1,24 @@
+-- the markov tables used to have a row for every time a pair of words
+-- was seen; squash the duplicates into one row with the count of them
+CREATE TABLE CHANNEL_{{ CHANNEL_ID }}_MARKOV_COMPACT (
+	word  TEXT NOT NULL,
+	succ  TEXT NOT NULL,
+	count INTEGER NOT NULL DEFAULT 1,
+	PRIMARY KEY (word, succ)
+);
+
+INSERT
+	INTO CHANNEL_{{ CHANNEL_ID }}_MARKOV_COMPACT
+		(word, succ, count)
+	SELECT word, succ, COUNT(*)
+		FROM CHANNEL_{{ CHANNEL_ID }}_MARKOV
+		GROUP BY
+			word, succ;
+
+DROP TABLE CHANNEL_{{ CHANNEL_ID }}_MARKOV;
+
+ALTER TABLE CHANNEL_{{ CHANNEL_ID }}_MARKOV_COMPACT
+	RENAME TO CHANNEL_{{ CHANNEL_ID }}_MARKOV;
+
+CREATE INDEX IF NOT EXISTS CHANNEL_{{ CHANNEL_ID }}_MARKOV_WORD
+	ON CHANNEL_{{ CHANNEL_ID }}_MARKOV (word COLLATE NOCASE);
@@ -0,0 +1,3 @@
+-- hooks count how many times they have fired (for the `{count}` in their responses)
+ALTER TABLE CHANNEL_{{ CHANNEL_ID }}_HOOKS
+	ADD COLUMN count INTEGER NOT NULL DEFAULT 0;
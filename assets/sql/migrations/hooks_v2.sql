@@ -0,0 +1,13 @@
+-- hooks used to be just a catchphrase and a response,
+-- now they can have cooldowns, be limited to some users and be turned off
+ALTER TABLE CHANNEL_{{ CHANNEL_ID }}_HOOKS
+	ADD COLUMN cooldown_s INTEGER NOT NULL DEFAULT 0;
+
+ALTER TABLE CHANNEL_{{ CHANNEL_ID }}_HOOKS
+	ADD COLUMN users TEXT NOT NULL DEFAULT '';
+
+ALTER TABLE CHANNEL_{{ CHANNEL_ID }}_HOOKS
+	ADD COLUMN badges TEXT NOT NULL DEFAULT '';
+
+ALTER TABLE CHANNEL_{{ CHANNEL_ID }}_HOOKS
+	ADD COLUMN enabled INTEGER NOT NULL DEFAULT 1;
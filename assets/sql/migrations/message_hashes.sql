@@ -0,0 +1,10 @@
+-- the copy check of markov chains looks messages up by a hash of them
+-- (filled in by the bot), since an index on the whole text of every message
+-- is about as big as the logs themselves
+ALTER TABLE CHANNEL_{{ CHANNEL_ID }}
+	ADD COLUMN message_hash INTEGER;
+
+DROP INDEX IF EXISTS CHANNEL_{{ CHANNEL_ID }}_MESSAGE;
+
+CREATE INDEX CHANNEL_{{ CHANNEL_ID }}_MESSAGE_HASH
+	ON CHANNEL_{{ CHANNEL_ID }} (message_hash);
@@ -0,0 +1,7 @@
+-- trivia games used to be a single question, now they are rounds
+-- of questions (that are worth more the more difficult they are)
+ALTER TABLE ongoing_trivia_games
+	ADD COLUMN difficulty TEXT NOT NULL DEFAULT 'medium';
+
+ALTER TABLE ongoing_trivia_games
+	ADD COLUMN round TEXT;
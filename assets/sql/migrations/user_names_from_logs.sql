@@ -0,0 +1,58 @@
+-- the names users went by before they were tracked, as seen in the logs of the channel;
+-- only the display names that are just their logins with a different case are of use
+-- (the logins are what's tracked), and a name changed wherever it differs
+-- from the one of the user's previous message
+INSERT
+	INTO user_name_changes
+		(user_id, old_name, new_name, timestamp)
+	SELECT sender_id, previous_name, name, timestamp
+		FROM (
+			SELECT
+				sender_id,
+				LOWER(sender_nick) AS name,
+				LAG(LOWER(sender_nick)) OVER (PARTITION BY sender_id ORDER BY id) AS previous_name,
+				timestamp
+				FROM CHANNEL_{{ CHANNEL_ID }}
+				WHERE
+					sender_nick NOT GLOB '*[^A-Za-z0-9_]*'
+		) AS logged
+		WHERE
+			previous_name <> name
+		AND
+			NOT EXISTS (
+				SELECT 1
+					FROM user_name_changes
+					WHERE
+						user_id=logged.sender_id
+					AND
+						old_name=logged.previous_name
+					AND
+						new_name=logged.name
+			);
+
+-- the name of every user's latest message, unless they were seen later (e.g. in another channel)
+INSERT
+	INTO user_names
+		(user_id, user_name, last_seen)
+	SELECT sender_id, LOWER(sender_nick), timestamp
+		FROM CHANNEL_{{ CHANNEL_ID }}
+		WHERE
+			id IN (
+				SELECT MAX(id)
+					FROM CHANNEL_{{ CHANNEL_ID }}
+					WHERE
+						sender_nick NOT GLOB '*[^A-Za-z0-9_]*'
+					GROUP BY
+						sender_id
+			)
+	ON CONFLICT (user_id)
+	DO UPDATE
+		SET user_name = excluded.user_name, last_seen = excluded.last_seen
+		WHERE
+			excluded.last_seen > user_names.last_seen;
+
+INSERT
+	INTO user_names_from_logs
+		(channel_id)
+	VALUES
+		({{ CHANNEL_ID }});
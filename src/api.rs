@@ -2,13 +2,346 @@
 
 use crate::{TwitchAuth, MyError};
 use crate::api_models as models;
+use crate::metrics::metrics;
 
 use std::borrow::BorrowMut;
 use std::fmt::Display;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
 
 use chrono::{DateTime, Utc};
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
 use rand::{thread_rng, Rng};
-use reqwest::Client;
+use reqwest::{Client, Method, RequestBuilder, Response, StatusCode};
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+use thiserror::Error;
+use tracing::{debug, warn};
+
+// —————————————————————————————————————————
+//               HTTP plumbing
+// —————————————————————————————————————————
+
+// how long a whole request (including reading the body) may take
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+// how long establishing a connection may take
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+// how many times a request is retried after the first attempt
+const MAX_RETRIES: u32 = 3;
+// the first backoff, every next one is twice as long
+const BASE_BACKOFF: Duration = Duration::from_millis(500);
+// never wait longer than this before retrying,
+// no matter what the API tells us
+const MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+// the base URLs of all of the APIs the bot talks to;
+// any of them can be overriden in the config file (`api_urls`)
+// or through an environment variable (e.g. `WTTR_API_URL`),
+// which comes in handy for pointing the bot at a mock server
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ApiUrls {
+    pub twitch_helix:     String,
+    pub twitch_tmi:       String,
+    pub seventv:          String,
+    pub bttv:             String,
+    pub channel_emotes:   String,
+    pub wttr:             String,
+    pub wikipedia:        String,
+    pub dictionary:       String,
+    pub urban_dictionary: String,
+    pub pastebin:         String,
+    pub reddit:           String,
+    pub ipgeolocation:    String,
+    pub devotionalium:    String,
+    pub opentdb:          String,
+    pub wolframalpha:     String,
+    pub inspirobot:       String,
+}
+
+impl Default for ApiUrls {
+    fn default() -> Self {
+        Self {
+            twitch_helix:     "https://api.twitch.tv/helix".into(),
+            twitch_tmi:       "https://tmi.twitch.tv".into(),
+            seventv:          "https://api.7tv.app".into(),
+            bttv:             "https://api.betterttv.net".into(),
+            channel_emotes:   "https://emotes.adamcy.pl".into(),
+            wttr:             "https://wttr.in".into(),
+            wikipedia:        "https://en.wikipedia.org".into(),
+            dictionary:       "https://api.dictionaryapi.dev".into(),
+            urban_dictionary: "https://api.urbandictionary.com".into(),
+            pastebin:         "https://pastebin.com".into(),
+            reddit:           "https://www.reddit.com".into(),
+            ipgeolocation:    "https://api.ipgeolocation.io".into(),
+            devotionalium:    "https://devotionalium.com".into(),
+            opentdb:          "https://opentdb.com".into(),
+            wolframalpha:     "http://api.wolframalpha.com".into(),
+            inspirobot:       "https://inspirobot.me".into(),
+        }
+    }
+}
+
+impl ApiUrls {
+    // replace the URLs for which an environment variable is set
+    pub fn with_env_overrides(mut self) -> Self {
+        let fields = [
+            ("TWITCH_HELIX_API_URL",     &mut self.twitch_helix),
+            ("TWITCH_TMI_API_URL",       &mut self.twitch_tmi),
+            ("SEVENTV_API_URL",          &mut self.seventv),
+            ("BTTV_API_URL",             &mut self.bttv),
+            ("CHANNEL_EMOTES_API_URL",   &mut self.channel_emotes),
+            ("WTTR_API_URL",             &mut self.wttr),
+            ("WIKIPEDIA_API_URL",        &mut self.wikipedia),
+            ("DICTIONARY_API_URL",       &mut self.dictionary),
+            ("URBAN_DICTIONARY_API_URL", &mut self.urban_dictionary),
+            ("PASTEBIN_API_URL",         &mut self.pastebin),
+            ("REDDIT_API_URL",           &mut self.reddit),
+            ("IPGEOLOCATION_API_URL",    &mut self.ipgeolocation),
+            ("DEVOTIONALIUM_API_URL",    &mut self.devotionalium),
+            ("OPENTDB_API_URL",          &mut self.opentdb),
+            ("WOLFRAMALPHA_API_URL",     &mut self.wolframalpha),
+            ("INSPIROBOT_API_URL",       &mut self.inspirobot),
+        ];
+
+        for (var, field) in fields {
+            if let Ok(url) = std::env::var(var) {
+                *field = url;
+            }
+        }
+
+        self
+    }
+
+    // so that the URLs can be simply joined with paths
+    fn without_trailing_slashes(mut self) -> Self {
+        for url in [
+            &mut self.twitch_helix, &mut self.twitch_tmi, &mut self.seventv, &mut self.bttv,
+            &mut self.channel_emotes, &mut self.wttr, &mut self.wikipedia, &mut self.dictionary,
+            &mut self.urban_dictionary, &mut self.pastebin, &mut self.reddit, &mut self.ipgeolocation,
+            &mut self.devotionalium, &mut self.opentdb, &mut self.wolframalpha, &mut self.inspirobot,
+        ] {
+            while url.ends_with('/') {
+                url.pop();
+            }
+        }
+
+        self
+    }
+}
+
+static API_URLS: OnceLock<ApiUrls> = OnceLock::new();
+
+// set the base URLs used by all of the API calls; they can be set only once,
+// before any of the calls (which use the defaults otherwise)
+pub fn set_api_urls(urls: ApiUrls) {
+    if API_URLS.set(urls.without_trailing_slashes()).is_err() {
+        warn!("The API URLs were already in use, they couldn't be set");
+    }
+}
+
+// the base URLs used by the API calls
+pub fn urls() -> &'static ApiUrls {
+    API_URLS.get_or_init(ApiUrls::default)
+}
+
+#[derive(Error, Debug)]
+pub enum ApiError {
+    #[error("❌ {0} is currently unavailable, try again later")]
+    ServiceUnavailable(String),
+    #[error("❌ {0} is rate limiting the bot, try again later")]
+    RateLimited(String),
+    #[error("❌ {0} took too long to respond, try again later")]
+    Timeout(String),
+    #[error("❌ could not connect to {0}")]
+    Connection(String),
+    #[error("❌ {0} responded with something unexpected")]
+    BadResponse(String),
+    #[error("❌ request could not be constructed")]
+    BadRequest,
+}
+
+// the state of the Twitch Helix rate limit bucket,
+// as reported by the `Ratelimit-*` headers of the last response
+struct HelixRatelimit {
+    remaining: u32,
+    // unix timestamp of when the bucket gets refilled
+    reset:     i64,
+}
+
+static HELIX_RATELIMIT: Mutex<Option<HelixRatelimit>> = Mutex::new(None);
+
+// the client shared by all of the API calls, so that
+// connections get reused and all requests have timeouts
+pub fn client() -> &'static Client {
+    static CLIENT: OnceLock<Client> = OnceLock::new();
+
+    CLIENT.get_or_init(|| {
+        Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .connect_timeout(CONNECT_TIMEOUT)
+            .build()
+            .expect("HTTP client could not be built")
+    })
+}
+
+// the chars that have to be escaped in a segment of a URL path
+// ref: https://url.spec.whatwg.org/#path-percent-encode-set (plus `/`, `\` and `%`)
+const PATH_SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ').add(b'"').add(b'#').add(b'<').add(b'>').add(b'?').add(b'`').add(b'{').add(b'}')
+    .add(b'/').add(b'\\').add(b'%');
+
+// user input as a single segment of a URL path, e.g. "São Paulo?" -> "S%C3%A3o%20Paulo%3F"
+// (user input in queries goes through `RequestBuilder::query` instead);
+// `None` for `.` and `..`, which would move the path up even as `%2E` (URLs get parsed that way)
+pub fn path_segment(s: &str) -> Option<String> {
+    match s {
+        "." | ".." => None,
+        _          => Some(utf8_percent_encode(s, PATH_SEGMENT).to_string()),
+    }
+}
+
+fn backoff(attempt: u32) -> Duration {
+    (BASE_BACKOFF * 2u32.pow(attempt)).min(MAX_BACKOFF)
+}
+
+// sleep until the Helix bucket is refilled, if it is empty
+async fn wait_for_helix_ratelimit() {
+    let wait = match HELIX_RATELIMIT.lock() {
+        Ok(limit) => match &*limit {
+            Some(l) if l.remaining == 0 => l.reset - Utc::now().timestamp(),
+            _                           => 0,
+        },
+        Err(_) => 0,
+    };
+
+    if wait > 0 {
+        tokio::time::sleep(Duration::from_secs(wait as u64).min(MAX_BACKOFF)).await;
+    }
+}
+
+fn numeric_header(res: &Response, name: &str) -> Option<i64> {
+    res.headers()
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<i64>().ok())
+}
+
+fn update_helix_ratelimit(res: &Response) {
+    let remaining = numeric_header(res, "Ratelimit-Remaining");
+    let reset     = numeric_header(res, "Ratelimit-Reset");
+
+    if let (Some(remaining), Some(reset)) = (remaining, reset) {
+        if let Ok(mut limit) = HELIX_RATELIMIT.lock() {
+            *limit = Some(HelixRatelimit { remaining: remaining as u32, reset });
+        }
+    }
+}
+
+// how long the API asked us to wait before trying again after a 429
+fn requested_wait(res: &Response, is_helix: bool) -> Option<Duration> {
+    let secs = if is_helix {
+        numeric_header(res, "Ratelimit-Reset").map(|reset| reset - Utc::now().timestamp())
+    } else {
+        numeric_header(res, "Retry-After")
+    }?;
+
+    Some(Duration::from_secs(secs.max(0) as u64).min(MAX_BACKOFF))
+}
+
+// send a request, retrying with an exponential backoff
+// on timeouts, connection errors, 5xx and 429 responses;
+// only GET and HEAD requests get retried, since doing anything else twice
+// (e.g. creating a paste) might not be the same as doing it once
+pub async fn send(req: RequestBuilder) -> Result<Response, ApiError> {
+    let req = req.build().map_err(|_| ApiError::BadRequest)?;
+    let host = req.url().host_str().unwrap_or_default().to_owned();
+    let is_helix = req.url().as_str().starts_with(&urls().twitch_helix);
+    let max_retries = match *req.method() {
+        Method::GET | Method::HEAD => MAX_RETRIES,
+        _                          => 0,
+    };
+
+    let mut attempt = 0;
+    loop {
+        if is_helix {
+            wait_for_helix_ratelimit().await;
+        }
+
+        let this_try = req.try_clone().ok_or(ApiError::BadRequest)?;
+        let started = std::time::Instant::now();
+
+        match client().execute(this_try).await {
+            Ok(res) => {
+                if is_helix {
+                    update_helix_ratelimit(&res);
+                }
+
+                let status = res.status();
+                let latency = started.elapsed();
+                metrics().api_request_duration.with_label_values(&[&host]).observe(latency.as_secs_f64());
+
+                if status.is_client_error() || status.is_server_error() {
+                    metrics().api_errors.with_label_values(&[&host, status.as_str()]).inc();
+                }
+
+                debug!(%host, status = status.as_u16(), latency_ms = latency.as_millis() as u64, "API request");
+
+                if status != StatusCode::TOO_MANY_REQUESTS && !status.is_server_error() {
+                    return Ok(res);
+                }
+
+                if attempt >= max_retries {
+                    return match status {
+                        StatusCode::TOO_MANY_REQUESTS => Err(ApiError::RateLimited(host)),
+                        _                             => Err(ApiError::ServiceUnavailable(host)),
+                    };
+                }
+
+                // (only a 429 says when to come back, a 5xx gets the usual backoff)
+                let wait = match status {
+                    StatusCode::TOO_MANY_REQUESTS => requested_wait(&res, is_helix).unwrap_or_else(|| backoff(attempt)),
+                    _                             => backoff(attempt),
+                };
+                warn!(%host, status = status.as_u16(), "API request failed, retrying in {wait:?}");
+                tokio::time::sleep(wait).await;
+            },
+            Err(e) => {
+                let retriable = e.is_timeout() || e.is_connect();
+                let kind = if e.is_timeout() { "timeout" } else { "connection" };
+                metrics().api_errors.with_label_values(&[&host, kind]).inc();
+
+                if !retriable || attempt >= max_retries {
+                    return match e.is_timeout() {
+                        true  => Err(ApiError::Timeout(host)),
+                        false => Err(ApiError::Connection(host)),
+                    };
+                }
+
+                warn!(%host, "API request failed, retrying; err: {e}");
+                tokio::time::sleep(backoff(attempt)).await;
+            },
+        }
+
+        attempt += 1;
+    }
+}
+
+// send a request and parse the JSON response
+pub async fn get_json<T: DeserializeOwned>(req: RequestBuilder) -> Result<T, ApiError> {
+    let res = send(req).await?;
+    let host = res.url().host_str().unwrap_or_default().to_owned();
+
+    res.json::<T>().await.map_err(|_| ApiError::BadResponse(host))
+}
+
+// send a request and get the plain-text response
+pub async fn get_text(req: RequestBuilder) -> Result<String, ApiError> {
+    let res = send(req).await?;
+    let host = res.url().host_str().unwrap_or_default().to_owned();
+
+    res.text().await.map_err(|_| ApiError::BadResponse(host))
+}
 
 // —————————————————————————————————————————
 //               Twitch API
@@ -19,14 +352,13 @@ pub async fn get_twitch_user(
     nick: &str,
     auth: &TwitchAuth,
 ) -> anyhow::Result<models::UsersResponse> {
-    let info: models::UsersResponse = Client::new()
-        .get(&format!("https://api.twitch.tv/helix/users?login={nick}"))
+    let req = client()
+        .get(format!("{}/users", urls().twitch_helix))
+        .query(&[("login", nick)])
         .header("Client-ID", auth.client_id.clone())
-        .header("Authorization", format!("Bearer {}", auth.oauth.clone()))
-        .send()
-        .await?
-        .json()
-        .await?;
+        .header("Authorization", format!("Bearer {}", auth.oauth.clone()));
+
+    let info: models::UsersResponse = get_json(req).await?;
 
     Ok(info)
 }
@@ -57,28 +389,31 @@ pub async fn nick_from_id(
     user_id: i32,
     auth:    &TwitchAuth,
 ) -> anyhow::Result<String> {
-    let res: models::UsersResponse = Client::new()
-        .get(&format!("https://api.twitch.tv/helix/users?id={user_id}"))
+    let req = client()
+        .get(format!("{}/users", urls().twitch_helix))
+        .query(&[("id", user_id)])
         .header("Client-ID", auth.client_id.clone())
-        .header("Authorization", format!("Bearer {}", auth.oauth.clone()))
-        .send()
-        .await?
-        .json()
-        .await?;
+        .header("Authorization", format!("Bearer {}", auth.oauth.clone()));
+
+    let res: models::UsersResponse = get_json(req).await?;
 
-    Ok(res.data[0].login.clone())
+    let user = res.data.first().ok_or(MyError::NotFound)?;
+
+    Ok(user.login.clone())
 }
 
 // gets all viewers present in a twitch stream
 pub async fn get_chatters(
     channel_name: &str,
 ) -> anyhow::Result<Option<Vec<String>>> {
-    let mut res: models::ChattersResponse = Client::new()
-        .get(&format!("https://tmi.twitch.tv/group/user/{channel_name}/chatters"))
-        .send()
-        .await?
-        .json()
-        .await?;
+    let channel_name = match path_segment(channel_name) {
+        Some(segment) => segment,
+        None          => return Ok(None),
+    };
+    let req = client()
+        .get(format!("{}/group/user/{}/chatters", urls().twitch_tmi, channel_name));
+
+    let mut res: models::ChattersResponse = get_json(req).await?;
 
     let mut chatters = vec![];
     
@@ -102,14 +437,13 @@ pub async fn get_stream_info(
     auth: &TwitchAuth,
     channel_name: &str,
 ) -> anyhow::Result<Option<models::StreamsResponse>> {
-    let res: models::StreamsResponse = Client::new()
-        .get(&format!("https://api.twitch.tv/helix/streams?user_login={channel_name}"))
+    let req = client()
+        .get(format!("{}/streams", urls().twitch_helix))
+        .query(&[("user_login", channel_name)])
         .header("Client-ID", auth.client_id.clone())
-        .header("Authorization", format!("Bearer {}", auth.oauth.clone()))
-        .send()
-        .await?
-        .json()
-        .await?;
+        .header("Authorization", format!("Bearer {}", auth.oauth.clone()));
+
+    let res: models::StreamsResponse = get_json(req).await?;
 
     match res.data.len() {
         0 => Ok(None),
@@ -117,6 +451,16 @@ pub async fn get_stream_info(
     }
 }
 
+// how long a streamer has been live for, `None` if they're offline
+pub async fn get_stream_uptime(
+    auth: &TwitchAuth,
+    channel_name: &str,
+) -> anyhow::Result<Option<chrono::Duration>> {
+    let info = get_stream_info(auth, channel_name).await?;
+
+    Ok(info.and_then(|i| i.data.first().map(|s| Utc::now() - s.started_at)))
+}
+
 // check if a streamer is live
 pub async fn streamer_is_live(
     auth: &TwitchAuth,
@@ -132,12 +476,14 @@ pub async fn streamer_is_live(
 pub async fn get_7tv_channel_emotes(
     channel_name: &str,
 ) -> anyhow::Result<Option<Vec<String>>> {
-    let res: models::Emotes7TVResponse = Client::new()
-        .get(&format!("https://api.7tv.app/v2/users/{channel_name}/emotes"))
-        .send()
-        .await?
-        .json()
-        .await?;
+    let channel_name = match path_segment(channel_name) {
+        Some(segment) => segment,
+        None          => return Ok(None),
+    };
+    let req = client()
+        .get(format!("{}/v2/users/{}/emotes", urls().seventv, channel_name));
+
+    let res: models::Emotes7TVResponse = get_json(req).await?;
 
     match res.len() {
         0 => Ok(None),
@@ -148,12 +494,10 @@ pub async fn get_7tv_channel_emotes(
 // fetches all 7tv global
 pub async fn get_7tv_global_emotes(
 ) -> anyhow::Result<Option<Vec<String>>> {
-    let res: models::GlobalEmotes7TVResponse = Client::new()
-        .get("https://api.7tv.app/v2/emotes/global")
-        .send()
-        .await?
-        .json()
-        .await?;
+    let req = client()
+        .get(format!("{}/v2/emotes/global", urls().seventv));
+
+    let res: models::GlobalEmotes7TVResponse = get_json(req).await?;
 
     match res.len() {
         0 => Ok(None),
@@ -165,12 +509,10 @@ pub async fn get_7tv_global_emotes(
 pub async fn get_bttv_channel_emotes<T: Display>(
     channel_id: T,
 ) -> anyhow::Result<Option<Vec<String>>> {
-    let res: models::EmotesBttvResponse = Client::new()
-        .get(&format!("https://api.betterttv.net/3/cached/users/twitch/{channel_id}"))
-        .send()
-        .await?
-        .json()
-        .await?;
+    let req = client()
+        .get(format!("{}/3/cached/users/twitch/{channel_id}", urls().bttv));
+
+    let res: models::EmotesBttvResponse = get_json(req).await?;
 
     match res.channel_emotes.len() + res.shared_emotes.len() {
         0 => Ok(None),
@@ -188,12 +530,10 @@ pub async fn get_bttv_channel_emotes<T: Display>(
 // fetches all bttv global emotes
 pub async fn get_bttv_global_emotes(
 ) -> anyhow::Result<Option<Vec<String>>> {
-    let res: models::GlobalEmotesBttvResponse  = Client::new()
-        .get("https://api.betterttv.net/3/cached/emotes/global")
-        .send()
-        .await?
-        .json()
-        .await?;
+    let req = client()
+        .get(format!("{}/3/cached/emotes/global", urls().bttv));
+
+    let res: models::GlobalEmotesBttvResponse = get_json(req).await?;
 
     match res.len() {
         0 => Ok(None),
@@ -205,12 +545,10 @@ pub async fn get_bttv_global_emotes(
 pub async fn get_ffz_channel_emotes<T: Display>(
     channel_id: T,
 ) -> anyhow::Result<Option<Vec<String>>> {
-    let res: Vec<models::EmotesFfzResponse> = Client::new()
-        .get(&format!("https://api.betterttv.net/3/cached/frankerfacez/users/twitch/{channel_id}"))
-        .send()
-        .await?
-        .json()
-        .await?;
+    let req = client()
+        .get(format!("{}/3/cached/frankerfacez/users/twitch/{channel_id}", urls().bttv));
+
+    let res: Vec<models::EmotesFfzResponse> = get_json(req).await?;
 
     match res.len() {
         0 => Ok(None),
@@ -221,12 +559,10 @@ pub async fn get_ffz_channel_emotes<T: Display>(
 // fetches all ffz global emotes
 pub async fn get_ffz_global_emotes(
 ) -> anyhow::Result<Option<Vec<String>>> {
-    let res: models::GlobalEmotesFfzResponse = Client::new()
-        .get("https://api.betterttv.net/3/cached/frankerfacez/emotes/global")
-        .send()
-        .await?
-        .json()
-        .await?;
+    let req = client()
+        .get(format!("{}/3/cached/frankerfacez/emotes/global", urls().bttv));
+
+    let res: models::GlobalEmotesFfzResponse = get_json(req).await?;
 
     match res.len() {
         0 => Ok(None),
@@ -238,12 +574,10 @@ pub async fn get_ffz_global_emotes(
 pub async fn get_all_channel_emotes<T: Display>(
     channel_id: T
 ) -> anyhow::Result<Option<Vec<String>>> {
-    let res: models::ChannelEmotesResponse = Client::new()
-        .get(&format!("https://emotes.adamcy.pl/v1/channel/{channel_id}/emotes/all"))
-        .send()
-        .await?
-        .json()
-        .await?;
+    let req = client()
+        .get(format!("{}/v1/channel/{channel_id}/emotes/all", urls().channel_emotes));
+
+    let res: models::ChannelEmotesResponse = get_json(req).await?;
 
     match res.len() {
         0 => Ok(None),
@@ -258,18 +592,17 @@ pub async fn get_followage(
     channel_id: i32,
     user_id: i32
 ) -> anyhow::Result<Option<DateTime<Utc>>> {
-    let res: models::TwitchFollowResponse = Client::new()
-        .get(&format!("https://api.twitch.tv/helix/users/follows?to_id={channel_id}&from_id={user_id}"))
+    let req = client()
+        .get(format!("{}/users/follows", urls().twitch_helix))
+        .query(&[("to_id", channel_id), ("from_id", user_id)])
         .header("Client-ID", auth.client_id.clone())
-        .header("Authorization", format!("Bearer {}", auth.oauth.clone()))
-        .send()
-        .await?
-        .json()
-        .await?;
+        .header("Authorization", format!("Bearer {}", auth.oauth.clone()));
 
-    match res.total {
-        0 => Ok(None),
-        _ => Ok(Some(res.data[0].followed_at)),
+    let res: models::TwitchFollowResponse = get_json(req).await?;
+
+    match res.data.first() {
+        Some(follow) => Ok(Some(follow.followed_at)),
+        None         => Ok(None),
     }
 }
 
@@ -281,24 +614,33 @@ pub async fn get_followage(
 pub async fn get_weather_report(
     location: &str,
 ) -> anyhow::Result<Option<String>> {
-    let weather: models::WttrInResponse = Client::new()
-        .get(&format!("https://wttr.in/{location}?format=j1"))
-        .send()
-        .await?
-        .json()
-        .await?;
+    let location = match path_segment(location) {
+        Some(segment) => segment,
+        None          => return Ok(None),
+    };
+    let req = client()
+        .get(format!("{}/{}", urls().wttr, location))
+        .query(&[("format", "j1")]);
+
+    let weather: models::WttrInResponse = get_json(req).await?;
 
-    let dir = &weather.current_condition[0].winddir16point;
+    let (condition, nearest_area) = match (weather.current_condition.first(), weather.nearest_area.first()) {
+        (Some(c), Some(a)) => (c, a),
+        _                  => return Ok(None),
+    };
 
-    let temp     = format!("🌡️ {}°C", weather.current_condition[0].temp_c);
-    let humid    = format!("🌫️ {}%", weather.current_condition[0].humidity);
-    let pressure = format!("🔽 {}hPa", weather.current_condition[0].pressure);
-    let precip   = format!("💧 {}mm", weather.current_condition[0].precip_mm);
-    let wind     = format!("💨 {}km/h {dir}", weather.current_condition[0].windspeed_kmph);
+    let dir = &condition.winddir16point;
 
-    // this might cause at some point cause issues
-    let area = &weather.nearest_area[0].area_name[0].value;
-    let country = &weather.nearest_area[0].country[0].value;
+    let temp     = format!("🌡️ {}°C", condition.temp_c);
+    let humid    = format!("🌫️ {}%", condition.humidity);
+    let pressure = format!("🔽 {}hPa", condition.pressure);
+    let precip   = format!("💧 {}mm", condition.precip_mm);
+    let wind     = format!("💨 {}km/h {dir}", condition.windspeed_kmph);
+
+    let (area, country) = match (nearest_area.area_name.first(), nearest_area.country.first()) {
+        (Some(a), Some(c)) => (&a.value, &c.value),
+        _                  => return Ok(None),
+    };
 
     Ok(Some(format!("Weather in {area}, {country}: {temp}, {humid}, {pressure}, {precip}, {wind}")))
 }
@@ -307,9 +649,19 @@ pub async fn get_weather_report(
 pub async fn query_wikipedia(
     phrase: &str,
 ) -> anyhow::Result<Option<models::WikiResponse>> {
-    let res = Client::new()
-        .get(&format!("https://en.wikipedia.org/w/api.php?action=query&titles={phrase}&prop=extracts&format=json&exintro=1&exsectionformat=plain&explaintext=1"))
-        .send()
+    let req = client()
+        .get(format!("{}/w/api.php", urls().wikipedia))
+        .query(&[
+            ("action",          "query"),
+            ("titles",          phrase),
+            ("prop",            "extracts"),
+            ("format",          "json"),
+            ("exintro",         "1"),
+            ("exsectionformat", "plain"),
+            ("explaintext",     "1"),
+        ]);
+
+    let res = send(req)
         .await?
         .json::<models::WikiResponse>()
         .await;
@@ -324,9 +676,14 @@ pub async fn query_wikipedia(
 pub async fn query_dictionary(
     word: &str,
 ) -> anyhow::Result<Option<String>> {
-    let res = Client::new()
-        .get(&format!("https://api.dictionaryapi.dev/api/v2/entries/en/{word}"))
-        .send()
+    let word = match path_segment(word) {
+        Some(segment) => segment,
+        None          => return Ok(None),
+    };
+    let req = client()
+        .get(format!("{}/api/v2/entries/en/{}", urls().dictionary, word));
+
+    let res = send(req)
         .await?
         .json::<models::DictionaryResponse>()
         .await;
@@ -337,11 +694,19 @@ pub async fn query_dictionary(
         Err(_) => return Ok(None),
     };
 
-    let pronunciation = match &res[0].phonetic {
+    let entry = match res.first() {
+        Some(e) => e,
+        None    => return Ok(None),
+    };
+
+    let pronunciation = match &entry.phonetic {
         Some(p) => p,
         None    => "",
     };
-    let definition = &res[0].meanings[0].definitions[0].definition;
+    let definition = match entry.meanings.first().and_then(|m| m.definitions.first()) {
+        Some(d) => &d.definition,
+        None    => return Ok(None),
+    };
 
     Ok(Some(format!("{pronunciation} {definition}")))
 }
@@ -350,19 +715,18 @@ pub async fn query_dictionary(
 pub async fn query_urban_dictionary(
     term: &str,
 ) -> anyhow::Result<Option<String>> {
-    let res: models::UrbanDictionaryResponse = Client::new()
-        .get(&format!("https://api.urbandictionary.com/v0/define?term={term}"))
-        .send()
-        .await?
-        .json()
-        .await?;
+    let req = client()
+        .get(format!("{}/v0/define", urls().urban_dictionary))
+        .query(&[("term", term)]);
+
+    let res: models::UrbanDictionaryResponse = get_json(req).await?;
 
     match res.list.len() {
         0 => Ok(None),
         _ => {
-            let term    = &res.list[0].word;
-            let def     = res.list[0].definition.replace('[', "").replace(']', "");
-            let example = res.list[0].example.replace('[', "").replace(']', "");
+            let term    = crate::convert_from_html_entities(res.list[0].word.clone());
+            let def     = crate::convert_from_html_entities(res.list[0].definition.replace('[', "").replace(']', ""));
+            let example = crate::convert_from_html_entities(res.list[0].example.replace('[', "").replace(']', ""));
             let more_defs_count = match res.list.len() {
                 0 => "".to_owned(),
                 _ => format!("({} more definitions)", res.list.len() - 1),
@@ -384,13 +748,11 @@ pub async fn upload_to_pastebin(
         ("api_option"           , "paste"                                ),
     ];
 
-    let res = Client::new()
-        .post("https://pastebin.com/api/api_post.php")
-        .form(&params)
-        .send()
-        .await?
-        .text()
-        .await?;
+    let req = client()
+        .post(&format!("{}/api/api_post.php", urls().pastebin))
+        .form(&params);
+
+    let res = get_text(req).await?;
     
     Ok(res)
 }
@@ -488,13 +850,13 @@ pub async fn get_reddit_posts(
     relevancy:  &RedditPostRelevancy,
 ) -> anyhow::Result<models::SubredditResponse> {
     let relevancy_str = relevancy.as_str();
+    let subreddit = path_segment(subreddit).ok_or(MyError::NotFound)?;
 
-    let res: models::SubredditResponse = Client::new()
-        .get(&format!("https://www.reddit.com/r/{subreddit}/top.json?limit=30&t=${relevancy_str}"))
-        .send()
-        .await?
-        .json()
-        .await?;
+    let req = client()
+        .get(format!("{}/r/{}/top.json", urls().reddit, subreddit))
+        .query(&[("limit", "30"), ("t", relevancy_str)]);
+
+    let res: models::SubredditResponse = get_json(req).await?;
 
     Ok(res)
 }
@@ -504,9 +866,11 @@ pub async fn get_time(
     location: &str
 ) -> anyhow::Result<Option<String>> {
     let api_key = &std::env::var("IPGEOLOCATION_API_KEY")?[..];
-    let res = Client::new()
-        .get(&format!("https://api.ipgeolocation.io/timezone?apiKey={api_key}&location={location}"))
-        .send()
+    let req = client()
+        .get(format!("{}/timezone", urls().ipgeolocation))
+        .query(&[("apiKey", api_key), ("location", location)]);
+
+    let res = send(req)
         .await?
         .json::<models::IPGeolocationResponse>()
         .await;
@@ -554,12 +918,10 @@ pub async fn get_rand_holy_book_verse(
     let rand_month: u16 = rand::thread_rng().gen_range(1..13);
     let rand_day  : u16 = rand::thread_rng().gen_range(1..29);
 
-    let res: models::DevotionaliumResponse = Client::new()
-        .get(&format!("https://devotionalium.com/api/v2?date={rand_year}-{rand_month}-{rand_day}"))
-        .send()
-        .await?
-        .json()
-        .await?;
+    let req = client()
+        .get(format!("{}/api/v2?date={rand_year}-{rand_month}-{rand_day}", urls().devotionalium));
+
+    let res: models::DevotionaliumResponse = get_json(req).await?;
     
     match book_kind {
         HolyBook::Bible  => Ok(res.bible),
@@ -684,6 +1046,38 @@ impl TriviaCategory {
             Self::EntertainmentCartoonAndAnimations => "32",
         }
     }
+
+    // the name opentdb gives the category, which is also
+    // what the questions in the local question bank are filed under
+    pub fn to_opentdb_name(&self) -> Option<&'static str> {
+        match self {
+            Self::Any                               => None,
+            Self::GeneralKnowledge                  => Some("General Knowledge"),
+            Self::EntertainmentBooks                => Some("Entertainment: Books"),
+            Self::EntertainmentFilm                 => Some("Entertainment: Film"),
+            Self::EntertainmentMusic                => Some("Entertainment: Music"),
+            Self::EntertainmentMusicalsAndTheatres  => Some("Entertainment: Musicals & Theatres"),
+            Self::EntertainmentTelevision           => Some("Entertainment: Television"),
+            Self::EntertainmentVideoGames           => Some("Entertainment: Video Games"),
+            Self::EntertainmentBoardGames           => Some("Entertainment: Board Games"),
+            Self::ScienceAndNature                  => Some("Science & Nature"),
+            Self::ScienceComputers                  => Some("Science: Computers"),
+            Self::ScienceMathematics                => Some("Science: Mathematics"),
+            Self::Mythology                         => Some("Mythology"),
+            Self::Sports                            => Some("Sports"),
+            Self::Geography                         => Some("Geography"),
+            Self::History                           => Some("History"),
+            Self::Politics                          => Some("Politics"),
+            Self::Art                               => Some("Art"),
+            Self::Celebrities                       => Some("Celebrities"),
+            Self::Animals                           => Some("Animals"),
+            Self::Vehicles                          => Some("Vehicles"),
+            Self::EntertainmentComics               => Some("Entertainment: Comics"),
+            Self::ScienceGadgets                    => Some("Science: Gadgets"),
+            Self::EntertainmentJapaneseAnimeAndSaga => Some("Entertainment: Japanese Anime & Manga"),
+            Self::EntertainmentCartoonAndAnimations => Some("Entertainment: Cartoon & Animations"),
+        }
+    }
 }
 
 #[derive(PartialEq)]
@@ -773,51 +1167,39 @@ pub async fn fetch_trivia_question(
     diff:  TriviaDifficulty,
     ttype: TriviaType,
 ) -> anyhow::Result<models::TriviaQuestion> {
-    let cat = {
-        if cat == TriviaCategory::Any {
-            "".into()
-        } else {
-            format!("&category={}", cat.to_opentdb_index())
-        }
-    };
-    let diff = {
-        if diff == TriviaDifficulty::Any {
-            "".into()
-        } else {
-            format!("&difficulty={}", diff.to_opentdb_index())
-        }
-    };
-    let ttype = {
-        if ttype == TriviaType::Any {
-            "".into()
-        } else {
-            format!("&type={}", ttype.to_opentdb_index())
-        }
-    };
+    let mut query = vec![("amount", "1")];
+    if cat != TriviaCategory::Any {
+        query.push(("category", cat.to_opentdb_index()));
+    }
+    if diff != TriviaDifficulty::Any {
+        query.push(("difficulty", diff.to_opentdb_index()));
+    }
+    if ttype != TriviaType::Any {
+        query.push(("type", ttype.to_opentdb_index()));
+    }
 
-    let res: models::TriviaResponse = Client::new()
-        .get(&format!("https://opentdb.com/api.php?amount=1{cat}{diff}{ttype}"))
-        .send()
-        .await?
-        .json()
-        .await?;
+    let req = client()
+        .get(format!("{}/api.php", urls().opentdb))
+        .query(&query);
+
+    let res: models::TriviaResponse = get_json(req).await?;
     
-    Ok(res.results[0].clone())
+    let question = res.results.into_iter().next().ok_or(MyError::NotFound)?;
+
+    Ok(question)
 }
 
 // query a question (currently WolframAlpha)
 pub async fn query_generic(
     query: &str,
 ) -> anyhow::Result<Option<String>> {
-    let formatted_query = crate::convert_to_html_encoding(query.to_owned());
     let appid = &std::env::var("WOLFRAMALPHA_APPID")?[..];
 
-    let res: models::WolframAlphaResponse = Client::new()
-        .get(&format!("http://api.wolframalpha.com/v2/query?input={formatted_query}&appid={appid}&output=json"))
-        .send()
-        .await?
-        .json()
-        .await?;
+    let req = client()
+        .get(format!("{}/v2/query", urls().wolframalpha))
+        .query(&[("input", query), ("appid", appid), ("output", "json")]);
+
+    let res: models::WolframAlphaResponse = get_json(req).await?;
 
     if let Some(pods) = res.queryresult.pods {
         let main_pod: Vec<models::Pod> = pods
@@ -829,9 +1211,8 @@ pub async fn query_generic(
             return Ok(None);
         }
          
-        if let Some(subpods) = &main_pod[0].subpods {
-            let answer = subpods[0].plaintext.clone();
-            return Ok(Some(answer));
+        if let Some(subpod) = main_pod[0].subpods.as_ref().and_then(|s| s.first()) {
+            return Ok(Some(subpod.plaintext.clone()));
         }
     } 
 
@@ -842,25 +1223,19 @@ pub async fn query_generic(
 pub async fn get_github_repo_info(
     url: &str,
 ) -> anyhow::Result<models::GitHubRepoResponse> {
-    // yes, it has to be wrapped like this
-    Ok(Client::new()
+    let req = client()
         .get(url)
         .header("Accept", "application/vnd.github.v3+json")
-        .header("User-Agent", "dynamo58")
-        .send()
-        .await?
-        .json::<models::GitHubRepoResponse>()
-        .await?)
+        .header("User-Agent", "dynamo58");
+
+    Ok(get_json(req).await?)
 }
 
 // get some words of wisdom from  inspirebot.me
 pub async fn get_inspire_image()
 -> anyhow::Result<String> {
-    Ok(Client::new()
-        .get("https://inspirobot.me/api?generate=true")
-        .send()
-        .await?
-        .text()
-        .await?
-    )
+    let req = client()
+        .get(format!("{}/api?generate=true", urls().inspirobot));
+
+    Ok(get_text(req).await?)
 }
\ No newline at end of file
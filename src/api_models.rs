@@ -408,7 +408,7 @@ pub struct TriviaResponse {
     pub results: Vec<TriviaQuestion>,
 }
 
-#[derive(Clone, Deserialize)]
+#[derive(Clone, Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct TriviaQuestion {
     pub category: String,
@@ -1,48 +1,47 @@
-use crate::{api, db, Config, TwitchAuth, NameIdCache};
+use crate::{api, commands, db, AppState, Channel, NameIdCache};
 
-use std::sync::{Mutex, Arc};
-
-use sqlx::SqlitePool;
+use chrono::Utc;
+use tracing::warn;
 
 pub async fn check_for_offliners(
-	pool: &SqlitePool,
-	config: &Config,
-	twitch_auth: &TwitchAuth,
-	cache_arc: &Arc<Mutex<NameIdCache>>,
+	state: &AppState,
 ) -> anyhow::Result<u16> {
 	let mut count = 0;
 
-	for channel_name in &config.channels {
-		if (api::get_stream_info(twitch_auth, channel_name).await?).is_none() {
+	for channel_name in &state.config.channels {
+		if (api::get_stream_info(&state.auth, channel_name).await?).is_none() {
 			if let Some(offliners) = api::get_chatters(channel_name).await? {
 				for offliner in &offliners {
-					if config.disregarded_users.contains(&offliner.to_lowercase()) {
+					if state.config.disregarded_users.contains(&offliner.to_lowercase()) {
 						continue
 					}
-					count += 1;
-
-					let mut _offliner_id: Option<i32> = None;
-					let mut _channel_id : Option<i32> = None;
-
-					if let Ok(cache) = cache_arc.lock() {
-						if let Some(id) = cache.get(offliner) {
-							_offliner_id = Some(*id);
-						}
 
-						if let Some(id) = cache.get(offliner) {
-							_offliner_id = Some(*id);
-						}
-					}
+					let mut _offliner_id: Option<i32> = state.name_id_cache.get(offliner).map(|id| *id);
+					let mut _channel_id : Option<i32> = state.name_id_cache.get(channel_name).map(|id| *id);
 
 					if _channel_id.is_none() {
-						_channel_id = Some(api::id_from_nick(channel_name, twitch_auth).await?.unwrap());
+						_channel_id = api::id_from_nick(channel_name, &state.auth).await?;
 					}
 
 					if _offliner_id.is_none() {
-						_offliner_id = Some(api::id_from_nick(offliner, twitch_auth).await?.unwrap());
+						_offliner_id = api::id_from_nick(offliner, &state.auth).await?;
 					}
 
-					db::add_offliner_minute(pool, _channel_id.unwrap(), _offliner_id.unwrap()).await?;
+					// (Twitch doesn't know banned, renamed or deleted users)
+					let (channel_id, offliner_id) = match (_channel_id, _offliner_id) {
+						(Some(c), Some(o)) => (c, o),
+						(None, _)          => {
+							warn!("Twitch doesn't know channel {channel_name}, skipping its offliners");
+							break;
+						},
+						(_, None)          => {
+							warn!("Twitch doesn't know offliner {offliner} of {channel_name}, skipping them");
+							continue;
+						},
+					};
+
+					db::add_offliner_minute(&state.pool, channel_id, offliner_id).await?;
+					count += 1;
 				}
 			}
 		}
@@ -52,14 +51,28 @@ pub async fn check_for_offliners(
 }
 
 pub async fn clear_name_id_cache(
-	name_id_cache_arc: &Arc<Mutex<NameIdCache>>,
+	name_id_cache: &NameIdCache,
+) -> anyhow::Result<usize> {
+	let num = name_id_cache.len();
+	name_id_cache.clear();
+
+	Ok(num)
+}
+
+// hint at (and then reveal) the answers of trivia questions
+// nobody could answer for too long
+pub async fn check_trivia_timers(
+	state:    &AppState,
+	channels: &[Channel],
 ) -> anyhow::Result<usize> {
-	let mut num = 0;
+	let mut count = 0;
 
-	if let Ok(mut cache) = name_id_cache_arc.lock() {
-		num = cache.len();
-		(*cache).clear();
+	for channel in channels {
+		if let Some(message) = commands::check_trivia_timer(state, channel, Utc::now()).await? {
+			commands::say(&state.client, &channel.name, message).await;
+			count += 1;
+		}
 	}
 
-	Ok(num)
+	Ok(count)
 }
@@ -2,21 +2,25 @@
 
 use crate::db;
 use crate::api;
+use crate::markov;
+use crate::trivia;
+use crate::template;
+use crate::metrics::metrics;
 use crate::{
+	AppState,
+	Channel,
 	Config,
 	MyError,
 	TwitchAuth,
 	TwitchBadge,
-	NameIdCache,
+	TwitchClient,
 	CommandSource,
-	ChannelSpecificsCache,
 	fmt_duration,
 	convert_from_html_entities,
 	binomial_p_exact,
 	binomial_p_exact_or_less,
 };
 
-use std::sync::{Arc, Mutex};
 use std::time::Instant;
 
 use anyhow::bail;
@@ -26,28 +30,56 @@ use rand::prelude::SliceRandom;
 use rand::{self, Rng};
 use sqlx::sqlite::SqlitePool;
 use thiserror::Error as ThisError;
+use tracing::{error, info, info_span, warn, Instrument};
 
 
-type TwitchClient = twitch_irc::TwitchIRCClient<twitch_irc::transport::tcp::TCPTransport<twitch_irc::transport::tcp::TLS>, twitch_irc::login::StaticLoginCredentials>;
+// commands taking longer than this get logged as slow
+const SLOW_COMMAND: std::time::Duration = std::time::Duration::from_secs(5);
+
+// the kinds of commands that can be created with `setcmd`
+pub const CHANNEL_COMMAND_TYPES: &[&str] = &["paste", "templ", "incr"];
+
+// the commands of `execute_command`, whose names channel commands can't take
+pub const BUILTIN_COMMANDS: &[&str] = &[
+	"cf", "echo", "8ball", "decide", "tf", "query", "math", "ping", "time", "pasta", "markov",
+	"setcmd", "editcmd", "cmds", "cmdinfo", "suggest", "inspireme", "binomial", "reddit", "wiki",
+	"setalias", "define", "rmalias", "random", "lurk", "explain", "urban", "pyramid", "weather",
+	"chatstats", "uptime", "accage", "delcmd", "followage", "clearreminders", "rmrm", "ls", "first",
+	"sethook", "rmhook", "hooks", "enablehook", "disablehook", "offlinetime", "namechanges", "bible",
+	"quran", "tanakh", "remindme", "remind", "giveup", "hint", "wordratio", "commands", "trivia",
+	"rose", "demultiplex", "bench", "pipe",
+];
+
+// twitch generally doesn't allow awfully long messages
+pub const MAX_MESSAGE_LENGTH: usize = 500;
 
 // handle incoming commands
 #[async_recursion]
 pub async fn handle_command(
-	// this looks like an abomination
-	// but it is what it is
-	pool:                  &SqlitePool,
-	client:                TwitchClient,
-	config:                &Config,
-	auth:                  &TwitchAuth,
-	cache_arc:             Arc<Mutex<NameIdCache>>,
-	cmd:                   CommandSource,
-	channel_specifics_arc: Arc<Mutex<crate::ChannelSpecificsCache>>,
+	state: &AppState,
+	cmd:   CommandSource,
 ) -> Option<String> {
-	if let Ok(mut cache) = cache_arc.clone().lock() {
-		cache.insert(cmd.sender.name.clone(), cmd.sender.id);
-	}
+	let span = info_span!(
+		"command",
+		command = %cmd.cmd,
+		channel = %cmd.channel.name,
+		user    = %cmd.sender.name,
+		pipe    = cmd.is_pipe,
+	);
+
+	execute_command(state, cmd).instrument(span).await
+}
+
+async fn execute_command(
+	state: &AppState,
+	cmd:   CommandSource,
+) -> Option<String> {
+	let AppState { pool, client, config, auth, .. } = state;
+
+	state.name_id_cache.insert(cmd.sender.name.clone(), cmd.sender.id);
 
 	let now = Instant::now();
+	let mut metric_name = cmd.cmd.as_str();
 
 	let cmd_out = match cmd.cmd.as_str() {
 		// standard commands
@@ -61,8 +93,11 @@ pub async fn handle_command(
 		"ping"           => ping(config).await,
 		"time"           => get_time(&cmd).await,
 		"pasta"          => get_rand_pasta().await,
-		"markov"         => markov(pool, &cmd).await,
-		"setcmd"         => set_cmd(pool, &cmd).await,
+		"markov"         => markov(state, &cmd).await,
+		"setcmd"         => set_cmd(pool, &cmd, config.prefix).await,
+		"editcmd"        => edit_cmd(pool, &cmd).await,
+		"cmds"           => list_cmds(pool, &cmd).await,
+		"cmdinfo"        => cmd_info(pool, &cmd).await,
 		"suggest"        => suggest(pool, &cmd).await,
 		"inspireme"      => get_inspire_image().await,
 		"binomial"       => binomial_probability(&cmd),
@@ -78,35 +113,56 @@ pub async fn handle_command(
 		"pyramid"        => pyramid(&cmd, client.clone()).await,
 		"weather"        => get_weather_report(&cmd.args).await,
 		"chatstats"      => get_chatstats(pool, &cmd, auth).await,
-		"uptime"         => get_uptime(auth, &cmd, cache_arc).await,
-		"accage"         => get_accage(auth, &cmd, cache_arc).await,
+		"uptime"         => get_uptime(auth, &cmd).await,
+		"accage"         => get_accage(state, &cmd).await,
 		"delcmd"         => remove_channel_command(pool, &cmd).await,
-		"followage"      => get_followage(&cmd, auth, cache_arc).await,
+		"followage"      => get_followage(state, &cmd).await,
 		"clearreminders" => clear_reminders(pool, cmd.sender.id).await,
 		"rmrm"           => clear_reminders(pool, cmd.sender.id).await,
 		"ls"             => find_last_seen(pool, &cmd, auth, config).await,
-		"first"          => first_message(pool, auth, cache_arc, &cmd).await,
-		"sethook"        => set_hook(pool, &cmd, channel_specifics_arc).await,
-		"offlinetime"    => get_offline_time(pool, auth, &cmd, cache_arc).await,
+		"first"          => first_message(state, &cmd).await,
+		"sethook"        => set_hook(state, &cmd).await,
+		"rmhook"         => remove_hook(state, &cmd).await,
+		"hooks"          => list_hooks(pool, &cmd).await,
+		"enablehook"     => enable_hook(state, &cmd, true).await,
+		"disablehook"    => enable_hook(state, &cmd, false).await,
+		"offlinetime"    => get_offline_time(state, &cmd).await,
+		"namechanges"    => get_name_changes(state, &cmd).await,
 		"bible"          => get_rand_holy_book_verse(api::HolyBook::Bible).await,
 		"quran"          => get_rand_holy_book_verse(api::HolyBook::Quran).await,
 		"tanakh"         => get_rand_holy_book_verse(api::HolyBook::Tanakh).await,
-		"remindme"       => add_reminder(pool, auth, cache_arc, &cmd, true).await,
-		"remind"         => add_reminder(pool, auth, cache_arc, &cmd, false).await,
-		"giveup"         => give_up_trivia(&cmd, auth, channel_specifics_arc).await,
-		"hint"           => give_trivia_hint(&cmd, auth, channel_specifics_arc).await,
-		"wordratio"      => get_word_ratio(pool, auth, &cmd, config.prefix, cache_arc).await,
+		"remindme"       => add_reminder(state, &cmd, true).await,
+		"remind"         => add_reminder(state, &cmd, false).await,
+		"giveup"         => give_up_trivia(state, &cmd).await,
+		"hint"           => give_trivia_hint(state, &cmd).await,
+		"wordratio"      => get_word_ratio(pool, auth, &cmd, config.prefix).await,
 		"commands"       => get_commands_reference_link(&config.commands_reference_path).await,
-		"trivia"         => attempt_start_trivia_game(&cmd, auth, channel_specifics_arc).await,
+		"trivia"         => attempt_start_trivia_game(state, &cmd).await,
 		"rose"           => tag_rand_chatter_with_rose(&cmd.channel.name, &config.disregarded_users).await,
-		"demultiplex"    => demultiplex(pool, client.clone(), config, auth, cache_arc, &cmd, channel_specifics_arc).await,
-		"bench"          => bench_command(pool, client.clone(), config, auth, cache_arc, &cmd, channel_specifics_arc).await,
+		"demultiplex"    => demultiplex(state, &cmd).await,
+		"bench"          => bench_command(state, &cmd).await,
 		// special commands
-		"pipe"           => pipe(pool, client.clone(), config, auth, cache_arc, &cmd, channel_specifics_arc).await,
-		""               => execute_alias(pool, client.clone(), config, auth, cache_arc, &cmd, channel_specifics_arc).await,
-		_                => try_execute_channel_command(pool, &cmd).await,
+		"pipe"           => pipe(state, &cmd).await,
+		""               => {
+			metric_name = "alias";
+			execute_alias(state, &cmd).await
+		},
+		_                => {
+			// (channel commands are named by anyone, so they share a label)
+			metric_name = "channel_command";
+			try_execute_channel_command(state, &cmd).await
+		},
 	};
 
+	let latency = now.elapsed();
+	let latency_ms = latency.as_millis() as u64;
+	metrics().observe_command(metric_name, latency, cmd_out.is_err());
+	match &cmd_out {
+		Err(e)                          => warn!(latency_ms, "Command failed; err: {e}"),
+		Ok(_) if latency > SLOW_COMMAND => warn!(latency_ms, "Slow command"),
+		Ok(_)                           => info!(latency_ms, "Executed command"),
+	}
+
 	let cmd_out = match cmd_out {
 		Ok(content_or_not) => content_or_not,
 		Err(e)      => {
@@ -126,29 +182,47 @@ pub async fn handle_command(
 	match db::log_command(
 		pool,
 		&cmd,
-		now.elapsed(),
+		latency,
 		if let Some(s) = &cmd_out {s} else {""}
 	).await {
 		Ok(_) => (),
-		Err(e) => println!("{e}")
+		Err(e) => error!("Couldn't log command; err: {e}")
 	};
 	
 	if let Some(output) = cmd_out {
-		// twitch generally doesn't allow awfully long messages
-		let out = {
-			if output.len() > 500 {
-				(&output[..500]).into()
-			} else {
-				output
-			}
-		};
-
-		client.say(cmd.channel.name.to_owned(), out).await.unwrap();
+		say(client, &cmd.channel.name, output).await;
 	}
 
 	None
 }
 
+// send a message into a channel (cut down to what twitch takes); failing
+// to do so shouldn't take anything down with it, so it only gets logged
+pub async fn say(
+	client:  &TwitchClient,
+	channel: &str,
+	message: String,
+) {
+	if let Err(e) = client.say(channel.to_owned(), fit_message(message)).await {
+		error!("Couldn't send a message to #{channel}; err: {e}");
+	}
+}
+
+// cut a message down to `MAX_MESSAGE_LENGTH` chars
+pub fn fit_message(message: String) -> String {
+	match message.chars().count() > MAX_MESSAGE_LENGTH {
+		true  => message.chars().take(MAX_MESSAGE_LENGTH).collect(),
+		false => message,
+	}
+}
+
+// commands working with the state of a channel (trivia, hooks)
+// have to run in the order they were sent in;
+// all of the other ones can run alongside each other
+pub fn needs_channel_order(cmd: &str) -> bool {
+	matches!(cmd, "trivia" | "hint" | "giveup" | "sethook" | "rmhook" | "enablehook" | "disablehook")
+}
+
 
 /// get a specified argument from list of Strings
 /// # Examples:
@@ -226,16 +300,15 @@ fn echo(
 
 // get age of specified account (or called)
 async fn get_accage(
-	twitch_auth: &TwitchAuth,
-	cmd:         &CommandSource,
-	name_id_cache_arc: Arc<Mutex<NameIdCache>>,
+	state: &AppState,
+	cmd:   &CommandSource,
 ) -> anyhow::Result<Option<String>> {
-	let (user, _) = match cmd.user_channel_info_from_args(twitch_auth, name_id_cache_arc).await {
+	let (user, _) = match cmd.user_channel_info_from_args(state).await {
 		Ok(a) => a,
 		Err(e) => return Ok(Some(e.to_string())),
 	};
 
-	match api::get_acc_creation_date(&user.name, twitch_auth).await? {
+	match api::get_acc_creation_date(&user.name, &state.auth).await? {
 		Some(date) => {
 			let duration = (Utc::now() - date).num_days();
 			let years = duration as f32 / 365.2425;
@@ -272,20 +345,15 @@ async fn set_alias(
 
 // run user's alias
 async fn execute_alias(
-	pool: &SqlitePool,
-	client: TwitchClient,
-	config: &Config,
-	auth: &TwitchAuth,
-	cache_arc: Arc<Mutex<NameIdCache>>,
-	cmd: &CommandSource,
-	channel_specifics_arc: Arc<Mutex<ChannelSpecificsCache>>,
+	state: &AppState,
+	cmd:   &CommandSource,
 ) -> anyhow::Result<Option<String>> {
 	let alias = match cmd.args.get(0) {
 		Some(a) => a.clone(),
 		None => return Ok(Some("❌ missing alias name".into())),
 	};
 
-	let alias_cmd = match db::get_alias_cmd(pool, cmd.sender.id, &alias).await? {
+	let alias_cmd = match db::get_alias_cmd(&state.pool, cmd.sender.id, &alias).await? {
 		Some(alias) => alias
 			.split(' ')
 			.map(|a| a.to_string())
@@ -308,7 +376,7 @@ async fn execute_alias(
 		timestamp: cmd.timestamp,
 	};
 
-	handle_command(pool, client, config, auth, cache_arc, new_cmd, channel_specifics_arc.clone()).await;
+	handle_command(state, new_cmd).await;
 
 	Ok(None)
 }
@@ -340,9 +408,7 @@ fn parse_duration_to_hm(s: &str) -> anyhow::Result<(i64, i64)> {
 
 // add a reminder for someone
 async fn add_reminder(
-	pool:        &SqlitePool,
-	auth:        &TwitchAuth,
-	cache_arc:   Arc<Mutex<NameIdCache>>,
+	state:       &AppState,
 	cmd:         &CommandSource,
 	is_for_self: bool,
 ) -> anyhow::Result<Option<String>> {
@@ -371,20 +437,13 @@ async fn add_reminder(
 		Err(_) => return Ok(Some("❌ no message provided".into())),
 	};
 
-	let mut for_user_id: Option<i32> = None;
-	if let Ok(cache) = cache_arc.lock() {
-		if let Some(id) = cache.get(to_user_name) {
-			for_user_id = Some(*id);
-		}
-	}
+	let mut for_user_id: Option<i32> = state.name_id_cache.get(to_user_name).map(|id| *id);
 
 	match for_user_id {
 		Some(_) => (),
-		None    => match api::id_from_nick(to_user_name, auth).await? {
+		None    => match api::id_from_nick(to_user_name, &state.auth).await? {
 			Some(id) => {
-				if let Ok(mut cache) = cache_arc.lock() {
-					cache.insert(to_user_name.to_string(), id);
-				}
+				state.name_id_cache.insert(to_user_name.to_string(), id);
 
 				for_user_id = Some(id); 
 			},
@@ -402,7 +461,7 @@ async fn add_reminder(
 		message,
 	};
 
-	db::insert_reminder(pool, &reminder).await?;
+	db::insert_reminder(&state.pool, &reminder).await?;
 
 	Ok(Some("✅ set successfully".into()))
 }
@@ -423,47 +482,88 @@ async fn clear_reminders(
 
 // return a markov chain of words
 async fn markov(
-	pool: &SqlitePool,
-	cmd: &CommandSource
+	state: &AppState,
+	cmd:   &CommandSource,
 ) -> anyhow::Result<Option<String>> {
-	let rounds: usize;
-	match cmd.args.len() {
-		// if no arguments are supplied, return immediately
-		0 => return Ok(Some("❌ insufficient args".into())),
-		// if number of rounds isn't set, set to default
-		1 => {rounds = 7},
-		// else parse both arguments
-		_ => 
-			match cmd.args[1].parse::<usize>() {
-				Ok(num) => {rounds = num},
-				Err(_)  => return Ok(Some("❌ expected positive integer".into())),
-		}
-	}
+	// don't (or again) let others imitate you with `user:`
+	match cmd.args.first().map(|a| a.as_str()) {
+		Some("optout") => {
+			db::set_markov_opt_out(&state.pool, cmd.sender.id, true).await?;
+			state.markov_models.retain(|(_, user_id), _| *user_id != cmd.sender.id);
 
-	// clamp the number of rounds to be <= 100
-	let rounds = {
-		if rounds > 100 { 100 } else { rounds }
-	};
+			return Ok(Some("✅ nobody can imitate you anymore".into()));
+		},
+		Some("optin") => {
+			db::set_markov_opt_out(&state.pool, cmd.sender.id, false).await?;
 
-	let mut output: Vec<String> = vec![cmd.args[0].clone()];
-	let mut seed: String = cmd.args[0].clone();
+			return Ok(Some("✅ you can be imitated again".into()));
+		},
+		// let (or don't let) other channels draw from this one
+		Some("share") => {
+			if !cmd.sender.is_mvb() {
+				return Ok(Some("❌ requires MVB privileges | E4".into()));
+			}
 
-	for _ in 0..rounds-1 {
-		let succ = match db::get_rand_markov_succ(pool, cmd.channel.id, &seed).await {
-		Ok(Some(successor)) => successor,
-			Ok(None) => continue,
-			Err(e) => {println!("{e}");break},
-		};
+			let shared = match cmd.args.get(1).map(|a| a.to_lowercase()).as_deref() {
+				Some("yes" | "true" | "on")  => true,
+				Some("no" | "false" | "off") => false,
+				_                            => return Ok(Some("❌ expected yes or no".into())),
+			};
+			db::set_markov_sharing(&state.pool, cmd.channel.id, shared).await?;
 
-		seed = succ.clone();
-		output.push(succ.to_owned());
-	}
+			return match shared {
+				true  => Ok(Some("✅ other channels can use the markov chains of this one".into())),
+				false => Ok(Some("✅ the markov chains of this channel stay here".into())),
+			};
+		},
+		// index all of the logged messages of the channel anew
+		Some("rebuild") => {
+			if !cmd.sender.statuses.contains(&TwitchBadge::Broadcaster) {
+				return Ok(Some("❌ only the broadcaster can rebuild the index".into()));
+			}
+
+			// a big channel takes a while, so the chat gets to know it's underway
+			// (a pipe only has its output to show)
+			if !cmd.is_pipe {
+				let total = db::count_channel_messages(&state.pool, cmd.channel.id).await?;
+				say(&state.client, &cmd.channel.name, format!("🔧 rebuilding the markov index out of {total} messages, this may take a while")).await;
+			}
+
+			// (a copy, so that the emotes can be refreshed in the meantime)
+			let emote_cache = state.emote_cache.read().await.clone();
+			let started = Instant::now();
+			let stats = markov::rebuild_index(&state.pool, &cmd.channel, &state.config, &emote_cache, |done, total| {
+				info!("Rebuilding the markov index of #{}: {done}/{total} messages", cmd.channel.name);
+			}).await?;
 
-	if output.len() == 1 {
-		return Ok(Some("❌ word not indexed yet | E1".into()));
+			let stats = match stats {
+				Some(s) => s,
+				None    => return Ok(Some("❌ the markov index of this channel is being rebuilt already".into())),
+			};
+
+			return Ok(Some(format!(
+				"✅ rebuilt the markov index out of {} messages in {}s (skipped {} commands and messages of disregarded users)",
+				stats.indexed,
+				started.elapsed().as_secs(),
+				stats.skipped,
+			)));
+		},
+		_ => (),
 	}
 
-	Ok(Some(format!("🔮 {}", output.join(" "))))
+	let req = match markov::MarkovRequest::from_args(&cmd.args, &state.config.markov) {
+		Ok(req) => req,
+		Err(e)  => return Ok(Some(format!("❌ {e}"))),
+	};
+
+	match markov::generate_for(state, &cmd.channel, &req).await {
+		Ok(Some(text))                 => Ok(Some(format!("🔮 {text}"))),
+		Ok(None) if req.user.is_some() => Ok(Some("❌ not enough messages to imitate yet".into())),
+		Ok(None) if req.seed.is_some() => Ok(Some("❌ word not indexed yet | E1".into())),
+		Ok(None)                       => Ok(Some("❌ couldn't come up with anything (new) yet".into())),
+		Err(markov::MarkovError::Other(e)) => Err(e),
+		Err(e)                         => Ok(Some(e.to_string())),
+	}
 }
 
 // show additional information about a spec. error
@@ -479,16 +579,14 @@ async fn explain (
 
 // returns the first (logged) message of a user
 async fn first_message(
-	pool:              &SqlitePool,
-	twitch_auth:       &TwitchAuth,
-	name_id_cache_arc: Arc<Mutex<NameIdCache>>,
-	cmd:               &CommandSource,
+	state: &AppState,
+	cmd:   &CommandSource,
 ) -> anyhow::Result<Option<String>> {
-	let (user, channel) = match cmd.user_channel_info_from_args(twitch_auth, name_id_cache_arc).await {
+	let (user, channel) = match cmd.user_channel_info_from_args(state).await {
 		Ok(a) => a,
 		Err(e) => return Ok(Some(e.to_string())),
 	};
-	let message = db::get_first_message(pool, user.id, channel.id).await?;
+	let message = db::get_first_message(&state.pool, user.id, channel.id).await?;
 
 	match message {
 		Some(msg) => Ok(Some(msg)),
@@ -559,22 +657,18 @@ async fn get_weather_report(
 
 // get uptime of a stream
 async fn get_uptime(
-	auth:              &TwitchAuth,
-	cmd:               &CommandSource,
-	name_id_cache_arc: Arc<Mutex<NameIdCache>>,
+	auth: &TwitchAuth,
+	cmd:  &CommandSource,
 ) -> anyhow::Result<Option<String>> {
 	let channel_name = match cmd.args.get(0) {
 		Some(nick) => nick,
 		None       => &cmd.channel.name,
 	};
 
-	let info = match api::get_stream_info(auth, channel_name).await? {
-		Some(i) => i,
+	let formatted = match api::get_stream_uptime(auth, channel_name).await? {
+		Some(d) => fmt_duration(d, false),
 		None    => return Ok(Some("❌ streamer not live".into())),
 	};
-	let duration = Utc::now() - info.data[0].started_at;
-	
-	let formatted = fmt_duration(duration, false);
 
 	Ok(Some(format!("⏱️ {channel_name} has been live for {formatted}")))
 }
@@ -605,13 +699,8 @@ async fn set_lurk_status(
 // measure how long a command takes
 // (requires bot to be vip/mod/...)
 async fn bench_command(
-	pool:       &SqlitePool,
-	client:     TwitchClient,
-	config:     &Config,
-	auth:       &TwitchAuth,
-	cache_arc:  Arc<Mutex<NameIdCache>>,
-	cmd:        &CommandSource,
-	channel_specifics_arc: Arc<Mutex<ChannelSpecificsCache>>,
+	state: &AppState,
+	cmd:   &CommandSource,
 ) -> anyhow::Result<Option<String>> {
 	let new_cmd = CommandSource {
 		is_pipe: true,
@@ -629,26 +718,49 @@ async fn bench_command(
 	};
 
 	let now = Instant::now();
-	handle_command(pool, client, config, auth, cache_arc, new_cmd, channel_specifics_arc.clone()).await;
+	handle_command(state, new_cmd).await;
 	Ok(Some(format!("📡 {} ms", now.elapsed().as_millis())))
 }
 
 // get the time a user has spent in an offline chat
 async fn get_offline_time(
-	pool: &SqlitePool,
-	twitch_auth: &TwitchAuth,
-	cmd:  &CommandSource,
-	name_id_cache_arc: Arc<Mutex<NameIdCache>>,
+	state: &AppState,
+	cmd:   &CommandSource,
 ) -> anyhow::Result<Option<String>> {
-	let (user, channel) = match cmd.user_channel_info_from_args(twitch_auth, name_id_cache_arc).await {
+	let (user, channel) = match cmd.user_channel_info_from_args(state).await {
 		Ok(a) => a,
 		Err(e) => return Ok(Some(e.to_string())),
 	};
 
-	let t = db::get_offline_time(pool, channel.id, user.id).await?;
+	let t = db::get_offline_time(&state.pool, channel.id, user.id).await?;
 	Ok(Some(format!("{} has spent {} in {}'s offline chat!", user.name, channel.name, fmt_duration(t, false))))
 }
 
+// list the names a user has previously been seen with
+async fn get_name_changes(
+	state: &AppState,
+	cmd:   &CommandSource,
+) -> anyhow::Result<Option<String>> {
+	let (user, _) = match cmd.user_channel_info_from_args(state).await {
+		Ok(a) => a,
+		Err(e) => return Ok(Some(e.to_string())),
+	};
+
+	let changes = db::get_name_changes(&state.pool, user.id).await?;
+
+	if changes.is_empty() {
+		return Ok(Some(format!("❌ no name changes of {} recorded", user.name)));
+	}
+
+	let history = changes
+		.iter()
+		.map(|c| format!("{} → {} ({})", c.old_name, c.new_name, c.timestamp.format("%Y-%m-%d")))
+		.collect::<Vec<String>>()
+		.join(", ");
+
+	Ok(Some(format!("📛 name changes of {}: {history}", user.name)))
+}
+
 // get the abstract from a wikipedia page
 async fn query_wikipedia(
 	cmd: &CommandSource,
@@ -662,7 +774,7 @@ async fn query_wikipedia(
 					.extract
 					.split('.').collect::<Vec<&str>>()[0];
 
-				Ok(Some(abs.to_owned()))
+				Ok(Some(convert_from_html_entities(abs.to_owned())))
 			} else {
 				Ok(Some("❌ couldn't get gist of article".into()))
 			}
@@ -702,16 +814,15 @@ async fn query_urban_dictionary(
 }
 
 async fn get_followage(
-	cmd:         &CommandSource,
-	twitch_auth: &TwitchAuth,
-	name_id_cache_arc: Arc<Mutex<NameIdCache>>,
+	state: &AppState,
+	cmd:   &CommandSource,
 ) -> anyhow::Result<Option<String>> {
-	let (user, channel) = match cmd.user_channel_info_from_args(twitch_auth, name_id_cache_arc).await {
+	let (user, channel) = match cmd.user_channel_info_from_args(state).await {
 		Ok(a) => a,
 		Err(e) => return Ok(Some(e.to_string())),
 	};
 
-	match api::get_followage(twitch_auth, channel.id, user.id).await? {
+	match api::get_followage(&state.auth, channel.id, user.id).await? {
 		Some(date) => {
 			let duration = Utc::now() - date;
 			let years = duration.num_days() as f32 / 365.2425;
@@ -727,45 +838,161 @@ async fn get_followage(
 }
 
 async fn set_cmd(
-	pool: &SqlitePool,
-	cmd:  &CommandSource,
+	pool:   &SqlitePool,
+	cmd:    &CommandSource,
+	prefix: char,
 ) -> anyhow::Result<Option<String>> {
 	if !cmd.sender.is_mvb() {
 		return Ok(Some("❌ not high enough status".into()));
 	}
 
-	let cmd_name = match cmd.args.get(0) {
-		Some(name) => name,
-		None       => return Ok(Some("❌ no name provided".into())),
+	let cmd_name = match cmd.args.get(0).map(|name| channel_command_name(name, prefix)) {
+		Some(Ok(name)) => name,
+		Some(Err(e))   => return Ok(Some(format!("❌ {e}"))),
+		None           => return Ok(Some("❌ no name provided".into())),
 	};
 
 	let cmd_type = match cmd.args.get(1) {
-		Some(type_) => type_,
+		Some(type_) if CHANNEL_COMMAND_TYPES.contains(&type_.as_str()) => type_,
+		Some(_)    => return Ok(Some("❌ command type not recognized".into())),
 		None       => return Ok(Some("❌ no type provided".into())),
 	};
 
-	if !(
-		cmd_type == "paste" ||
-		cmd_type == "templ" ||
-		cmd_type == "incr"
-	) {
-		return Ok(Some("❌ command type not recognized".into()));
+	let cmd_expr = match cmd.args.get(2) {
+		Some(_) => cmd.args[2..].join(" "),
+		None    => return Ok(Some("❌ no expression provided".into())),
+	};
+
+	match db::set_cmd(pool, cmd.channel.id, &cmd_name, cmd_type, &cmd_expr, &cmd.sender.name, cmd.timestamp).await? {
+		true  => Ok(Some("🔧 command created successfully".into())),
+		false => Ok(Some("🔧 command updated successfully".into())),
 	}
+}
 
-	let cmd_expr = match cmd.args.get(2) {
-		Some(name) => cmd.args[2..].join(" "),
-		None       => return Ok(Some("❌ no expression provided".into())),
+// the name a channel command gets saved under (lowercase, like the commands
+// that get called), as long as it could be called and isn't a built-in command
+pub fn channel_command_name(name: &str, prefix: char) -> Result<String, &'static str> {
+	let name = name.to_lowercase();
+
+	if name.is_empty() {
+		Err("the name can't be empty")
+	} else if name.chars().any(char::is_whitespace) {
+		Err("the name can't have spaces in it")
+	} else if name.contains(prefix) {
+		Err("the name can't have the prefix in it")
+	} else if BUILTIN_COMMANDS.contains(&name.as_str()) {
+		Err("there is a built-in command with that name")
+	} else {
+		Ok(name)
+	}
+}
+
+// set who may use a channel command and how often, e.g.
+// `$editcmd greet permission="sub" cooldown="30"`
+async fn edit_cmd(
+	pool: &SqlitePool,
+	cmd:  &CommandSource,
+) -> anyhow::Result<Option<String>> {
+	if !cmd.sender.is_mvb() {
+		return Ok(Some("❌ requires MVB privileges | E4".into()));
+	}
+
+	let cmd_name = match cmd.args.first() {
+		Some(name) => name,
+		None       => return Ok(Some("❌ no name provided".into())),
 	};
 
-	db::set_cmd(pool, cmd.channel.id, cmd_name, cmd_type, &cmd_expr).await?;
+	let saved = match db::get_channel_cmd(pool, cmd.channel.id, cmd_name).await? {
+		Some(c) => c,
+		None    => return Ok(Some(format!("❌ there is no command `{cmd_name}`"))),
+	};
 
-	Ok(Some("🔧 command created successfully".into()))
+	let permission = match parse_by_ident(&cmd.args, "permission") {
+		Some(p) => match crate::CommandPermission::from_name(&p) {
+			Some(p) => p,
+			None    => return Ok(Some(format!("❌ unknown permission `{p}` (everyone/sub/vip/mod/broadcaster)"))),
+		},
+		None    => crate::CommandPermission::from_name(&saved.permission).unwrap_or(crate::CommandPermission::Everyone),
+	};
+
+	let cooldown_s = match parse_by_ident(&cmd.args, "cooldown") {
+		Some(c) => match c.parse::<u64>() {
+			Ok(c)  => c,
+			Err(_) => return Ok(Some("❌ cooldown must be a number of seconds".into())),
+		},
+		None    => saved.cooldown_s as u64,
+	};
+
+	db::set_cmd_limits(pool, cmd.channel.id, cmd_name, permission, cooldown_s).await?;
+
+	Ok(Some(format!("🔧 command `{cmd_name}` updated successfully")))
 }
 
-pub async fn set_hook(
+// list the commands of the channel (as a paste if there are too many for a message)
+async fn list_cmds(
+	pool: &SqlitePool,
+	cmd:  &CommandSource,
+) -> anyhow::Result<Option<String>> {
+	let cmds = db::get_channel_commands(pool, cmd.channel.id).await?;
+
+	if cmds.is_empty() {
+		return Ok(Some("❌ the channel has no commands".into()));
+	}
+
+	let listed = format!(
+		"🛠️ {}",
+		cmds.iter().map(|c| c.name.as_str()).collect::<Vec<_>>().join(", "),
+	);
+
+	if listed.chars().count() <= MAX_MESSAGE_LENGTH {
+		return Ok(Some(listed));
+	}
+
+	let paste = cmds
+		.iter()
+		.map(|c| format!("{} ({}): {}", c.name, c.cmd_type, c.expression))
+		.collect::<Vec<_>>()
+		.join("\n");
+
+	Ok(Some(format!("🛠️ {}", api::upload_to_pastebin(&paste).await?)))
+}
+
+// what there is to know about a channel command
+async fn cmd_info(
 	pool: &SqlitePool,
 	cmd:  &CommandSource,
-	channel_specifics_arc: Arc<Mutex<crate::ChannelSpecificsCache>>,
+) -> anyhow::Result<Option<String>> {
+	let cmd_name = match cmd.args.first() {
+		Some(name) => name,
+		None       => return Ok(Some("❌ no name provided".into())),
+	};
+
+	let saved = match db::get_channel_cmd(pool, cmd.channel.id, cmd_name).await? {
+		Some(c) => c,
+		None    => return Ok(Some(format!("❌ there is no command `{cmd_name}`"))),
+	};
+
+	// (commands from before these were saved have no creator)
+	let mut details = vec![saved.cmd_type.clone()];
+	match (saved.creator.as_str(), saved.created_at) {
+		("", _)               => (),
+		(creator, Some(date)) => details.push(format!("created by {creator} on {}", date.format("%Y-%m-%d"))),
+		(creator, None)       => details.push(format!("created by {creator}")),
+	}
+	details.push(format!("used {} times", saved.uses));
+	details.push(format!("for {}", saved.permission));
+	if saved.cooldown_s > 0 {
+		details.push(format!("{}s cooldown", saved.cooldown_s));
+	}
+
+	Ok(Some(format!("🛠️ {} | {}", saved.name, details.join(" | "))))
+}
+
+// set a hook (replacing the one of the same name), e.g.
+// `$sethook greeting word catch="hi" content="hello" cooldown="30" users="forsen,nymn" badges="sub,vip"`
+pub async fn set_hook(
+	state: &AppState,
+	cmd:   &CommandSource,
 ) -> anyhow::Result<Option<String>> {
 	if !cmd.sender.is_mvb() {
 		return Ok(Some("❌ requires MVB privileges | E4".into()));
@@ -780,7 +1007,7 @@ pub async fn set_hook(
 		Some(s) => {
 			match crate::HookMatchType::from_str(s) {
 				Ok(h) => h,
-				Err(_) => return Ok(Some("❌ hook type not valid".into()))
+				Err(_) => return Ok(Some("❌ hook type not valid (exact/substr/word/regex)".into()))
 			}
 		},
 		None => return Ok(Some("❌ no hook type provided".into())),
@@ -796,70 +1023,274 @@ pub async fn set_hook(
 		None => return Ok(Some("❌ no hook content provided".into())),
 	};
 
-	db::set_hook(pool, cmd.channel.id, hook_name, &hook_type.to_string(), &hook_catchphrase, &hook_content).await?;
-
-	let hook = crate::MessageHook {
-		capture_string: hook_catchphrase.to_owned(),
-		h_type:         hook_type,
-		content:        hook_content.to_owned(),
+	let mut hook = match crate::MessageHook::new(hook_name.to_owned(), hook_type, hook_catchphrase, hook_content) {
+		Ok(h)  => h,
+		Err(_) => return Ok(Some("❌ the catchphrase is not a valid regex".into())),
 	};
 
+	if let Some(cooldown) = parse_by_ident(&cmd.args, "cooldown") {
+		hook.cooldown_s = match cooldown.parse() {
+			Ok(c)  => c,
+			Err(_) => return Ok(Some("❌ the cooldown has to be a number of seconds".into())),
+		};
+	}
+
+	if let Some(users) = parse_by_ident(&cmd.args, "users") {
+		hook.users = users
+			.split(',')
+			.map(|u| u.trim().trim_start_matches('@').to_lowercase())
+			.filter(|u| !u.is_empty())
+			.collect();
+	}
 
-	if let Ok(mut cache) = channel_specifics_arc.lock() {
-		let curr_hooks = (*cache)
-			.get(&cmd.channel.id.to_string())
-			.unwrap()
-			.hooks
-			.clone();
-		
-		let curr_ong = (*cache)
-			.get(&cmd.channel.id.to_string())
-			.unwrap()
-			.ongoing_trivia_game
-			.clone();
-
-		let mut hooks = curr_hooks;
-		hooks.push(hook);
-
-		(*cache).insert(
-			cmd.channel.id.to_string(),
-			crate::ChannelSpecifics {
-				hooks,
-				ongoing_trivia_game: curr_ong,
+	if let Some(badges) = parse_by_ident(&cmd.args, "badges") {
+		for badge in badges.split(',').map(str::trim).filter(|b| !b.is_empty()) {
+			match TwitchBadge::from_name(badge) {
+				TwitchBadge::Unrecognized => return Ok(Some(format!("❌ unknown badge `{badge}`"))),
+				badge                     => hook.badges.push(badge),
 			}
-		);
+		}
 	}
 
+	db::set_hook(&state.pool, cmd.channel.id, &hook).await?;
+
+	let channel = state.channel(&cmd.channel.id.to_string());
+	let mut channel = channel.lock().await;
+	if let Some(old) = channel.hooks.iter().find(|h| h.name == hook.name) {
+		hook.count = old.count;
+	}
+	channel.hooks.retain(|h| h.name != hook.name);
+	channel.hooks.push(hook);
+
 	Ok(Some("🔧 hook created successfully".into()))
 }
 
-pub async fn try_execute_channel_command(
+// remove a hook of the channel
+async fn remove_hook(
+	state: &AppState,
+	cmd:   &CommandSource,
+) -> anyhow::Result<Option<String>> {
+	if !cmd.sender.is_mvb() {
+		return Ok(Some("❌ requires MVB privileges | E4".into()));
+	}
+
+	let hook_name = match cmd.args.first() {
+		Some(h) => h,
+		None    => return Ok(Some("❌ no hook name provided".into()))
+	};
+
+	if !db::remove_hook(&state.pool, cmd.channel.id, hook_name).await? {
+		return Ok(Some(format!("❌ there is no hook `{hook_name}`")));
+	}
+
+	state.channel(&cmd.channel.id.to_string())
+		.lock()
+		.await
+		.hooks
+		.retain(|h| &h.name != hook_name);
+
+	Ok(Some("🔧 hook removed successfully".into()))
+}
+
+// turn a hook of the channel on or off
+async fn enable_hook(
+	state:   &AppState,
+	cmd:     &CommandSource,
+	enabled: bool,
+) -> anyhow::Result<Option<String>> {
+	if !cmd.sender.is_mvb() {
+		return Ok(Some("❌ requires MVB privileges | E4".into()));
+	}
+
+	let hook_name = match cmd.args.first() {
+		Some(h) => h,
+		None    => return Ok(Some("❌ no hook name provided".into()))
+	};
+
+	if !db::set_hook_enabled(&state.pool, cmd.channel.id, hook_name, enabled).await? {
+		return Ok(Some(format!("❌ there is no hook `{hook_name}`")));
+	}
+
+	let channel = state.channel(&cmd.channel.id.to_string());
+	let mut channel = channel.lock().await;
+	for hook in channel.hooks.iter_mut().filter(|h| &h.name == hook_name) {
+		hook.enabled = enabled;
+	}
+
+	match enabled {
+		true  => Ok(Some(format!("🔧 hook `{hook_name}` enabled"))),
+		false => Ok(Some(format!("🔧 hook `{hook_name}` disabled"))),
+	}
+}
+
+// list the hooks of the channel, e.g. "🪝 greeting (substr), spam (regex, 30s cooldown, off)"
+async fn list_hooks(
 	pool: &SqlitePool,
 	cmd:  &CommandSource,
+) -> anyhow::Result<Option<String>> {
+	let hooks = db::get_saved_hooks(pool, cmd.channel.id).await?;
+
+	if hooks.is_empty() {
+		return Ok(Some("❌ the channel has no hooks".into()));
+	}
+
+	let hooks = hooks
+		.iter()
+		.map(|h| {
+			let mut details = vec![h.h_type.clone()];
+			if h.cooldown_s > 0 {
+				details.push(format!("{}s cooldown", h.cooldown_s));
+			}
+			if !h.enabled {
+				details.push("off".into());
+			}
+
+			format!("{} ({})", h.name, details.join(", "))
+		})
+		.collect::<Vec<_>>()
+		.join(", ");
+
+	Ok(Some(format!("🪝 {hooks}")))
+}
+
+// the responses of the channel's hooks to a chat message (each hook that
+// goes off has its own, and the cooldowns of those start); a hook responding
+// with a command gets the output of the command run by the sender of the message
+pub async fn check_hooks(
+	state:   &AppState,
+	channel: &Channel,
+	sender:  &crate::Sender,
+	message: &str,
+	now:     DateTime<Utc>,
+) -> Vec<String> {
+	let mut responses = vec![];
+	for fired in fire_hooks(state, channel, sender, message, now).await {
+		if let Some(response) = hook_response(state, fired).await {
+			responses.push(response);
+		}
+	}
+
+	responses
+}
+
+// a hook a message set off, along with what it needs for its response
+pub struct FiredHook {
+	hook:   crate::MessageHook,
+	source: CommandSource,
+}
+
+// the hooks of the channel a message sets off, which count as fired from then on
+// (their responses are up to `hook_response`)
+pub async fn fire_hooks(
+	state:   &AppState,
+	channel: &Channel,
+	sender:  &crate::Sender,
+	message: &str,
+	now:     DateTime<Utc>,
+) -> Vec<FiredHook> {
+	// (the map entry mustn't be held across the await)
+	let specifics = match state.channel_specifics.get(&channel.id.to_string()) {
+		Some(specifics) => specifics.clone(),
+		None            => return vec![],
+	};
+
+	// (the commands the hooks run may need the channel themselves)
+	let fired = specifics
+		.lock()
+		.await
+		.hooks
+		.iter_mut()
+		.filter_map(|h| {
+			let captures = h.fires_on(sender, message, now)?;
+			h.last_fired = Some(now);
+			h.count += 1;
+
+			Some((h.clone(), captures))
+		})
+		.collect::<Vec<_>>();
+
+	fired
+		.into_iter()
+		.map(|(hook, captures)| FiredHook {
+			// (the hook's args are the groups its regex captured, so that `{1}` is the first one)
+			source: CommandSource {
+				cmd:       hook.name.clone(),
+				args:      captures.into_iter().skip(1).collect(),
+				sender:    sender.clone(),
+				channel:   channel.clone(),
+				timestamp: now,
+				is_pipe:   true,
+			},
+			hook,
+		})
+		.collect()
+}
+
+// the response of a fired hook, `None` if it's empty; as it may
+// run commands, it can take as long as they do
+pub async fn hook_response(
+	state: &AppState,
+	fired: FiredHook,
+) -> Option<String> {
+	let FiredHook { hook, source } = fired;
+
+	if let Err(e) = db::count_hook_fire(&state.pool, source.channel.id, &hook.name).await {
+		error!("Couldn't count the fire of hook `{}`; err: {e}", hook.name);
+	}
+
+	let ctx = template::TemplateContext { state, cmd: &source, count: hook.count };
+
+	// (a content starting with the prefix runs a command, the same as `{$command}` would)
+	let response = match hook.content.strip_prefix(state.config.prefix) {
+		Some(command) => template::run(&ctx, command).await,
+		None          => Some(template::fill(&ctx, &hook.content).await),
+	};
+
+	response.filter(|r| !r.is_empty())
+}
+
+pub async fn try_execute_channel_command(
+	state: &AppState,
+	cmd:   &CommandSource,
 ) -> anyhow::Result<Option<String>> {
 	let cmd_name = cmd.cmd.as_str();
 
-	let (cmd_type, cmd_expr, cmd_meta) = match db::get_channel_cmd(pool, cmd.channel.id, cmd_name).await? {
+	let saved = match db::get_channel_cmd(&state.pool, cmd.channel.id, cmd_name).await? {
 		Some(cmd) => cmd,
 		None => return Ok(None),
 	};
 
-	let mut out = cmd_expr;
+	let permission = crate::CommandPermission::from_name(&saved.permission)
+		.unwrap_or(crate::CommandPermission::Everyone);
+	if cmd.sender.permission() < permission {
+		return Ok(Some(format!("❌ requires {} status", permission.name())));
+	}
 
-	if (cmd_type == "templ") {
-		for i in 0..cmd.args.len() {
-			out = out.replace(&format!("{{{}}}", i+1), &cmd.args[i]);
+	// (a command that's cooling down just doesn't respond)
+	if let Some(last_used) = saved.last_used {
+		if cmd.timestamp < last_used + Duration::seconds(saved.cooldown_s) {
+			return Ok(None);
 		}
+	}
+
+	db::count_cmd_use(&state.pool, cmd.channel.id, cmd_name, cmd.timestamp).await?;
 
-		return Ok(Some(out));
+	let cmd_type = saved.cmd_type.as_str();
+	let ctx = template::TemplateContext { state, cmd, count: saved.uses + 1 };
+
+	if (cmd_type == "templ") {
+		return Ok(Some(template::fill(&ctx, &saved.expression).await));
 	}
 
 	if (cmd_type == "paste") {
-		return Ok(Some(out));
+		return Ok(Some(saved.expression));
 	}
 
+	// (`{}` is what the command counts)
 	if (cmd_type == "incr") {
-		return Ok(Some(out.replace(&"{}", &format!("{cmd_meta}"))));
+		let expr = saved.expression.replace("{}", &(saved.metadata + 1).to_string());
+
+		return Ok(Some(template::fill(&ctx, &expr).await));
 	}
 
 	// unreachable unless some obscure internal error occures
@@ -870,6 +1301,10 @@ pub async fn remove_channel_command(
 	pool: &SqlitePool,
 	cmd:  &CommandSource,
 ) -> anyhow::Result<Option<String>> {
+	if !cmd.sender.is_mvb() {
+		return Ok(Some("❌ requires MVB privileges | E4".into()));
+	}
+
 	let cmd_name = match cmd.args.get(0) {
 		Some(a) => a,
 		None => return Ok(Some("❌ no command name provided".into()))
@@ -886,7 +1321,6 @@ pub async fn get_word_ratio(
 	auth:   &TwitchAuth,
 	cmd:    &CommandSource,
 	cmd_prefix: char,
-	name_id_cache_arc: Arc<Mutex<NameIdCache>>,
 ) -> anyhow::Result<Option<String>> {
 	let (user_name, user_id, word) = match cmd.args.len() {
 		0 => return Ok(Some("❌ no word provided".into())),
@@ -964,13 +1398,8 @@ fn decide(
 
 // chain commands via |
 async fn pipe(
-	pool:      &SqlitePool,
-	client:    TwitchClient,
-	config:    &Config,
-	auth:      &TwitchAuth,
-	cache_arc: Arc<Mutex<NameIdCache>>,
-	cmd:       &CommandSource,
-	channel_specifics_arc: Arc<Mutex<ChannelSpecificsCache>>,
+	state: &AppState,
+	cmd:   &CommandSource,
 ) -> anyhow::Result<Option<String>> {
 	// the command is supposed to be of the form
 	// $pipe <command1 + command1 args> | <command2 + command3 args> | ...
@@ -1023,7 +1452,7 @@ async fn pipe(
 			_           => (),
 		}
 
-		if let Some(output) = handle_command(pool, client.clone(), config, auth, cache_arc.clone(), new_cmd, channel_specifics_arc.clone()).await {
+		if let Some(output) = handle_command(state, new_cmd).await {
 			temp_output = output;
 		} else {
 			temp_output = "".to_owned();
@@ -1083,10 +1512,10 @@ async fn get_reddit_post(
 
 			match post_type {
 				api::RedditPostType::MostUpvotes => {
-					let title    = &posts[0].data.title;
+					let title    = convert_from_html_entities(posts[0].data.title.clone());
 					let selftext = match &posts[0].data.selftext[..] {
-						"" => format!(": {}", &posts[0].data.selftext),
-						_  => "".into(),
+						"" => String::new(),
+						s  => format!(": {}", convert_from_html_entities(s.to_owned())),
 					};
 					let url      = &posts[0].data.url;
 
@@ -1095,10 +1524,10 @@ async fn get_reddit_post(
 				api::RedditPostType::Random => {
 					let rand_post = posts[rand::thread_rng().gen_range(0..posts.len())].clone();
 
-					let title    = rand_post.data.title;
+					let title    = convert_from_html_entities(rand_post.data.title);
 					let selftext = match &rand_post.data.selftext[..] {
-						"" => format!(": {}", rand_post.data.selftext),
-						_  => "".into(),
+						"" => String::new(),
+						s  => format!(": {}", convert_from_html_entities(s.to_owned())),
 					};
 					let url      = rand_post.data.url;
 
@@ -1141,114 +1570,314 @@ pub async fn get_rand_holy_book_verse(
 	Ok(Some(format!("({book}{book_number} ch. {chapter}) {text}")))
 }
 
-// start a trivia game (if one is not going on)
+// the most questions a round of trivia can have
+const MAX_TRIVIA_ROUND_LENGTH: u32 = 20;
+// how many users `$trivia top` shows
+const TRIVIA_LEADERBOARD_SIZE: i64 = 5;
+
+// start a trivia game (if one is not going on),
+// or show the points scored in the channel so far
 pub async fn attempt_start_trivia_game(
-	cmd:                      &CommandSource,
-	twitch_auth:              &TwitchAuth,
-	channel_specifics_arc:    Arc<Mutex<ChannelSpecificsCache>>,
+	state: &AppState,
+	cmd:   &CommandSource,
 ) -> anyhow::Result<Option<String>, anyhow::Error> {
-	if let Ok(mut cache) = channel_specifics_arc.lock() {
-		// check if there isn't a game going on
-		if (
-			(*cache).get(&cmd.channel.id.to_string()).is_some() &&
-			(*cache).get(&cmd.channel.id.to_string()).unwrap().ongoing_trivia_game.is_some()
-		) {
-			return Ok(Some("❌ there is currently a game going on!".into()));
-		}
-	} // the access here has to be closed in order to execute async stuff
-	  // (there might be a better way to do this but i am oblivious)
-	else {
-		return Ok(Some("❌ internal server error has occurred, sorry PoroSad".into()))
+	match cmd.args.first().map(|a| a.as_str()) {
+		Some("top")   => return trivia_leaderboard(state, cmd).await,
+		Some("score") => return trivia_score(state, cmd).await,
+		Some("add")   => return add_trivia_question(state, cmd).await,
+		_             => (),
 	}
 
-	// since there is no game in the channel, start one
+	let specifics = state.channel(&cmd.channel.id.to_string());
+	let mut channel = specifics.lock().await;
 
-	let cat = api::TriviaCategory::from_vec(&cmd.args);
-	let dif = api::TriviaDifficulty::from_vec(&cmd.args);
-	let typ = api::TriviaType::from_vec(&cmd.args);
-	
-	let question = api::fetch_trivia_question(cat, dif, typ).await?;
-	let fmted_info = {
-		let q = convert_from_html_entities(question.question);
-		let c = convert_from_html_entities(question.correct_answer);
-		let w = question
-			.incorrect_answers
-			.iter()
-			.map(|a| convert_from_html_entities(a.to_owned()))
-			.collect::<Vec<String>>();
-
-		crate::TriviaGameInfo {
-			question:       q,
-			correct_answer: c,
-			wrong_answers:  w,
+	// check if there isn't a game going on (or about to)
+	if channel.ongoing_trivia_game.is_some() || channel.trivia_pending {
+		return Ok(Some("❌ there is currently a game going on!".into()));
+	}
+
+	// since there is no game in the channel, start one;
+	// a number among the arguments is how many questions there will be
+	let mut round = crate::TriviaRound::default();
+	for arg in &cmd.args {
+		match arg.parse::<u32>() {
+			Ok(length) => round.length = length.clamp(1, MAX_TRIVIA_ROUND_LENGTH),
+			Err(_)     => round.options.push(arg.clone()),
 		}
+	}
+
+	// (the rest of the channel doesn't wait for the question)
+	channel.trivia_pending = true;
+	drop(channel);
+
+	let fetched = trivia::fetch_question(&state.pool, cmd.channel.id, round).await;
+
+	let mut channel = specifics.lock().await;
+	channel.trivia_pending = false;
+
+	let game = match fetched? {
+		Some(game) => game,
+		None       => return Ok(Some("❌ there are no questions like that".into())),
 	};
+	let announcement = game.announcement();
+	channel.ongoing_trivia_game = Some(game);
 
-	if let Ok(mut cache) = channel_specifics_arc.lock() {
-		let curr_hooks = (*cache)
-			.get(&cmd.channel.id.to_string())
-			.unwrap()
-			.hooks
-			.clone();
+	Ok(Some(announcement))
+}
 
-		(*cache).insert(
-			cmd.channel.id.to_string(),
-			crate::ChannelSpecifics {
-				hooks:               curr_hooks,
-				ongoing_trivia_game: Some(fmted_info.clone())
-			}
-		);
+// add a question to the channel's own trivia questions, e.g.
+// `$trivia add question="..." answer="..." wrong="x|y|z" difficulty="hard" category="film"`
+async fn add_trivia_question(
+	state: &AppState,
+	cmd:   &CommandSource,
+) -> anyhow::Result<Option<String>> {
+	if !cmd.sender.is_mvb() {
+		return Ok(Some("❌ requires MVB privileges | E4".into()));
+	}
 
+	let question   = parse_by_ident(&cmd.args, "question");
+	let answer     = parse_by_ident(&cmd.args, "answer");
+	let wrong      = parse_by_ident(&cmd.args, "wrong");
+	let difficulty = parse_by_ident(&cmd.args, "difficulty");
+	let category   = parse_by_ident(&cmd.args, "category");
 
-		Ok(Some(fmted_info.question))
+	let (question, answer, wrong) = match (question, answer, wrong) {
+		(Some(q), Some(a), Some(w)) if !q.trim().is_empty() && !a.trim().is_empty() => (q.trim().to_owned(), a.trim().to_owned(), w),
+		_ => return Ok(Some("❌ usage: $trivia add question=\"...\" answer=\"...\" wrong=\"x|y|z\" [difficulty=\"...\"] [category=\"...\"]".into())),
+	};
+	let wrong = wrong
+		.split('|')
+		.map(|w| w.trim().to_owned())
+		.filter(|w| !w.is_empty())
+		.collect::<Vec<_>>();
+
+	// true or false questions are asked like opentdb's boolean ones
+	let is_bool = |a: &str| a.eq_ignore_ascii_case("true") || a.eq_ignore_ascii_case("false");
+	let type_field = match wrong.len() == 1 && is_bool(&answer) && is_bool(&wrong[0]) {
+		true  => "boolean",
+		false => "multiple",
+	};
 
-	} else {
-		Ok(Some("An internal error has occured".into()))
+	let question = crate::api_models::TriviaQuestion {
+		category:          category
+			.map(|c| api::TriviaCategory::from_vec(&[c]).to_opentdb_name().unwrap_or_default().to_owned())
+			.unwrap_or_default(),
+		type_field:        type_field.into(),
+		difficulty:        difficulty.unwrap_or_else(|| "medium".into()).to_lowercase(),
+		question,
+		correct_answer:    answer,
+		incorrect_answers: wrong,
+	};
+
+	if let Err(e) = trivia::check_question(&question) {
+		return Ok(Some(format!("❌ {e}")));
+	}
+
+	match db::add_trivia_question(&state.pool, cmd.channel.id, &question, Some(cmd.sender.id)).await? {
+		true  => Ok(Some("🔧 Added the question".into())),
+		false => Ok(Some("❌ The channel has that question already".into())),
+	}
+}
+
+// check whether a chat message answers the trivia question of the channel;
+// if it does, the points are given out and the next question (if any) asked
+pub async fn check_trivia_answer(
+	state:     &AppState,
+	channel:   &Channel,
+	user_id:   i32,
+	user_name: &str,
+	message:   &str,
+) -> anyhow::Result<Option<String>> {
+	// (the map entry mustn't be held across the await)
+	let channel_specifics = match state.channel_specifics.get(&channel.id.to_string()) {
+		Some(specifics) => specifics.clone(),
+		None            => return Ok(None),
+	};
+	let mut specifics = channel_specifics.lock().await;
+
+	let game = match &mut specifics.ongoing_trivia_game {
+		Some(game) => game,
+		None       => return Ok(None),
+	};
+	if !game.guess(user_id, message) {
+		return Ok(None);
+	}
+
+	let points = game.points();
+	let mut round = game.round.clone();
+	round.add_points(user_name, points);
+	// only the end of a game (not the channel's hooks) goes away
+	specifics.ongoing_trivia_game = None;
+	specifics.trivia_pending = !round.is_last_question();
+	drop(specifics);
+
+	// (the next question is pending, so it gets asked even if the points can't be saved)
+	if let Err(e) = db::add_trivia_points(&state.pool, channel.id, user_id, user_name, points).await {
+		error!("Couldn't add the trivia points of {user_name}; err: {e}");
+	}
+
+	let correct = format!("@{user_name} Correct! (+{points})");
+
+	match continue_trivia_round(state, channel.id, &channel_specifics, round, true).await {
+		Some(next) => Ok(Some(format!("{correct} | {next}"))),
+		None       => Ok(Some(correct)),
+	}
+}
+
+// ask the next question of a round (if there is one and `go_on`),
+// else say how the round went (if there was more than one question);
+// the caller marks the channel's trivia as pending (and lets go of it) when
+// there is a next question, which gets put in place once it's fetched
+async fn continue_trivia_round(
+	state:      &AppState,
+	channel_id: i32,
+	specifics: &tokio::sync::Mutex<crate::ChannelSpecifics>,
+	mut round: crate::TriviaRound,
+	go_on:     bool,
+) -> Option<String> {
+	if round.is_last_question() || !go_on {
+		return match round.length {
+			1 => None,
+			_ => Some(format!("The round is over: {}", round.fmt_scores())),
+		};
+	}
+
+	round.current += 1;
+	let scores = round.fmt_scores();
+
+	let fetched = trivia::fetch_question(&state.pool, channel_id, round).await;
+
+	let mut specifics = specifics.lock().await;
+	specifics.trivia_pending = false;
+
+	match fetched {
+		Ok(Some(game)) => {
+			let announcement = game.announcement();
+			specifics.ongoing_trivia_game = Some(game);
+
+			Some(announcement)
+		},
+		Ok(None) => Some(format!("Couldn't get another question, so the round is over: {scores}")),
+		Err(e)   => {
+			error!("Couldn't get the next trivia question; err: {e}");
+
+			Some(format!("Couldn't get another question, so the round is over: {scores}"))
+		},
+	}
+}
+
+// give a hint once half of the time for a question is up,
+// and reveal the answer once all of it is
+pub async fn check_trivia_timer(
+	state:   &AppState,
+	channel: &Channel,
+	now:     DateTime<Utc>,
+) -> anyhow::Result<Option<String>> {
+	let config = &state.config.trivia;
+	if config.timeout_s == 0 {
+		return Ok(None);
+	}
+
+	// (the map entry mustn't be held across the await)
+	let channel_specifics = match state.channel_specifics.get(&channel.id.to_string()) {
+		Some(specifics) => specifics.clone(),
+		None            => return Ok(None),
+	};
+	let mut specifics = channel_specifics.lock().await;
+
+	let game = match &mut specifics.ongoing_trivia_game {
+		Some(game) => game,
+		None       => return Ok(None),
+	};
+	let elapsed = (now - game.asked_at).num_seconds().max(0) as u64;
+
+	if elapsed >= config.timeout_s {
+		let answer = game.correct_answer.clone();
+		let round = game.round.clone();
+		specifics.ongoing_trivia_game = None;
+		specifics.trivia_pending = config.continue_round && !round.is_last_question();
+		drop(specifics);
+
+		let times_up = format!("⏰ Time's up! The answer was \'{answer}\'");
+
+		return match continue_trivia_round(state, channel.id, &channel_specifics, round, config.continue_round).await {
+			Some(next) => Ok(Some(format!("{times_up} | {next}"))),
+			None       => Ok(Some(times_up)),
+		};
+	}
+
+	if elapsed * 2 >= config.timeout_s && !game.hinted {
+		game.hinted = true;
+
+		let hint = match config.hint {
+			crate::TriviaHint::Options => game.options_hint(),
+			crate::TriviaHint::Letters => game.letters_hint(),
+		};
+
+		return Ok(Some(format!("💡 {hint}")));
 	}
+
+	Ok(None)
 }
 
 // if there is a game going on in the chatroom, give it up
 pub async fn give_up_trivia(
-	cmd:                      &CommandSource,
-	twitch_auth:              &TwitchAuth,
-	channel_specifics_arc: Arc<Mutex<ChannelSpecificsCache>>,
+	state: &AppState,
+	cmd:   &CommandSource,
 ) -> anyhow::Result<Option<String>> {
-	let channel_id = cmd.channel.id;
-
-	if let Ok(mut cache) = channel_specifics_arc.lock() {
-		if (*cache).get(&channel_id.to_string()).is_some() {
-			if (*cache).get(&channel_id.to_string()).unwrap().ongoing_trivia_game.is_some() {
-				let curr_hooks = (*cache)
-					.get(&channel_id.to_string())
-					.unwrap()
-					.hooks
-					.clone();
-				
-				let q = &(*cache)
-					.get(&channel_id.to_string())
-					.unwrap()
-					.ongoing_trivia_game
-					.clone();
-				
-				(*cache).insert(
-					channel_id.to_string(),
-					crate::ChannelSpecifics {
-						hooks:               curr_hooks,
-						ongoing_trivia_game: None,
-					}
-				);
-				
-				if let Some(qa) = q {
-					let corr_answer = &qa.correct_answer;
-					return Ok(Some(format!("So bad LUL | The answer was \'{corr_answer}\'")));
-				}
+	let channel = state.channel(&cmd.channel.id.to_string());
+	let mut channel = channel.lock().await;
+
+	match channel.ongoing_trivia_game.take() {
+		Some(qa) => {
+			let corr_answer = &qa.correct_answer;
+
+			match qa.round.length {
+				1 => Ok(Some(format!("So bad LUL | The answer was \'{corr_answer}\'"))),
+				_ => Ok(Some(format!("So bad LUL | The answer was \'{corr_answer}\' | The round is over: {}", qa.round.fmt_scores()))),
 			}
-			
-			return Ok(Some("❌ there was no game going on LUL".into()));
-		}
+		},
+		None     => Ok(Some("❌ there was no game going on LUL".into())),
+	}
+}
+
+// the users with the most trivia points in the channel
+async fn trivia_leaderboard(
+	state: &AppState,
+	cmd:   &CommandSource,
+) -> anyhow::Result<Option<String>> {
+	let scores = db::get_trivia_leaderboard(&state.pool, cmd.channel.id, TRIVIA_LEADERBOARD_SIZE).await?;
+
+	if scores.is_empty() {
+		return Ok(Some("❌ nobody has answered anything yet".into()));
+	}
+
+	let top = scores
+		.iter()
+		.map(|s| format!("{}. {} ({})", s.rank, s.user_name, s.points))
+		.collect::<Vec<_>>()
+		.join(", ");
+
+	Ok(Some(format!("🏆 {top}")))
+}
+
+// the trivia points of a user (or of the sender) in the channel
+async fn trivia_score(
+	state: &AppState,
+	cmd:   &CommandSource,
+) -> anyhow::Result<Option<String>> {
+	let user = match cmd.args.get(1) {
+		Some(name) => match crate::resolve_user(&name.trim_start_matches('@').to_lowercase(), state).await {
+			Ok(Some(user)) => user,
+			_              => return Ok(Some(format!("💢 User `{name}` was not found"))),
+		},
+		None       => Channel { id: cmd.sender.id, name: cmd.sender.name.clone() },
+	};
+
+	match db::get_trivia_score(&state.pool, cmd.channel.id, user.id).await? {
+		Some(s) => Ok(Some(format!("🏆 {} has {} points from {} answers (#{})", s.user_name, s.points, s.answers, s.rank))),
+		None    => Ok(Some(format!("❌ {} hasn't answered anything yet", user.name))),
 	}
-	
-	Ok(Some("An internal error has occured".into()))
 }
 
 // get an answer to "any" question
@@ -1270,13 +1899,8 @@ pub async fn query(
 
 // used to execute a command multiple times
 pub async fn demultiplex(
-	pool:      &SqlitePool,
-	client:    TwitchClient,
-	config:    &Config,
-	auth:      &TwitchAuth,
-	cache_arc: Arc<Mutex<NameIdCache>>,
-	cmd:       &CommandSource,
-	channel_specifics_arc: Arc<Mutex<ChannelSpecificsCache>>,
+	state: &AppState,
+	cmd:   &CommandSource,
 ) -> anyhow::Result<Option<String>> {
 	if !cmd.sender.is_mvb() {
 		return Ok(Some("❌ requires MVB privileges | E4".into()))
@@ -1321,14 +1945,7 @@ pub async fn demultiplex(
 
 	let mut final_output = String::new();
 	for _ in 0..rounds {
-		let temp_out = handle_command(
-			pool,
-			client.clone(),
-			config,
-			auth, cache_arc.clone(),
-			new_cmd.clone(),
-			channel_specifics_arc.clone()
-		).await;
+		let temp_out = handle_command(state, new_cmd.clone()).await;
 
 		if let Some(o) = temp_out {
 			final_output.push(' ');
@@ -1441,39 +2058,16 @@ pub async fn get_chatstats(
 }
 
 async fn give_trivia_hint(
-	cmd:                      &CommandSource,
-	twitch_auth:              &TwitchAuth,
-	channel_specifics_arc: Arc<Mutex<ChannelSpecificsCache>>,
+	state: &AppState,
+	cmd:   &CommandSource,
 ) -> anyhow::Result<Option<String>> {
-	let channel_id = cmd.channel.id;
-
-	if let Ok(mut cache) = channel_specifics_arc.lock() {
-		if (*cache).get(&channel_id.to_string()).is_some() {
-			if (*cache).get(&channel_id.to_string()).unwrap().ongoing_trivia_game.is_some() {
-				
-				let trivia_info = (*cache)
-					.get(&channel_id.to_string())
-					.unwrap()
-					.ongoing_trivia_game
-					.clone();
-
-				if let Some(ti) = trivia_info {
-					let c = ti
-						.shuffled_answers()
-						.iter()
-						.map(|a| a.to_string())
-						.collect::<Vec<String>>()
-						.join("\", \"");
-
-					return Ok(Some(format!("The options are: \"{}\"", c)));
-				}
-			}
+	let channel = state.channel(&cmd.channel.id.to_string());
+	let mut channel = channel.lock().await;
 
-			return Ok(Some("❌ there is no game going on FeelsDankMan".into()));
-		}
+	match &mut channel.ongoing_trivia_game {
+		Some(ti) => Ok(Some(ti.options_hint())),
+		None     => Ok(Some("❌ there is no game going on FeelsDankMan".into())),
 	}
-	
-	Ok(Some("❌ an internal error has occured".into()))
 }
 
 // find when and where was specified user last seen
@@ -1496,30 +2090,17 @@ pub async fn find_last_seen(
 		}
 	};
 
-	let mut latest_timestamp: Option<DateTime<Utc>> = None;
-	let mut found_in_channel: Option<String>   = None;
+	let mut channels = vec![];
 	for channel_name in &config.channels {
-		let channel_id = api::id_from_nick(channel_name, twitch_auth).await?.unwrap();
-		let latest = db::latest_message_date(pool, channel_id, target_user_id).await?;
-
-		if let Some(ts) = latest {
-			if let Some(latest_ts) = latest_timestamp {
-				if ts > latest_ts {
-					latest_timestamp = Some(ts);
-					found_in_channel = Some(channel_name.clone());
-				}
-			} else {
-				latest_timestamp = Some(ts);
-				found_in_channel = Some(channel_name.clone());
-			}
-			
-		}
+		let channel_id = api::id_from_nick(channel_name, twitch_auth).await?.ok_or(MyError::NotFound)?;
+
+		channels.push(crate::Channel { id: channel_id, name: channel_name.clone() });
 	}
 
-	match latest_timestamp {
-		Some(tm) => {
+	match db::last_seen(pool, &channels, target_user_id).await? {
+		Some((channel, tm)) => {
 			let duration = fmt_duration(Utc::now() - tm, false);
-			Ok(Some(format!("⌛ {target_user_name} was last seen {duration} in {}", found_in_channel.unwrap())))
+			Ok(Some(format!("⌛ {target_user_name} was last seen {duration} in {}", channel.name)))
 		},
 		None     => Ok(Some(format!("❌ {target_user_name} not found in records"))),
 	}
@@ -1556,18 +2137,18 @@ async fn pyramid(
 		msg.push_str(emote);
 		msg.push(' ');
 
-		client.say(cmd.channel.name.to_owned(), msg.clone()).await.unwrap();
+		client.say(cmd.channel.name.to_owned(), fit_message(msg.clone())).await?;
 		// this is a very dirty workaround
 		// TODO: fix this when ChannelSpecifics has info
 		// about whether the bot is a mod or not
-		std::thread::sleep(std::time::Duration::from_secs(2));
+		tokio::time::sleep(std::time::Duration::from_secs(2)).await;
 	}
 
 	let mut msg_end_idx = msg.len();
 	for _ in 0..len {
 		msg_end_idx -= emote.len() + 1;
-		client.say(cmd.channel.name.to_owned(), msg[..msg_end_idx].to_owned()).await.unwrap();
-		std::thread::sleep(std::time::Duration::from_secs(2));
+		client.say(cmd.channel.name.to_owned(), fit_message(msg[..msg_end_idx].to_owned())).await?;
+		tokio::time::sleep(std::time::Duration::from_secs(2)).await;
 	}
 
 	Ok(None)
@@ -1,12 +1,18 @@
-use crate::{MyError, EmoteCache, CommandSource};
+use crate::{MyError, EmoteCache, CommandSource, TriviaGameInfo};
+use crate::metrics::metrics;
 
+use std::collections::HashMap;
 use std::str::FromStr;
-use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use rand::{self, Rng};
 use chrono::{offset::TimeZone, DateTime, Utc};
 use sqlx::sqlite::SqlitePool;
 use sqlx::{Sqlite};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info};
 use twitch_irc::message::PrivmsgMessage;
 
 
@@ -21,15 +27,12 @@ struct I32QR(i32);
 pub struct I32I32QR(pub i32, pub i32);
 
 #[derive(sqlx::FromRow)]
-struct DateTimeQR(DateTime<Utc>);
+struct I32StringQR(i32, String);
 
 #[derive(sqlx::FromRow)]
-struct ChannelCommandQR(String, String, i32);
-
-#[derive(sqlx::FromRow, Debug)]
-pub struct StringStringStringQR(String, String, String);
+struct DateTimeQR(DateTime<Utc>);
 
-#[derive(sqlx::FromRow, Debug)]
+#[derive(sqlx::FromRow, serde::Serialize, Debug)]
 pub struct Reminder {
 	pub id: i32,
     pub from_user_id: i32,
@@ -47,10 +50,46 @@ pub async fn init_db(
 	sqlx::query::<Sqlite>(sql)
 		.execute(&mut *conn)
 		.await?;
+
+	drop(conn);
+	migrate_trivia_games_table(pool).await?;
 	
 	Ok(())
 }
 
+// add the columns of trivia rounds to the saved games of an older database
+async fn migrate_trivia_games_table(
+	pool: &SqlitePool,
+) -> anyhow::Result<()> {
+	let mut tx = pool.begin().await?;
+
+	let sql = r#"
+		SELECT COUNT(*)
+			FROM pragma_table_info('ongoing_trivia_games')
+			WHERE
+				name='round';
+	"#;
+
+	let is_migrated = sqlx::query_as::<Sqlite, I32QR>(sql)
+		.fetch_one(&mut tx)
+		.await?
+		.0 > 0;
+
+	if is_migrated {
+		return Ok(());
+	}
+
+	info!("Adding trivia rounds to the saved trivia games");
+
+	sqlx::query::<Sqlite>(include_str!("../assets/sql/migrations/trivia_rounds.sql"))
+		.execute(&mut tx)
+		.await?;
+
+	tx.commit().await?;
+
+	Ok(())
+}
+
 // create table for current set channel (if it does not exist)
 pub async fn try_create_tables_for_channel(
     pool:       &SqlitePool,
@@ -64,83 +103,520 @@ pub async fn try_create_tables_for_channel(
 		.execute(&mut *conn)
 		.await?;
 
+	drop(conn);
+	compact_markov_table(pool, channel_id).await?;
+	migrate_hooks_table(pool, channel_id).await?;
+	migrate_hook_counts(pool, channel_id).await?;
+	migrate_commands_table(pool, channel_id).await?;
+	pick_up_user_names_from_logs(pool, channel_id).await?;
+	hash_logged_messages(pool, channel_id).await?;
+
 	Ok(())
 }
 
-// save incoming messages to db
-pub async fn log(
-    pool: &SqlitePool,
-    privmsg: &twitch_irc::message::PrivmsgMessage,
+// how many logged messages get hashed at a time by the migration
+const HASH_BATCH_SIZE: i64 = 10_000;
+
+// what a message is looked up by when checking whether a markov chain
+// is a copy of it; case-insensitive like `COLLATE NOCASE` (64-bit FNV-1a,
+// so that it stays the same across builds)
+pub fn message_hash(message: &str) -> i64 {
+	let hash = message
+		.bytes()
+		.map(|byte| byte.to_ascii_lowercase())
+		.fold(0xcbf29ce484222325_u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3));
+
+	hash as i64
+}
+
+// add the hashes of the messages to a log from before they were looked up
+// by them; the first start after this hashes the whole log, which takes a while
+async fn hash_logged_messages(
+	pool:       &SqlitePool,
+	channel_id: i32,
 ) -> anyhow::Result<()> {
-	let mut conn = pool.acquire().await?;
-	let channel_id = privmsg.channel_id.parse::<i32>().unwrap();
+	let mut tx = pool.begin().await?;
 
-    let sql = r#"
-    INSERT
-        INTO CHANNEL_{{ CHANNEL_ID }} 
-	        (sender_id, sender_nick, badges, timestamp, message)
-        VALUES
-	        (?1, ?2, ?3, ?4, ?5)
-    "#.replace("{{ CHANNEL_ID }}", &channel_id.to_string());
+	let sql = r#"
+		SELECT COUNT(*)
+			FROM pragma_table_info('CHANNEL_{{ CHANNEL_ID }}')
+			WHERE
+				name='message_hash';
+	"#.replace("{{ CHANNEL_ID }}", &channel_id.to_string());
+
+	let is_migrated = sqlx::query_as::<Sqlite, I32QR>(&sql)
+		.fetch_one(&mut tx)
+		.await?
+		.0 > 0;
+
+	if is_migrated {
+		return Ok(());
+	}
+
+	info!("Hashing the logged messages of channel {channel_id} (only this once, it may take a while)");
+
+	let sql = include_str!("../assets/sql/migrations/message_hashes.sql")
+		.replace("{{ CHANNEL_ID }}", &channel_id.to_string());
 
 	sqlx::query::<Sqlite>(&sql)
-		.bind(&privmsg.sender.id)
-		.bind(&privmsg.sender.name)
-		.bind(&privmsg.badges.iter().map(|badge| badge.name.clone()).collect::<Vec<String>>().join(" "))
-		// here, the datetime was previously unformatted ... pain 
-		.bind(&privmsg.server_timestamp.format("%Y-%m-%d %H:%M:%S").to_string())
-		.bind(&privmsg.message_text)
-		.execute(&mut *conn)
+		.execute(&mut tx)
+		.await?;
+
+	let select = r#"
+		SELECT id, message
+			FROM CHANNEL_{{ CHANNEL_ID }}
+			WHERE
+				id > ?1 AND
+				message IS NOT NULL
+			ORDER BY id
+			LIMIT ?2;
+	"#.replace("{{ CHANNEL_ID }}", &channel_id.to_string());
+	let update = "UPDATE CHANNEL_{{ CHANNEL_ID }} SET message_hash=?1 WHERE id=?2;"
+		.replace("{{ CHANNEL_ID }}", &channel_id.to_string());
+
+	let mut last_id = 0;
+	loop {
+		let messages = sqlx::query_as::<Sqlite, (i64, String)>(&select)
+			.bind(last_id)
+			.bind(HASH_BATCH_SIZE)
+			.fetch_all(&mut tx)
+			.await?;
+
+		last_id = match messages.last() {
+			Some((id, _)) => *id,
+			None          => break,
+		};
+
+		for (id, message) in &messages {
+			sqlx::query::<Sqlite>(&update)
+				.bind(message_hash(message))
+				.bind(id)
+				.execute(&mut tx)
+				.await?;
+		}
+	}
+
+	tx.commit().await?;
+
+	Ok(())
+}
+
+// the names users went by are only tracked since a while ago,
+// so pick up the ones before from the logs of the channel (once)
+async fn pick_up_user_names_from_logs(
+	pool:       &SqlitePool,
+	channel_id: i32,
+) -> anyhow::Result<()> {
+	let mut tx = pool.begin().await?;
+
+	let sql = r#"
+		SELECT COUNT(*)
+			FROM user_names_from_logs
+			WHERE
+				channel_id=?1;
+	"#;
+
+	let is_picked_up = sqlx::query_as::<Sqlite, I32QR>(sql)
+		.bind(channel_id)
+		.fetch_one(&mut tx)
+		.await?
+		.0 > 0;
+
+	if is_picked_up {
+		return Ok(());
+	}
+
+	info!("Picking up the names users went by from the logs of channel {channel_id}");
+
+	let sql = include_str!("../assets/sql/migrations/user_names_from_logs.sql")
+		.replace("{{ CHANNEL_ID }}", &channel_id.to_string());
+
+	sqlx::query::<Sqlite>(&sql)
+		.execute(&mut tx)
 		.await?;
 
+	tx.commit().await?;
+
 	Ok(())
 }
 
-// processes message for markov index table entries
-pub async fn log_markov(
-    pool: &SqlitePool,
-	emote_cache_arc: &Arc<Mutex<EmoteCache>>,
-    privmsg: &twitch_irc::message::PrivmsgMessage,
+// add the creators, usage counts, permissions and cooldowns
+// of commands to the commands table of an older database
+async fn migrate_commands_table(
+	pool:       &SqlitePool,
+	channel_id: i32,
 ) -> anyhow::Result<()> {
-	let mut conn = pool.acquire().await?;
+	let mut tx = pool.begin().await?;
+
+	let sql = r#"
+		SELECT COUNT(*)
+			FROM pragma_table_info('CHANNEL_{{ CHANNEL_ID }}_COMMANDS')
+			WHERE
+				name='uses';
+	"#.replace("{{ CHANNEL_ID }}", &channel_id.to_string());
 
-	let words = privmsg.message_text.split(' ').collect::<Vec<&str>>();
+	let is_migrated = sqlx::query_as::<Sqlite, I32QR>(&sql)
+		.fetch_one(&mut tx)
+		.await?
+		.0 > 0;
 
-	// this is kinda tacky but I couldn't get it working otherwise, so idk
-	let emote_cache = if let Ok(cache) = emote_cache_arc.lock() {
-		cache.clone()
-	} else {
+	if is_migrated {
 		return Ok(());
-	};
+	}
 
-	// process each word (besides the last one)
-	for idx in 0..words.len()-1 {
-		let word = match format_markov_entry(privmsg, &emote_cache, words[idx]) {
-			Ok(a) => a,
-			Err(_) => return Ok(()),
-		};
-		let succ = match format_markov_entry(privmsg, &emote_cache, words[idx + 1]) {
-			Ok(a) => a,
-			Err(_) => return Ok(()),
-		};
+	info!("Adding usage counts, permissions and cooldowns to the commands of channel {channel_id}");
+
+	let sql = include_str!("../assets/sql/migrations/commands_v2.sql")
+		.replace("{{ CHANNEL_ID }}", &channel_id.to_string());
+
+	sqlx::query::<Sqlite>(&sql)
+		.execute(&mut tx)
+		.await?;
+
+	tx.commit().await?;
+
+	Ok(())
+}
+
+// add the cooldowns, filters and switches of hooks
+// to the hooks table of an older database
+async fn migrate_hooks_table(
+	pool:       &SqlitePool,
+	channel_id: i32,
+) -> anyhow::Result<()> {
+	let mut tx = pool.begin().await?;
+
+	let sql = r#"
+		SELECT COUNT(*)
+			FROM pragma_table_info('CHANNEL_{{ CHANNEL_ID }}_HOOKS')
+			WHERE
+				name='enabled';
+	"#.replace("{{ CHANNEL_ID }}", &channel_id.to_string());
+
+	let is_migrated = sqlx::query_as::<Sqlite, I32QR>(&sql)
+		.fetch_one(&mut tx)
+		.await?
+		.0 > 0;
+
+	if is_migrated {
+		return Ok(());
+	}
 
-		if let (Some(w), Some(s)) = (word, succ) {
-			let sql = r#"
-				INSERT 
-					INTO CHANNEL_{{ CHANNEL_ID }}_MARKOV
-						(word, succ)
+	info!("Adding cooldowns and filters to the hooks of channel {channel_id}");
+
+	let sql = include_str!("../assets/sql/migrations/hooks_v2.sql")
+		.replace("{{ CHANNEL_ID }}", &channel_id.to_string());
+
+	sqlx::query::<Sqlite>(&sql)
+		.execute(&mut tx)
+		.await?;
+
+	tx.commit().await?;
+
+	Ok(())
+}
+
+// bring a markov table from before the pairs were counted
+// (one row per occurence) up to date, if it is one
+async fn compact_markov_table(
+	pool:       &SqlitePool,
+	channel_id: i32,
+) -> anyhow::Result<()> {
+	let mut tx = pool.begin().await?;
+
+	let sql = r#"
+		SELECT COUNT(*)
+			FROM pragma_table_info('CHANNEL_{{ CHANNEL_ID }}_MARKOV')
+			WHERE
+				name='count';
+	"#.replace("{{ CHANNEL_ID }}", &channel_id.to_string());
+
+	let is_compact = sqlx::query_as::<Sqlite, I32QR>(&sql)
+		.fetch_one(&mut tx)
+		.await?
+		.0 > 0;
+
+	if is_compact {
+		return Ok(());
+	}
+
+	info!("Compacting the markov table of channel {channel_id}");
+
+	let sql = include_str!("../assets/sql/migrations/compact_markov.sql")
+		.replace("{{ CHANNEL_ID }}", &channel_id.to_string());
+
+	sqlx::query::<Sqlite>(&sql)
+		.execute(&mut tx)
+		.await?;
+
+	tx.commit().await?;
+
+	Ok(())
+}
+
+// add the counts of fires to the hooks table of an older database
+async fn migrate_hook_counts(
+	pool:       &SqlitePool,
+	channel_id: i32,
+) -> anyhow::Result<()> {
+	let mut tx = pool.begin().await?;
+
+	let sql = r#"
+		SELECT COUNT(*)
+			FROM pragma_table_info('CHANNEL_{{ CHANNEL_ID }}_HOOKS')
+			WHERE
+				name='count';
+	"#.replace("{{ CHANNEL_ID }}", &channel_id.to_string());
+
+	let is_migrated = sqlx::query_as::<Sqlite, I32QR>(&sql)
+		.fetch_one(&mut tx)
+		.await?
+		.0 > 0;
+
+	if is_migrated {
+		return Ok(());
+	}
+
+	info!("Adding counts to the hooks of channel {channel_id}");
+
+	let sql = include_str!("../assets/sql/migrations/hook_counts.sql")
+		.replace("{{ CHANNEL_ID }}", &channel_id.to_string());
+
+	sqlx::query::<Sqlite>(&sql)
+		.execute(&mut tx)
+		.await?;
+
+	tx.commit().await?;
+
+	Ok(())
+}
+
+// at most how many queued writes get committed in one transaction
+const LOG_BATCH_SIZE: usize = 256;
+// how long the log writer waits for more writes before committing
+const LOG_FLUSH_INTERVAL: Duration = Duration::from_millis(500);
+
+// a write waiting in the queue of the `LogWriter`
+#[derive(Debug)]
+pub enum LogEntry {
+	Message {
+		channel_id:  String,
+		sender_id:   String,
+		sender_nick: String,
+		badges:      String,
+		timestamp:   String,
+		message:     String,
+	},
+	MarkovPair {
+		channel_id: String,
+		word:       String,
+		succ:       String,
+	},
+}
+
+// chat logs and markov entries are not written right away;
+// they are queued up and written in batches by a background task,
+// so that handling a message never waits for the database
+#[derive(Clone)]
+pub struct LogWriter {
+	tx: UnboundedSender<LogQueueItem>,
+}
+
+enum LogQueueItem {
+	// (the entries of one message always get written together)
+	Entries(Vec<LogEntry>),
+	// write everything queued so far right away and report back
+	Flush(oneshot::Sender<()>),
+}
+
+impl LogWriter {
+	// start the background task doing the writes; it finishes
+	// (after writing everything queued) once all of the writers are dropped
+	pub fn spawn(pool: SqlitePool) -> (Self, JoinHandle<()>) {
+		let (tx, rx) = mpsc::unbounded_channel();
+		let handle = tokio::spawn(write_logs(pool, rx));
+
+		(Self { tx }, handle)
+	}
+
+	// queue an incoming message to be saved to db
+	pub fn log(&self, privmsg: &PrivmsgMessage) {
+		self.queue(vec![message_entry(privmsg)]);
+	}
+
+	// queue the markov index table entries of a message
+	pub fn log_markov(
+		&self,
+		emote_cache: &EmoteCache,
+		privmsg:     &PrivmsgMessage,
+		order:       usize,
+	) {
+		self.queue(markov_entries(emote_cache, privmsg, order));
+	}
+
+	// queue a message along with its markov index table entries,
+	// so that a rebuild of the index sees either both or neither
+	pub fn log_with_markov(
+		&self,
+		emote_cache: &EmoteCache,
+		privmsg:     &PrivmsgMessage,
+		order:       usize,
+	) {
+		let mut entries = vec![message_entry(privmsg)];
+		entries.extend(markov_entries(emote_cache, privmsg, order));
+
+		self.queue(entries);
+	}
+
+	// wait until everything queued so far is written
+	pub async fn flush(&self) {
+		let (done_tx, done_rx) = oneshot::channel();
+
+		if self.tx.send(LogQueueItem::Flush(done_tx)).is_ok() {
+			done_rx.await.ok();
+		}
+	}
+
+	fn queue(&self, entries: Vec<LogEntry>) {
+		let count = entries.len() as i64;
+		metrics().log_queue_depth.add(count);
+
+		// this only fails when the writing task is gone already
+		if self.tx.send(LogQueueItem::Entries(entries)).is_err() {
+			metrics().log_queue_depth.sub(count);
+			error!("Log writer is not running, dropping a write");
+		}
+	}
+}
+
+fn message_entry(privmsg: &PrivmsgMessage) -> LogEntry {
+	LogEntry::Message {
+		channel_id:  privmsg.channel_id.clone(),
+		sender_id:   privmsg.sender.id.clone(),
+		sender_nick: privmsg.sender.name.clone(),
+		badges:      privmsg.badges.iter().map(|badge| badge.name.clone()).collect::<Vec<String>>().join(" "),
+		// here, the datetime was previously unformatted ... pain 
+		timestamp:   privmsg.server_timestamp.format("%Y-%m-%d %H:%M:%S").to_string(),
+		message:     privmsg.message_text.clone(),
+	}
+}
+
+fn markov_entries(
+	emote_cache: &EmoteCache,
+	privmsg:     &PrivmsgMessage,
+	order:       usize,
+) -> Vec<LogEntry> {
+	let tokens = crate::markov::tokens(privmsg, emote_cache);
+
+	crate::markov::transitions(&tokens, order)
+		.into_iter()
+		.map(|(word, succ)| LogEntry::MarkovPair {
+			channel_id: privmsg.channel_id.clone(),
+			word,
+			succ,
+		})
+		.collect()
+}
+
+async fn write_logs(
+	pool:   SqlitePool,
+	mut rx: UnboundedReceiver<LogQueueItem>,
+) {
+	while let Some(item) = rx.recv().await {
+		let mut batch = vec![];
+		let mut flushed = vec![];
+
+		match item {
+			LogQueueItem::Entries(entries) => batch.extend(entries),
+			LogQueueItem::Flush(done)      => flushed.push(done),
+		}
+
+		let flush_at = tokio::time::Instant::now() + LOG_FLUSH_INTERVAL;
+
+		// gather more writes until it is time to flush
+		// (or a flush is asked for, or the queue closes)
+		while flushed.is_empty() && batch.len() < LOG_BATCH_SIZE {
+			match tokio::time::timeout_at(flush_at, rx.recv()).await {
+				Ok(Some(LogQueueItem::Entries(entries))) => batch.extend(entries),
+				Ok(Some(LogQueueItem::Flush(done)))      => flushed.push(done),
+				_                                        => break,
+			}
+		}
+
+		metrics().log_queue_depth.sub(batch.len() as i64);
+
+		if !batch.is_empty() {
+			let timer = metrics().db_write_duration.start_timer();
+			let res = write_log_batch(&pool, &batch).await;
+			timer.observe_duration();
+
+			match res {
+				Ok(_)  => debug!(entries = batch.len(), "Wrote log batch"),
+				Err(e) => error!("Couldn't write {} log entries; err: {e}", batch.len()),
+			}
+		}
+
+		for done in flushed {
+			done.send(()).ok();
+		}
+	}
+}
+
+// write a batch of entries in a single transaction
+pub async fn write_log_batch(
+	pool:  &SqlitePool,
+	batch: &[LogEntry],
+) -> anyhow::Result<()> {
+	let mut tx = pool.begin().await?;
+
+	for entry in batch {
+		let res = match entry {
+			LogEntry::Message { channel_id, sender_id, sender_nick, badges, timestamp, message } => {
+				let sql = r#"
+				INSERT
+					INTO CHANNEL_{{ CHANNEL_ID }} 
+						(sender_id, sender_nick, badges, timestamp, message, message_hash)
 					VALUES
-						($1, $2);
-			"#.replace("{{ CHANNEL_ID }}", &privmsg.channel_id);
-				
-			sqlx::query::<Sqlite>(&sql)
-				.bind(w)
-				.bind(s)
-				.execute(&mut *conn)
-				.await?;
+						(?1, ?2, ?3, ?4, ?5, ?6)
+				"#.replace("{{ CHANNEL_ID }}", channel_id);
+
+				sqlx::query::<Sqlite>(&sql)
+					.bind(sender_id)
+					.bind(sender_nick)
+					.bind(badges)
+					.bind(timestamp)
+					.bind(message)
+					.bind(message_hash(message))
+					.execute(&mut tx)
+					.await
+			},
+			LogEntry::MarkovPair { channel_id, word, succ } => {
+				let sql = r#"
+					INSERT 
+						INTO CHANNEL_{{ CHANNEL_ID }}_MARKOV
+							(word, succ)
+						VALUES
+							($1, $2)
+						ON CONFLICT (word, succ) DO UPDATE
+							SET count = count + 1;
+				"#.replace("{{ CHANNEL_ID }}", channel_id);
+
+				sqlx::query::<Sqlite>(&sql)
+					.bind(word)
+					.bind(succ)
+					.execute(&mut tx)
+					.await
+			},
+		};
+
+		// one bad entry shouldn't cost the rest of the batch
+		if let Err(e) = res {
+			error!("Couldn't write log entry {entry:?}; err: {e}");
 		}
 	}
 
+	tx.commit().await?;
+
 	Ok(())
 }
 
@@ -190,72 +666,6 @@ pub async fn check_for_reminders(
 }
 
 
-// format the words parsed from the message into format
-// acceptible for the db entry
-fn format_markov_entry(
-	privmsg: &PrivmsgMessage,
-	emote_cache: &EmoteCache,
-	s: &str,
-) -> anyhow::Result<Option<String>> {
-    let mut out = s.to_owned();
-    let invalid_front_chars = vec![
-		'"',
-		'\'',
-		'«',
-		'「',
-		'“',
-		'‘',
-		'(',
-		'[',
-		'{',
-		',',
-		'.',
-		';',
-		' ',
-		'⠀' // this is a "blank" braille character
-	];
-    let invalid_back_chars = vec![
-		'"',
-		'\'',
-		'»',
-		'」',
-		'”',
-		'’',
-		')',
-		']',
-		'}',
-		',',
-		'.',
-		';',
-		' ', // space
-		'⠀', // this is a "blank" braille character
-		'!',
-		'?'
-	];
-    // the invisible braille char
-
-    // shave off all trailing unwanted chars
-    while invalid_front_chars.contains(&out.chars().next().ok_or(MyError::OutOfBounds)?) {
-        out.remove(0);
-    }
-
-    while invalid_back_chars.contains(&out.chars().last().ok_or(MyError::OutOfBounds)?) {
-        out.pop();
-    }
-
-    // if there is still the blank braille's or it is a link
-	// don't remove anything; else remove the formatted word
-    if out.contains('⠀') || out.contains("//") || out.contains("www.") || out == *"" { 
-        Ok(None)
-    } else {
-		match emote_cache.self_or_privmsg_has_emote(privmsg, &out) {
-			true  => Ok(Some(out)),
-			false => Ok(Some(out.to_lowercase()))
-		}
-        
-    }
-}
-
 pub async fn clear_users_sent_reminders(
 	pool: &SqlitePool,
 	user_id: i32,
@@ -279,38 +689,56 @@ pub async fn clear_users_sent_reminders(
 		Ok(num_affected)
 	} 
 
-// get a random successor of specified word from the markov index table
+// get a random successor of specified word from the markov index table,
+// the more often a pair has been seen, the likelier it is to be picked
 pub async fn get_rand_markov_succ(
-	pool:       &SqlitePool,
-	channel_id: i32,
-	word:       &str
+	pool:        &SqlitePool,
+	channel_ids: &[i32],
+	word:        &str
 ) -> anyhow::Result<Option<String>> {
+	if channel_ids.is_empty() {
+		return Ok(None);
+	}
+
 	let mut conn = pool.acquire().await?;
 
+	// the successors from every channel, as if it was all a single index
+	let pairs = channel_ids
+		.iter()
+		.map(|id| format!("SELECT succ, count FROM CHANNEL_{id}_MARKOV WHERE word=$1 COLLATE NOCASE"))
+		.collect::<Vec<_>>()
+		.join(" UNION ALL ");
+
+	// walk the successors, summing up their counts,
+	// and take the first one where the sum passes a random point
 	let sql = r#"
+		WITH pairs AS (
+			{{ PAIRS }}
+		), succs AS (
+			SELECT
+				succ,
+				SUM(count) OVER (ORDER BY succ ROWS UNBOUNDED PRECEDING) AS running_total,
+				SUM(count) OVER ()                                        AS total
+			FROM pairs
+		)
 		SELECT succ
-			FROM CHANNEL_{{ CHANNEL_ID }}_MARKOV
+			FROM succs
 			WHERE
-				word=$1
-			COLLATE NOCASE;
-	"#.replace("{{ CHANNEL_ID }}", &channel_id.to_string());
+				running_total > $2 * total
+			ORDER BY
+				running_total ASC
+			LIMIT 1;
+	"#.replace("{{ PAIRS }}", &pairs);
 
+	let point: f64 = rand::thread_rng().gen();
 
-	let succs: Vec<String> = sqlx::query_as::<Sqlite, StringQR>(&sql)
+	let succ = sqlx::query_as::<Sqlite, StringQR>(&sql)
 		.bind(word)
-		.fetch_all(&mut *conn)
-		.await?
-		.iter()
-		.map(|succ| succ.0.clone())
-		.collect();
-	
-	if succs.is_empty() {
-		return Ok(None);
-	}
-
-	let rand_succ = succs[rand::thread_rng().gen_range(0..succs.len())].clone();
+		.bind(point)
+		.fetch_optional(&mut *conn)
+		.await?;
 
-	Ok(rand_succ.into())
+	Ok(succ.map(|s| s.0))
 }
 
 // insert a reminder for a user
@@ -676,91 +1104,230 @@ pub async fn get_offline_time(
 	}
 }
 
+// save a command, or change the type and expression of the one of the same name
+// (keeping its creator, counts and limits); returns whether it's a new one
 pub async fn set_cmd(
 	pool: &SqlitePool,
 	channel_id: i32,
 	cmd_name:   &str,
 	cmd_type:   &str,
-	cmd_expr:   &str
-) -> anyhow::Result<()> {
-	let mut conn = pool.acquire().await?;
+	cmd_expr:   &str,
+	creator:    &str,
+	timestamp:  DateTime<Utc>,
+) -> anyhow::Result<bool> {
+	let mut tx = pool.begin().await?;
+
+	let sql = r#"
+		SELECT COUNT(*)
+			FROM CHANNEL_{{ CHANNEL_ID }}_COMMANDS
+			WHERE
+				name=?1;
+	"#.replace("{{ CHANNEL_ID }}", &channel_id.to_string());
+
+	let is_new = sqlx::query_as::<Sqlite, I32QR>(&sql)
+		.bind(cmd_name)
+		.fetch_one(&mut tx)
+		.await?
+		.0 == 0;
 
     let sql = r#"
-    INSERT OR REPLACE
+    INSERT
         INTO CHANNEL_{{ CHANNEL_ID }}_COMMANDS
-	        (name, type, expression)
+	        (name, type, expression, creator, created_at)
         VALUES
-	        (?1, ?2, ?3)
+	        (?1, ?2, ?3, ?4, ?5)
+        ON CONFLICT (name) DO UPDATE
+            SET
+                type=excluded.type,
+                expression=excluded.expression
     "#.replace("{{ CHANNEL_ID }}", &channel_id.to_string());
 
 	sqlx::query::<Sqlite>(&sql)
 		.bind(cmd_name)
 		.bind(cmd_type)
 		.bind(cmd_expr)
-		.execute(&mut *conn)
+		.bind(creator)
+		.bind(timestamp)
+		.execute(&mut tx)
 		.await?;
+
+	tx.commit().await?;
 	
-	Ok(())
+	Ok(is_new)
+}
+
+// set who may use a command and how often, returns whether there is one of that name
+pub async fn set_cmd_limits(
+	pool:       &SqlitePool,
+	channel_id: i32,
+	cmd_name:   &str,
+	permission: crate::CommandPermission,
+	cooldown_s: u64,
+) -> anyhow::Result<bool> {
+	let mut conn = pool.acquire().await?;
+
+	let sql = r#"
+		UPDATE CHANNEL_{{ CHANNEL_ID }}_COMMANDS
+			SET
+				permission=?2,
+				cooldown_s=?3
+			WHERE
+				name=?1;
+	"#.replace("{{ CHANNEL_ID }}", &channel_id.to_string());
+
+	let res = sqlx::query::<Sqlite>(&sql)
+		.bind(cmd_name)
+		.bind(permission.name())
+		.bind(cooldown_s as i64)
+		.execute(&mut *conn)
+		.await?;
+
+	Ok(res.rows_affected() > 0)
 }
 
+// save a hook, replacing the one of the same name (but keeping its count)
 pub async fn set_hook(
-	pool:           &SqlitePool,
-	channel_id:     i32,
-	name:           &str,
-	h_type:         &str,
-	capture_string: &str,
-	content:        &str,
+	pool:       &SqlitePool,
+	channel_id: i32,
+	hook:       &crate::MessageHook,
 ) -> anyhow::Result<()> {
 	let mut conn = pool.acquire().await?;
 
     let sql = r#"
-    INSERT OR REPLACE
+    INSERT
         INTO CHANNEL_{{ CHANNEL_ID }}_HOOKS
-	        (name, type, capture_string, content)
+	        (name, type, capture_string, content, cooldown_s, users, badges, enabled)
         VALUES
-	        (?1, ?2, ?3, ?4)
+	        (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+        ON CONFLICT (name) DO UPDATE
+            SET
+                type=excluded.type,
+                capture_string=excluded.capture_string,
+                content=excluded.content,
+                cooldown_s=excluded.cooldown_s,
+                users=excluded.users,
+                badges=excluded.badges,
+                enabled=excluded.enabled
     "#.replace("{{ CHANNEL_ID }}", &channel_id.to_string());
 
+	let badges = hook.badges
+		.iter()
+		.map(|b| b.name())
+		.collect::<Vec<_>>();
+
 	sqlx::query::<Sqlite>(&sql)
-		.bind(name)
-		.bind(h_type)
-		.bind(capture_string)
-		.bind(content)
+		.bind(&hook.name)
+		.bind(hook.h_type.to_string())
+		.bind(&hook.capture_string)
+		.bind(&hook.content)
+		.bind(hook.cooldown_s as i64)
+		.bind(hook.users.join(","))
+		.bind(badges.join(","))
+		.bind(hook.enabled)
 		.execute(&mut *conn)
 		.await?;
 	
 	Ok(())
 }
 
+// count that a hook of a channel has fired
+pub async fn count_hook_fire(
+	pool:       &SqlitePool,
+	channel_id: i32,
+	name:       &str,
+) -> anyhow::Result<()> {
+	let mut conn = pool.acquire().await?;
+
+	let sql = r#"
+		UPDATE CHANNEL_{{ CHANNEL_ID }}_HOOKS
+			SET
+				count=count + 1
+			WHERE
+				name=?1;
+	"#.replace("{{ CHANNEL_ID }}", &channel_id.to_string());
+
+	sqlx::query::<Sqlite>(&sql)
+		.bind(name)
+		.execute(&mut *conn)
+		.await?;
+
+	Ok(())
+}
+
+// turn a hook of a channel on or off, returns whether there is one of that name
+pub async fn set_hook_enabled(
+	pool:       &SqlitePool,
+	channel_id: i32,
+	name:       &str,
+	enabled:    bool,
+) -> anyhow::Result<bool> {
+	let mut conn = pool.acquire().await?;
+
+	let sql = r#"
+		UPDATE CHANNEL_{{ CHANNEL_ID }}_HOOKS
+			SET
+				enabled=?2
+			WHERE
+				name=?1;
+	"#.replace("{{ CHANNEL_ID }}", &channel_id.to_string());
+
+	let res = sqlx::query::<Sqlite>(&sql)
+		.bind(name)
+		.bind(enabled)
+		.execute(&mut *conn)
+		.await?;
+
+	Ok(res.rows_affected() > 0)
+}
+
 pub async fn get_channel_cmd(
     pool:       &SqlitePool,
     channel_id: i32,
     cmd_name:   &str,
-) -> anyhow::Result<Option<(String, String, i32)>> {
+) -> anyhow::Result<Option<ChannelCommand>> {
 	let mut conn = pool.acquire().await?;
 
 	let sql = r#"
-		UPDATE CHANNEL_{{ CHANNEL_ID }}_COMMANDS
-		SET
-			metadata = metadata + 1
-		WHERE
-			name=?1;
-		SELECT type, expression, metadata 
+		SELECT name, type, expression, metadata, uses, creator, created_at, permission, cooldown_s, last_used
 			FROM CHANNEL_{{ CHANNEL_ID }}_COMMANDS
 			WHERE
 				name=?1
 	"#.replace("{{ CHANNEL_ID }}", &channel_id.to_string());
 
-	let cmds: Vec<ChannelCommandQR> = sqlx::query_as::<Sqlite, ChannelCommandQR>(&sql)
+	let cmd = sqlx::query_as::<Sqlite, ChannelCommand>(&sql)
 		.bind(cmd_name)
-		.fetch_all(&mut *conn)
+		.fetch_optional(&mut *conn)
 		.await?;
 
-	if cmds.is_empty() {
-		Ok(None)
-	} else {
-		Ok(Some((cmds[0].0.clone(), cmds[0].1.clone(), cmds[0].2)))
-	}
+	Ok(cmd)
+}
+
+// count a use of a command (and what an `incr` one counts)
+pub async fn count_cmd_use(
+	pool:       &SqlitePool,
+	channel_id: i32,
+	cmd_name:   &str,
+	timestamp:  DateTime<Utc>,
+) -> anyhow::Result<()> {
+	let mut conn = pool.acquire().await?;
+
+	let sql = r#"
+		UPDATE CHANNEL_{{ CHANNEL_ID }}_COMMANDS
+			SET
+				uses=uses + 1,
+				metadata=IFNULL(metadata, 0) + (type = 'incr'),
+				last_used=?2
+			WHERE
+				name=?1;
+	"#.replace("{{ CHANNEL_ID }}", &channel_id.to_string());
+
+	sqlx::query::<Sqlite>(&sql)
+		.bind(cmd_name)
+		.bind(timestamp)
+		.execute(&mut *conn)
+		.await?;
+
+	Ok(())
 }
 
 pub async fn remove_channel_command(
@@ -969,7 +1536,8 @@ pub async fn get_channel_chat_stats(
 		ChatStatPeriod::Alltime     => "LIKE \"%\"".to_owned(),
 		ChatStatPeriod::ThisStream  => {
 			let stream_info = crate::api::get_stream_info(twitch_auth, &channel.name).await?.ok_or(MyError::NotFound)?;
-			let clause = format!(">= \"{}\"", stream_info.data[0].started_at);
+			let stream = stream_info.data.first().ok_or(MyError::NotFound)?;
+			let clause = format!(">= \"{}\"", stream.started_at);
 
 			clause
 		},
@@ -1053,37 +1621,1081 @@ pub async fn latest_message_date(
 	}
 }
 
+// find in which of the channels a user has chatted the most recently, and when
+pub async fn last_seen(
+	pool:           &SqlitePool,
+	channels:       &[crate::Channel],
+	target_user_id: i32,
+) -> anyhow::Result<Option<(crate::Channel, DateTime<Utc>)>> {
+	let mut latest: Option<(crate::Channel, DateTime<Utc>)> = None;
+
+	for channel in channels {
+		if let Some(ts) = latest_message_date(pool, channel.id, target_user_id).await? {
+			match &latest {
+				Some((_, latest_ts)) if *latest_ts >= ts => (),
+				_                                        => latest = Some((channel.clone(), ts)),
+			}
+		}
+	}
+
+	Ok(latest)
+}
+
+// get the hooks of a channel the way they run
+// (the ones that can't be set up anymore get skipped)
 pub async fn get_channel_hooks(
 	pool:           &SqlitePool,
 	channel_id:     i32,
-) -> anyhow::Result<Option<Vec<crate::MessageHook>>> {
+) -> anyhow::Result<Vec<crate::MessageHook>> {
+	let hooks = get_saved_hooks(pool, channel_id)
+		.await?
+		.into_iter()
+		.filter_map(|hook| {
+			let name = hook.name.clone();
+
+			hook.into_hook()
+				.map_err(|e| error!("Couldn't set up hook `{name}` of channel {channel_id}; err: {e}"))
+				.ok()
+		})
+		.collect();
+
+	Ok(hooks)
+}
+
+#[derive(sqlx::FromRow, Debug)]
+pub struct NameChange {
+	pub old_name:  String,
+	pub new_name:  String,
+	pub timestamp: DateTime<Utc>,
+}
+
+// remember the name a user is currently seen with;
+// if it differs from the one known before, the change
+// gets recorded and the previous name is returned
+pub async fn update_user_name(
+	pool:      &SqlitePool,
+	user_id:   i32,
+	user_name: &str,
+	timestamp: DateTime<Utc>,
+) -> anyhow::Result<Option<String>> {
 	let mut conn = pool.acquire().await?;
 
 	let sql = r#"
-		SELECT
-			type, capture_string, content
-		FROM
-			CHANNEL_{{ CHANNEL_ID }}_HOOKS
-		WHERE
-			type="hook";
-	"#.replace("{{ CHANNEL_ID }}", &channel_id.to_string());
+		SELECT user_name
+			FROM user_names
+			WHERE
+				user_id=?1;
+	"#;
 
-	let hooks = sqlx::query_as::<Sqlite, StringStringStringQR>(&sql)
+	let known_names = sqlx::query_as::<Sqlite, StringQR>(sql)
+		.bind(user_id)
 		.fetch_all(&mut *conn)
 		.await?;
-		
-	if hooks.is_empty() {
-		return Ok(None);
+
+	let previous_name = match known_names.first() {
+		Some(n) if n.0 != user_name => Some(n.0.clone()),
+		_                           => None,
+	};
+
+	if let Some(old_name) = &previous_name {
+		let sql = r#"
+			INSERT
+				INTO user_name_changes
+					(user_id, old_name, new_name, timestamp)
+				VALUES
+					(?1, ?2, ?3, ?4);
+		"#;
+
+		sqlx::query::<Sqlite>(sql)
+			.bind(user_id)
+			.bind(old_name)
+			.bind(user_name)
+			.bind(timestamp.format("%Y-%m-%d %H:%M:%S").to_string())
+			.execute(&mut *conn)
+			.await?;
 	}
 
-	let mut out = vec![];
-	for hook in hooks {
-		out.push(crate::MessageHook {
-			h_type:         crate::HookMatchType::from_str(&hook.0)?,
-			capture_string: hook.1,
-			content:        hook.2,
-		});
+	let sql = r#"
+		INSERT INTO
+			user_names
+				(user_id, user_name, last_seen)
+			VALUES
+				(?1, ?2, ?3)
+			ON CONFLICT (user_id)
+			DO UPDATE
+				SET user_name = ?2, last_seen = ?3;
+	"#;
+
+	sqlx::query::<Sqlite>(sql)
+		.bind(user_id)
+		.bind(user_name)
+		.bind(timestamp.format("%Y-%m-%d %H:%M:%S").to_string())
+		.execute(&mut *conn)
+		.await?;
+
+	Ok(previous_name)
+}
+
+// get all recorded name changes of a user (oldest first)
+pub async fn get_name_changes(
+	pool:    &SqlitePool,
+	user_id: i32,
+) -> anyhow::Result<Vec<NameChange>> {
+	let mut conn = pool.acquire().await?;
+
+	let sql = r#"
+		SELECT old_name, new_name, timestamp
+			FROM user_name_changes
+			WHERE
+				user_id=?1
+			ORDER BY
+				timestamp ASC;
+	"#;
+
+	let changes = sqlx::query_as::<Sqlite, NameChange>(sql)
+		.bind(user_id)
+		.fetch_all(&mut *conn)
+		.await?;
+
+	Ok(changes)
+}
+
+// find the (id, current name) of a user by a name
+// they have been seen with, be it the current or a previous one
+pub async fn user_from_known_name(
+	pool:      &SqlitePool,
+	user_name: &str,
+) -> anyhow::Result<Option<(i32, String)>> {
+	let mut conn = pool.acquire().await?;
+
+	let sql = r#"
+		SELECT user_id, user_name
+			FROM user_names
+			WHERE
+				user_name=?1 COLLATE NOCASE
+			OR
+				user_id IN (
+					SELECT user_id
+						FROM user_name_changes
+						WHERE
+							old_name=?1 COLLATE NOCASE
+				)
+			ORDER BY
+				(user_name=?1 COLLATE NOCASE) DESC,
+				last_seen DESC
+			LIMIT 1;
+	"#;
+
+	let users = sqlx::query_as::<Sqlite, I32StringQR>(sql)
+		.bind(user_name)
+		.fetch_all(&mut *conn)
+		.await?;
+
+	match users.first() {
+		Some(u) => Ok(Some((u.0, u.1.clone()))),
+		None    => Ok(None),
 	}
+}
+
+// save a trivia game that is going on as the bot shuts down,
+// so that it can be picked up again after a restart
+pub async fn save_trivia_game(
+	pool:       &SqlitePool,
+	channel_id: &str,
+	game:       &TriviaGameInfo,
+) -> anyhow::Result<()> {
+	let mut conn = pool.acquire().await?;
+
+	let sql = r#"
+		INSERT OR REPLACE
+			INTO ongoing_trivia_games
+				(channel_id, question, correct_answer, wrong_answers, difficulty, round)
+			VALUES
+				(?1, ?2, ?3, ?4, ?5, ?6);
+	"#;
+
+	sqlx::query::<Sqlite>(sql)
+		.bind(channel_id)
+		.bind(&game.question)
+		.bind(&game.correct_answer)
+		.bind(serde_json::to_string(&game.wrong_answers)?)
+		.bind(&game.difficulty)
+		.bind(serde_json::to_string(&game.round)?)
+		.execute(&mut *conn)
+		.await?;
+
+	Ok(())
+}
 
-	Ok(Some(out))
+#[derive(sqlx::FromRow)]
+struct SavedTriviaGame {
+	channel_id:     String,
+	question:       String,
+	correct_answer: String,
+	wrong_answers:  String,
+	difficulty:     String,
+	round:          Option<String>,
+}
+
+// load the trivia games saved on the last shutdown (and forget them)
+pub async fn take_saved_trivia_games(
+	pool: &SqlitePool,
+) -> anyhow::Result<Vec<(String, TriviaGameInfo)>> {
+	let mut conn = pool.acquire().await?;
+
+	let sql = r#"
+		DELETE
+			FROM ongoing_trivia_games
+			RETURNING channel_id, question, correct_answer, wrong_answers, difficulty, round;
+	"#;
+
+	let games = sqlx::query_as::<Sqlite, SavedTriviaGame>(sql)
+		.fetch_all(&mut *conn)
+		.await?
+		.into_iter()
+		.map(|g| {
+			// the time for answering starts over
+			let mut game = TriviaGameInfo::new(
+				g.question,
+				g.correct_answer,
+				serde_json::from_str(&g.wrong_answers)?,
+				g.difficulty,
+			);
+
+			// (games saved before there were rounds are a round of their own)
+			if let Some(round) = g.round {
+				game.round = serde_json::from_str(&round)?;
+			}
+
+			Ok((g.channel_id, game))
+		})
+		.collect::<anyhow::Result<Vec<_>>>()?;
+
+	Ok(games)
+}
+
+// a question from the local trivia question bank
+#[derive(sqlx::FromRow, Debug)]
+pub struct LocalTriviaQuestion {
+	pub category:       Option<String>,
+	pub difficulty:     String,
+	#[sqlx(rename = "type")]
+	pub question_type:  String,
+	pub question:       String,
+	pub correct_answer: String,
+	// a JSON array
+	pub wrong_answers:  String,
+}
+
+// save a question into the local question bank (channel 0 for every channel);
+// returns false if the channel has the same question already
+pub async fn add_trivia_question(
+	pool:       &SqlitePool,
+	channel_id: i32,
+	question:   &crate::api_models::TriviaQuestion,
+	added_by:   Option<i32>,
+) -> anyhow::Result<bool> {
+	let mut conn = pool.acquire().await?;
+
+	let sql = r#"
+		INSERT
+			INTO trivia_questions
+				(channel_id, category, difficulty, type, question, correct_answer, wrong_answers, added_by)
+			VALUES
+				(?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+			ON CONFLICT DO NOTHING;
+	"#;
+
+	let inserted = sqlx::query::<Sqlite>(sql)
+		.bind(channel_id)
+		.bind(Some(&question.category).filter(|c| !c.is_empty()))
+		.bind(&question.difficulty)
+		.bind(&question.type_field)
+		.bind(&question.question)
+		.bind(&question.correct_answer)
+		.bind(serde_json::to_string(&question.incorrect_answers)?)
+		.bind(added_by)
+		.execute(&mut *conn)
+		.await?
+		.rows_affected() > 0;
+
+	Ok(inserted)
+}
+
+// a random question from the local question bank for a channel
+// (either any that can be asked there, or only its own);
+// the filters are the opentdb names, `None` for any
+pub async fn get_random_trivia_question(
+	pool:       &SqlitePool,
+	channel_id: i32,
+	only_own:   bool,
+	category:   Option<&str>,
+	difficulty: Option<&str>,
+	q_type:     Option<&str>,
+) -> anyhow::Result<Option<LocalTriviaQuestion>> {
+	let mut conn = pool.acquire().await?;
+
+	let sql = r#"
+		SELECT category, difficulty, type, question, correct_answer, wrong_answers
+			FROM trivia_questions
+			WHERE
+				(channel_id=?1 OR (channel_id=0 AND NOT ?2))
+			AND (?3 IS NULL OR category=?3)
+			AND (?4 IS NULL OR difficulty=?4)
+			AND (?5 IS NULL OR type=?5)
+			ORDER BY RANDOM()
+			LIMIT 1;
+	"#;
+
+	let question = sqlx::query_as::<Sqlite, LocalTriviaQuestion>(sql)
+		.bind(channel_id)
+		.bind(only_own)
+		.bind(category)
+		.bind(difficulty)
+		.bind(q_type)
+		.fetch_optional(&mut *conn)
+		.await?;
+
+	Ok(question)
+}
+
+// the trivia points of a user in a channel
+#[derive(sqlx::FromRow, Debug, PartialEq)]
+pub struct TriviaScore {
+	pub user_name: String,
+	pub points:    i32,
+	pub answers:   i32,
+	pub rank:      i32,
+}
+
+// add points for a correctly answered trivia question
+pub async fn add_trivia_points(
+	pool:       &SqlitePool,
+	channel_id: i32,
+	user_id:    i32,
+	user_name:  &str,
+	points:     u32,
+) -> anyhow::Result<()> {
+	let mut conn = pool.acquire().await?;
+
+	let sql = r#"
+		INSERT
+			INTO trivia_scores
+				(channel_id, user_id, user_name, points, answers)
+			VALUES
+				(?1, ?2, ?3, ?4, 1)
+			ON CONFLICT (channel_id, user_id) DO UPDATE
+				SET
+					user_name = excluded.user_name,
+					points    = points + excluded.points,
+					answers   = answers + 1;
+	"#;
+
+	sqlx::query::<Sqlite>(sql)
+		.bind(channel_id)
+		.bind(user_id)
+		.bind(user_name)
+		.bind(points)
+		.execute(&mut *conn)
+		.await?;
+
+	Ok(())
+}
+
+const TRIVIA_RANKING: &str = r#"
+	SELECT
+		user_id,
+		user_name,
+		points,
+		answers,
+		RANK() OVER (ORDER BY points DESC) AS rank
+	FROM trivia_scores
+	WHERE
+		channel_id=?1
+"#;
+
+// the users with the most trivia points in a channel
+pub async fn get_trivia_leaderboard(
+	pool:       &SqlitePool,
+	channel_id: i32,
+	limit:      i64,
+) -> anyhow::Result<Vec<TriviaScore>> {
+	let mut conn = pool.acquire().await?;
+
+	let sql = format!("{TRIVIA_RANKING} ORDER BY rank, user_name LIMIT ?2;");
+
+	let scores = sqlx::query_as::<Sqlite, TriviaScore>(&sql)
+		.bind(channel_id)
+		.bind(limit)
+		.fetch_all(&mut *conn)
+		.await?;
+
+	Ok(scores)
+}
+
+// the trivia points of a user in a channel (if they've got any)
+pub async fn get_trivia_score(
+	pool:       &SqlitePool,
+	channel_id: i32,
+	user_id:    i32,
+) -> anyhow::Result<Option<TriviaScore>> {
+	let mut conn = pool.acquire().await?;
+
+	let sql = format!("SELECT * FROM ({TRIVIA_RANKING}) WHERE user_id=?2;");
+
+	let score = sqlx::query_as::<Sqlite, TriviaScore>(&sql)
+		.bind(channel_id)
+		.bind(user_id)
+		.fetch_optional(&mut *conn)
+		.await?;
+
+	Ok(score)
+}
+
+// a message from the chat logs of a channel
+#[derive(sqlx::FromRow, serde::Serialize, Debug)]
+pub struct LoggedMessage {
+	pub id:          i64,
+	pub sender_id:   i32,
+	pub sender_nick: String,
+	pub badges:      Option<String>,
+	pub timestamp:   String,
+	pub message:     Option<String>,
+}
+
+// get (at most `limit`) logged messages of a channel, newest first,
+// older than the message with id `before` (if set)
+// and optionally only those sent by `sender_id`
+pub async fn get_channel_messages(
+	pool:       &SqlitePool,
+	channel_id: i32,
+	sender_id:  Option<i32>,
+	before:     Option<i64>,
+	limit:      i64,
+) -> anyhow::Result<Vec<LoggedMessage>> {
+	let mut conn = pool.acquire().await?;
+
+	let sql = r#"
+		SELECT id, sender_id, sender_nick, badges, timestamp, message
+			FROM CHANNEL_{{ CHANNEL_ID }}
+			WHERE
+				id < ?1
+			AND (?2 IS NULL OR sender_id = ?2)
+			ORDER BY
+				id DESC
+			LIMIT ?3;
+	"#.replace("{{ CHANNEL_ID }}", &channel_id.to_string());
+
+	let messages = sqlx::query_as::<Sqlite, LoggedMessage>(&sql)
+		.bind(before.unwrap_or(i64::MAX))
+		.bind(sender_id)
+		.bind(limit)
+		.fetch_all(&mut *conn)
+		.await?;
+
+	Ok(messages)
+}
+
+// get (at most `limit`) logged messages of a channel, oldest first,
+// newer than the message with id `after`
+pub async fn get_channel_messages_after(
+	pool:       &SqlitePool,
+	channel_id: i32,
+	after:      i64,
+	limit:      i64,
+) -> anyhow::Result<Vec<LoggedMessage>> {
+	let mut conn = pool.acquire().await?;
+
+	let sql = r#"
+		SELECT id, sender_id, sender_nick, badges, timestamp, message
+			FROM CHANNEL_{{ CHANNEL_ID }}
+			WHERE
+				id > ?1
+			ORDER BY
+				id ASC
+			LIMIT ?2;
+	"#.replace("{{ CHANNEL_ID }}", &channel_id.to_string());
+
+	let messages = sqlx::query_as::<Sqlite, LoggedMessage>(&sql)
+		.bind(after)
+		.bind(limit)
+		.fetch_all(&mut *conn)
+		.await?;
+
+	Ok(messages)
+}
+
+// how many messages of a channel are logged
+pub async fn count_channel_messages(
+	pool:       &SqlitePool,
+	channel_id: i32,
+) -> anyhow::Result<i32> {
+	let mut conn = pool.acquire().await?;
+
+	let sql = "SELECT COUNT(*) FROM CHANNEL_{{ CHANNEL_ID }};"
+		.replace("{{ CHANNEL_ID }}", &channel_id.to_string());
+
+	let count = sqlx::query_as::<Sqlite, I32QR>(&sql)
+		.fetch_one(&mut *conn)
+		.await?
+		.0;
+
+	Ok(count)
+}
+
+// how long a markov rebuild may go without writing anything before it's taken to have crashed
+const MARKOV_REBUILD_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10 * 60);
+
+// start over with an empty markov table for a channel next to the current one,
+// which keeps being used until `finish_markov_rebuild`; returns `false` (and does nothing)
+// if the channel is being rebuilt already (by this or another process)
+pub async fn start_markov_rebuild(
+	pool:       &SqlitePool,
+	channel_id: i32,
+) -> anyhow::Result<bool> {
+	let mut tx = pool.begin().await?;
+	let now = Utc::now();
+
+	// (a rebuild that stopped checking in has crashed)
+	let sql = r#"
+		DELETE
+			FROM markov_rebuilds
+			WHERE
+				channel_id=?1 AND
+				heartbeat < ?2;
+		INSERT
+			INTO markov_rebuilds
+				(channel_id, heartbeat)
+			VALUES
+				(?1, ?3)
+			ON CONFLICT DO NOTHING;
+	"#;
+
+	let started = sqlx::query::<Sqlite>(sql)
+		.bind(channel_id)
+		.bind(now - chrono::Duration::from_std(MARKOV_REBUILD_TIMEOUT)?)
+		.bind(now)
+		.execute(&mut tx)
+		.await?
+		.rows_affected() > 0;
+
+	if !started {
+		return Ok(false);
+	}
+
+	let sql = r#"
+		DROP TABLE IF EXISTS CHANNEL_{{ CHANNEL_ID }}_MARKOV_REBUILD;
+
+		CREATE TABLE CHANNEL_{{ CHANNEL_ID }}_MARKOV_REBUILD (
+			word  TEXT NOT NULL,
+			succ  TEXT NOT NULL,
+			count INTEGER NOT NULL DEFAULT 1,
+			PRIMARY KEY (word, succ)
+		);
+	"#.replace("{{ CHANNEL_ID }}", &channel_id.to_string());
+
+	sqlx::query::<Sqlite>(&sql)
+		.execute(&mut tx)
+		.await?;
+
+	tx.commit().await?;
+
+	Ok(true)
+}
+
+// add counted markov pairs to the table being rebuilt
+pub async fn write_markov_rebuild_batch(
+	pool:       &SqlitePool,
+	channel_id: i32,
+	pairs:      &HashMap<(String, String), i32>,
+) -> anyhow::Result<()> {
+	let mut tx = pool.begin().await?;
+
+	let sql = r#"
+		INSERT
+			INTO CHANNEL_{{ CHANNEL_ID }}_MARKOV_REBUILD
+				(word, succ, count)
+			VALUES
+				($1, $2, $3)
+			ON CONFLICT (word, succ) DO UPDATE
+				SET count = count + excluded.count;
+	"#.replace("{{ CHANNEL_ID }}", &channel_id.to_string());
+
+	for ((word, succ), count) in pairs {
+		sqlx::query::<Sqlite>(&sql)
+			.bind(word)
+			.bind(succ)
+			.bind(count)
+			.execute(&mut tx)
+			.await?;
+	}
+
+	sqlx::query::<Sqlite>("UPDATE markov_rebuilds SET heartbeat=?2 WHERE channel_id=?1;")
+		.bind(channel_id)
+		.bind(Utc::now())
+		.execute(&mut tx)
+		.await?;
+
+	tx.commit().await?;
+
+	Ok(())
+}
+
+// replace the markov table of a channel with the rebuilt one;
+// the messages logged since `last_id` (whose pairs went to the old table)
+// get counted by `count_pairs` and added first, all while nothing else can write
+pub async fn finish_markov_rebuild(
+	pool:        &SqlitePool,
+	channel_id:  i32,
+	last_id:     i64,
+	count_pairs: impl FnOnce(&[LoggedMessage]) -> HashMap<(String, String), i32>,
+) -> anyhow::Result<()> {
+	let mut tx = pool.begin().await?;
+
+	// (writing first takes the lock, so no messages can come in until the commit)
+	sqlx::query::<Sqlite>("UPDATE markov_rebuilds SET heartbeat=?2 WHERE channel_id=?1;")
+		.bind(channel_id)
+		.bind(Utc::now())
+		.execute(&mut tx)
+		.await?;
+
+	let sql = r#"
+		SELECT id, sender_id, sender_nick, badges, timestamp, message
+			FROM CHANNEL_{{ CHANNEL_ID }}
+			WHERE
+				id > ?1
+			ORDER BY
+				id ASC;
+	"#.replace("{{ CHANNEL_ID }}", &channel_id.to_string());
+
+	let messages = sqlx::query_as::<Sqlite, LoggedMessage>(&sql)
+		.bind(last_id)
+		.fetch_all(&mut tx)
+		.await?;
+
+	let sql = r#"
+		INSERT
+			INTO CHANNEL_{{ CHANNEL_ID }}_MARKOV_REBUILD
+				(word, succ, count)
+			VALUES
+				($1, $2, $3)
+			ON CONFLICT (word, succ) DO UPDATE
+				SET count = count + excluded.count;
+	"#.replace("{{ CHANNEL_ID }}", &channel_id.to_string());
+
+	for ((word, succ), count) in count_pairs(&messages) {
+		sqlx::query::<Sqlite>(&sql)
+			.bind(word)
+			.bind(succ)
+			.bind(count)
+			.execute(&mut tx)
+			.await?;
+	}
+
+	let sql = r#"
+		DELETE FROM markov_rebuilds WHERE channel_id={{ CHANNEL_ID }};
+
+		DROP TABLE CHANNEL_{{ CHANNEL_ID }}_MARKOV;
+
+		ALTER TABLE CHANNEL_{{ CHANNEL_ID }}_MARKOV_REBUILD
+			RENAME TO CHANNEL_{{ CHANNEL_ID }}_MARKOV;
+
+		CREATE INDEX IF NOT EXISTS CHANNEL_{{ CHANNEL_ID }}_MARKOV_WORD
+			ON CHANNEL_{{ CHANNEL_ID }}_MARKOV (word COLLATE NOCASE);
+	"#.replace("{{ CHANNEL_ID }}", &channel_id.to_string());
+
+	sqlx::query::<Sqlite>(&sql)
+		.execute(&mut tx)
+		.await?;
+
+	tx.commit().await?;
+
+	Ok(())
+}
+
+// give up on a rebuild of the markov table of a channel, keeping the current one
+pub async fn abort_markov_rebuild(
+	pool:       &SqlitePool,
+	channel_id: i32,
+) -> anyhow::Result<()> {
+	let mut conn = pool.acquire().await?;
+
+	let sql = r#"
+		DROP TABLE IF EXISTS CHANNEL_{{ CHANNEL_ID }}_MARKOV_REBUILD;
+		DELETE FROM markov_rebuilds WHERE channel_id={{ CHANNEL_ID }};
+	"#.replace("{{ CHANNEL_ID }}", &channel_id.to_string());
+
+	sqlx::query::<Sqlite>(&sql)
+		.execute(&mut *conn)
+		.await?;
+
+	Ok(())
+}
+
+// a command created with `setcmd`
+#[derive(sqlx::FromRow, serde::Serialize, Debug)]
+pub struct ChannelCommand {
+	pub name:       String,
+	#[sqlx(rename = "type")]
+	#[serde(rename = "type")]
+	pub cmd_type:   String,
+	pub expression: String,
+	// what an `incr` command counts
+	pub metadata:   i32,
+	pub uses:       i64,
+	pub creator:    String,
+	pub created_at: Option<DateTime<Utc>>,
+	pub permission: String,
+	pub cooldown_s: i64,
+	pub last_used:  Option<DateTime<Utc>>,
+}
+
+// get all of the commands of a channel
+pub async fn get_channel_commands(
+	pool:       &SqlitePool,
+	channel_id: i32,
+) -> anyhow::Result<Vec<ChannelCommand>> {
+	let mut conn = pool.acquire().await?;
+
+	let sql = r#"
+		SELECT name, type, expression, metadata, uses, creator, created_at, permission, cooldown_s, last_used
+			FROM CHANNEL_{{ CHANNEL_ID }}_COMMANDS
+			ORDER BY
+				name ASC;
+	"#.replace("{{ CHANNEL_ID }}", &channel_id.to_string());
+
+	let cmds = sqlx::query_as::<Sqlite, ChannelCommand>(&sql)
+		.fetch_all(&mut *conn)
+		.await?;
+
+	Ok(cmds)
+}
+
+// a hook as it is saved in the db
+#[derive(sqlx::FromRow, serde::Serialize, Debug)]
+pub struct SavedHook {
+	pub name:           String,
+	#[sqlx(rename = "type")]
+	#[serde(rename = "type")]
+	pub h_type:         String,
+	pub capture_string: String,
+	pub content:        String,
+	pub cooldown_s:     i64,
+	pub users:          String,
+	pub badges:         String,
+	pub enabled:        bool,
+	pub count:          i64,
+}
+
+impl SavedHook {
+	// the hook the way it runs
+	pub fn into_hook(self) -> anyhow::Result<crate::MessageHook> {
+		let mut hook = crate::MessageHook::new(
+			self.name,
+			crate::HookMatchType::from_str(&self.h_type)?,
+			self.capture_string,
+			self.content,
+		)?;
+
+		hook.cooldown_s = self.cooldown_s.max(0) as u64;
+		hook.users      = split_list(&self.users).map(str::to_owned).collect();
+		hook.badges     = split_list(&self.badges).map(crate::TwitchBadge::from_name).collect();
+		hook.enabled    = self.enabled;
+		hook.count      = self.count;
+
+		Ok(hook)
+	}
+}
+
+// the items of a list saved as "a,b,c"
+fn split_list(s: &str) -> impl Iterator<Item = &str> {
+	s.split(',').filter(|i| !i.is_empty())
+}
+
+// get all of the hooks of a channel, by their names
+pub async fn get_saved_hooks(
+	pool:       &SqlitePool,
+	channel_id: i32,
+) -> anyhow::Result<Vec<SavedHook>> {
+	let mut conn = pool.acquire().await?;
+
+	let sql = r#"
+		SELECT name, type, capture_string, content, cooldown_s, users, badges, enabled, count
+			FROM CHANNEL_{{ CHANNEL_ID }}_HOOKS
+			ORDER BY
+				name ASC;
+	"#.replace("{{ CHANNEL_ID }}", &channel_id.to_string());
+
+	let hooks = sqlx::query_as::<Sqlite, SavedHook>(&sql)
+		.fetch_all(&mut *conn)
+		.await?;
+
+	Ok(hooks)
+}
+
+// delete a hook of a channel, returns whether there was one
+pub async fn remove_hook(
+	pool:       &SqlitePool,
+	channel_id: i32,
+	name:       &str,
+) -> anyhow::Result<bool> {
+	let mut conn = pool.acquire().await?;
+
+	let sql = r#"
+		DELETE
+			FROM CHANNEL_{{ CHANNEL_ID }}_HOOKS
+			WHERE
+				name=?1;
+	"#.replace("{{ CHANNEL_ID }}", &channel_id.to_string());
+
+	let res = sqlx::query::<Sqlite>(&sql)
+		.bind(name)
+		.execute(&mut *conn)
+		.await?;
+
+	Ok(res.rows_affected() > 0)
+}
+
+// get (at most `limit`) pending reminders, newest first,
+// with an id lower than `before` (if set)
+pub async fn get_pending_reminders(
+	pool:   &SqlitePool,
+	before: Option<i64>,
+	limit:  i64,
+) -> anyhow::Result<Vec<Reminder>> {
+	let mut conn = pool.acquire().await?;
+
+	let sql = r#"
+		SELECT *
+			FROM user_reminders
+			WHERE
+				id < ?1
+			ORDER BY
+				id DESC
+			LIMIT ?2;
+	"#;
+
+	let reminders = sqlx::query_as::<Sqlite, Reminder>(sql)
+		.bind(before.unwrap_or(i64::MAX))
+		.bind(limit)
+		.fetch_all(&mut *conn)
+		.await?;
+
+	Ok(reminders)
+}
+
+// a command as it was logged by `log_command`
+#[derive(sqlx::FromRow, serde::Serialize, Debug)]
+pub struct CommandHistoryEntry {
+	pub id:               i64,
+	pub sender_id:        i32,
+	pub sender_name:      String,
+	pub command:          String,
+	pub args:             Option<String>,
+	pub execution_time_s: f64,
+	pub output:           Option<String>,
+	pub timestamp:        String,
+}
+
+// get (at most `limit`) logged commands, newest first,
+// older than the one with id `before` (if set)
+pub async fn get_command_history(
+	pool:   &SqlitePool,
+	before: Option<i64>,
+	limit:  i64,
+) -> anyhow::Result<Vec<CommandHistoryEntry>> {
+	let mut conn = pool.acquire().await?;
+
+	let sql = r#"
+		SELECT id, sender_id, sender_name, command, args, execution_time_s, output, timestamp
+			FROM command_history
+			WHERE
+				id < ?1
+			ORDER BY
+				id DESC
+			LIMIT ?2;
+	"#;
+
+	let history = sqlx::query_as::<Sqlite, CommandHistoryEntry>(sql)
+		.bind(before.unwrap_or(i64::MAX))
+		.bind(limit)
+		.fetch_all(&mut *conn)
+		.await?;
+
+	Ok(history)
+}
+
+// the name a user was last seen with
+pub async fn user_name_from_id(
+	pool:    &SqlitePool,
+	user_id: i32,
+) -> anyhow::Result<Option<String>> {
+	let mut conn = pool.acquire().await?;
+
+	let sql = r#"
+		SELECT user_name
+			FROM user_names
+			WHERE
+				user_id=?1;
+	"#;
+
+	let names = sqlx::query_as::<Sqlite, StringQR>(sql)
+		.bind(user_id)
+		.fetch_all(&mut *conn)
+		.await?;
+
+	Ok(names.into_iter().next().map(|n| n.0))
+}
+
+// whether a message with exactly this text was logged in a channel
+pub async fn message_was_logged(
+	pool:       &SqlitePool,
+	channel_id: i32,
+	message:    &str,
+) -> anyhow::Result<bool> {
+	let mut conn = pool.acquire().await?;
+
+	let sql = r#"
+		SELECT EXISTS (
+			SELECT 1
+				FROM CHANNEL_{{ CHANNEL_ID }}
+				WHERE
+					message_hash=?1 AND
+					message=?2
+				COLLATE NOCASE
+		);
+	"#.replace("{{ CHANNEL_ID }}", &channel_id.to_string());
+
+	let exists = sqlx::query_as::<Sqlite, I32QR>(&sql)
+		.bind(message_hash(message))
+		.bind(message)
+		.fetch_one(&mut *conn)
+		.await?
+		.0 == 1;
+
+	Ok(exists)
+}
+
+// the latest messages of a user in a channel, newest first
+pub async fn get_user_messages(
+	pool:       &SqlitePool,
+	channel_id: i32,
+	user_id:    i32,
+	limit:      i64,
+) -> anyhow::Result<Vec<String>> {
+	let mut conn = pool.acquire().await?;
+
+	let sql = r#"
+		SELECT message
+			FROM CHANNEL_{{ CHANNEL_ID }}
+			WHERE
+				sender_id=?1 AND
+				message IS NOT NULL
+			ORDER BY id DESC
+			LIMIT ?2;
+	"#.replace("{{ CHANNEL_ID }}", &channel_id.to_string());
+
+	let messages = sqlx::query_as::<Sqlite, StringQR>(&sql)
+		.bind(user_id)
+		.bind(limit)
+		.fetch_all(&mut *conn)
+		.await?
+		.into_iter()
+		.map(|m| m.0)
+		.collect();
+
+	Ok(messages)
+}
+
+// whether a user doesn't want markov chains imitating them
+pub async fn is_markov_opted_out(
+	pool:    &SqlitePool,
+	user_id: i32,
+) -> anyhow::Result<bool> {
+	let mut conn = pool.acquire().await?;
+
+	let sql = r#"
+		SELECT EXISTS (
+			SELECT 1
+				FROM markov_opt_outs
+				WHERE
+					user_id=?1
+		);
+	"#;
+
+	let opted_out = sqlx::query_as::<Sqlite, I32QR>(sql)
+		.bind(user_id)
+		.fetch_one(&mut *conn)
+		.await?
+		.0 == 1;
+
+	Ok(opted_out)
+}
+
+// opt a user out of (or back into) being imitated by markov chains
+pub async fn set_markov_opt_out(
+	pool:      &SqlitePool,
+	user_id:   i32,
+	opted_out: bool,
+) -> anyhow::Result<()> {
+	let mut conn = pool.acquire().await?;
+
+	let query = match opted_out {
+		true  => sqlx::query("INSERT INTO markov_opt_outs (user_id, timestamp) VALUES (?1, ?2) ON CONFLICT DO NOTHING;")
+			.bind(user_id)
+			.bind(Utc::now().format("%Y-%m-%d %H:%M:%S").to_string()),
+		false => sqlx::query("DELETE FROM markov_opt_outs WHERE user_id=?1;")
+			.bind(user_id),
+	};
+
+	query.execute(&mut *conn).await?;
+
+	Ok(())
+}
+
+// whether a channel lets other channels draw from its markov index,
+// if it has said so
+pub async fn get_markov_sharing(
+	pool:       &SqlitePool,
+	channel_id: i32,
+) -> anyhow::Result<Option<bool>> {
+	let mut conn = pool.acquire().await?;
+
+	let sql = r#"
+		SELECT shared
+			FROM markov_sharing
+			WHERE
+				channel_id=?1;
+	"#;
+
+	let shared = sqlx::query_as::<Sqlite, I32QR>(sql)
+		.bind(channel_id)
+		.fetch_optional(&mut *conn)
+		.await?
+		.map(|s| s.0 == 1);
+
+	Ok(shared)
+}
+
+// let (or don't let) other channels draw from the markov index of a channel
+pub async fn set_markov_sharing(
+	pool:       &SqlitePool,
+	channel_id: i32,
+	shared:     bool,
+) -> anyhow::Result<()> {
+	let mut conn = pool.acquire().await?;
+
+	let sql = r#"
+		INSERT INTO markov_sharing (channel_id, shared)
+			VALUES (?1, ?2)
+			ON CONFLICT (channel_id) DO UPDATE SET shared=?2;
+	"#;
+
+	sqlx::query(sql)
+		.bind(channel_id)
+		.bind(shared)
+		.execute(&mut *conn)
+		.await?;
+
+	Ok(())
 }
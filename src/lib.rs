@@ -4,18 +4,34 @@ pub mod api;
 pub mod api_models;
 pub mod background;
 pub mod constants;
+pub mod logging;
+pub mod markov;
+pub mod metrics;
+pub mod server;
+pub mod template;
+pub mod trivia;
 
 use std::{collections::HashMap, fs::read_to_string};
-use std::sync::{Arc, Mutex};
+use std::future::Future;
+use std::sync::{Arc, OnceLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::path::Path;
 use constants::*;
 
-use colored::*;
 use chrono::{DateTime, Utc};
+use dashmap::DashMap;
 use rand::seq::SliceRandom;
 use serde::{Serialize, Deserialize};
+use sqlx::sqlite::SqlitePool;
 use thiserror::Error;
+use tracing::{error, info, warn};
+use tokio::sync::{Mutex, Notify, RwLock};
+use twitch_irc::login::StaticLoginCredentials;
 use twitch_irc::message::PrivmsgMessage;
+use twitch_irc::{SecureTCPTransport, TwitchIRCClient};
+
+
+pub type TwitchClient = TwitchIRCClient<SecureTCPTransport, StaticLoginCredentials>;
 
 
 #[derive(Error, Debug)]
@@ -41,7 +57,7 @@ pub enum MyError {
 }
 
 // All the statuses one can have in Twitch chat
-#[derive(Clone, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum TwitchBadge {
 	Broadcaster,
 	Admin,
@@ -59,6 +75,81 @@ pub enum TwitchBadge {
 	Partner,
 }
 
+impl TwitchBadge {
+	// from the name Twitch uses for the badge (or a shorter one)
+	pub fn from_name(name: &str) -> Self {
+		match name.to_lowercase().as_str() {
+			"admin"                 => Self::Admin,
+			"broadcaster"           => Self::Broadcaster,
+			"global_mod"            => Self::GlobalMod,
+			"moderator" | "mod"     => Self::Mod,
+			"staff"                 => Self::Staff,
+			"subscriber" | "sub"    => Self::Subscriber,
+			"vip"                   => Self::Vip,
+			"premium"               => Self::Premium,
+			"glitchcon2020"         => Self::GlitchCon2020,
+			"glhf-pledge"           => Self::GLHFPledge,
+			"bits"                  => Self::Bits,
+			"bits-charity"          => Self::BitsCharity,
+			"partner"               => Self::Partner,
+			_                       => Self::Unrecognized,
+		}
+	}
+
+	// the name Twitch uses for the badge
+	pub fn name(&self) -> &'static str {
+		match self {
+			Self::Admin         => "admin",
+			Self::Broadcaster   => "broadcaster",
+			Self::GlobalMod     => "global_mod",
+			Self::Mod           => "moderator",
+			Self::Staff         => "staff",
+			Self::Subscriber    => "subscriber",
+			Self::Vip           => "vip",
+			Self::Premium       => "premium",
+			Self::GlitchCon2020 => "glitchcon2020",
+			Self::GLHFPledge    => "glhf-pledge",
+			Self::Bits          => "bits",
+			Self::BitsCharity   => "bits-charity",
+			Self::Partner       => "partner",
+			Self::Unrecognized  => "unrecognized",
+		}
+	}
+}
+
+// who may use a channel command, from the least to the most privileged
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum CommandPermission {
+	Everyone,
+	Subscriber,
+	Vip,
+	Mod,
+	Broadcaster,
+}
+
+impl CommandPermission {
+	pub fn from_name(name: &str) -> Option<Self> {
+		match name.to_lowercase().as_str() {
+			"everyone"              => Some(Self::Everyone),
+			"subscriber" | "sub"    => Some(Self::Subscriber),
+			"vip"                   => Some(Self::Vip),
+			"moderator" | "mod"     => Some(Self::Mod),
+			"broadcaster"           => Some(Self::Broadcaster),
+			_                       => None,
+		}
+	}
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			Self::Everyone    => "everyone",
+			Self::Subscriber  => "subscriber",
+			Self::Vip         => "vip",
+			Self::Mod         => "moderator",
+			Self::Broadcaster => "broadcaster",
+		}
+	}
+}
+
 // twitch authentification credentials
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct TwitchAuth {
@@ -88,6 +179,16 @@ pub struct Config {
 	pub index_markov:            bool,
 	pub track_offliners:         bool,
 	pub prefix:                  char,
+	#[serde(default)]
+	pub api_urls:                api::ApiUrls,
+	#[serde(default)]
+	pub logging:                 logging::LoggingConfig,
+	#[serde(default)]
+	pub server:                  server::ServerConfig,
+	#[serde(default)]
+	pub markov:                  markov::MarkovConfig,
+	#[serde(default)]
+	pub trivia:                  TriviaConfig,
 }
 
 impl Config {
@@ -115,6 +216,40 @@ pub struct Sender {
 }
 
 impl Sender {
+	// the sender of a chat message
+	pub fn from_privmsg(privmsg: &PrivmsgMessage) -> Self {
+		let statuses = privmsg.badges
+			.iter()
+			.map(|badge| match TwitchBadge::from_name(&badge.name) {
+				TwitchBadge::Unrecognized => {
+					warn!(badge = %badge.name, "Encountered unrecognized badge");
+
+					TwitchBadge::Unrecognized
+				},
+				badge => badge,
+			})
+			.collect();
+
+		Self {
+			id:   privmsg.sender.id.parse::<i32>().unwrap(),
+			name: privmsg.sender.name.clone(),
+			statuses,
+		}
+	}
+
+	// the most that a user's badges let them use
+	pub fn permission(&self) -> CommandPermission {
+		[
+			(TwitchBadge::Broadcaster, CommandPermission::Broadcaster),
+			(TwitchBadge::Mod,         CommandPermission::Mod),
+			(TwitchBadge::Vip,         CommandPermission::Vip),
+			(TwitchBadge::Subscriber,  CommandPermission::Subscriber),
+		]
+			.into_iter()
+			.find(|(badge, _)| self.statuses.contains(badge))
+			.map_or(CommandPermission::Everyone, |(_, permission)| permission)
+	}
+
 	// checks whether a certain user is either mod/vip/broadcaster 
 	pub fn is_mvb(&self) -> bool {
 		self.statuses.contains(&TwitchBadge::Vip) ||
@@ -124,7 +259,7 @@ impl Sender {
 }
 
 // the channel a message is posted in
-#[derive(Clone)]
+#[derive(Clone, Serialize)]
 pub struct Channel {
 	pub id: i32,
 	pub name: String,
@@ -152,40 +287,7 @@ impl CommandSource {
 		let cmd = args[0].to_lowercase()[1..].to_owned();
 		args = args[1..].to_owned();
 
-		// parse badges
-		let badges: Vec<TwitchBadge> = privmsg.badges
-			.into_iter()
-			.map(|badge| match badge.name.as_str() {
-				"admin"         => TwitchBadge::Admin,
-				"broadcaster"   => TwitchBadge::Broadcaster,
-				"global_mod"    => TwitchBadge::GlobalMod,
-				"moderator"     => TwitchBadge::Mod,
-				"staff"         => TwitchBadge::Staff,
-				"subscriber"    => TwitchBadge::Subscriber,
-				"vip"           => TwitchBadge::Vip,
-				"premium"       => TwitchBadge::Premium,
-				"glitchcon2020" => TwitchBadge::GlitchCon2020,
-				"glhf-pledge"   => TwitchBadge::GLHFPledge,
-				"bits"          => TwitchBadge::Bits,
-				"bits-charity"  => TwitchBadge::BitsCharity,
-				"partner"       => TwitchBadge::Partner,
-				_ => {
-					println!(
-						"{} Encountered unrecognized badge: {}",
-						"WARN   ".bright_red().bold(),
-						badge.name.bold()
-					);
-					
-					TwitchBadge::Unrecognized
-				}
-		})
-		.collect();
-
-		let sender = Sender {
-			id: privmsg.sender.id.parse::<i32>().unwrap(),
-			name: privmsg.sender.name,
-			statuses: badges,
-		};
+		let sender = Sender::from_privmsg(&privmsg);
 
 		Self {
 			cmd,
@@ -204,8 +306,7 @@ impl CommandSource {
 	// from by infering the command target
 	pub async fn user_channel_info_from_args(
 		&self,
-		twitch_auth:       &TwitchAuth,
-		name_id_cache_arc: Arc<Mutex<NameIdCache>>
+		state: &AppState,
 	) -> Result<(Channel, Channel), UserChannelParseError> {
 		match self.args.len() {
 			// if 0 args are supplied:
@@ -226,25 +327,9 @@ impl CommandSource {
 			//     the first arg is the target user,
 			//     the channel from which command is ran is the target channel
 			1 => {
-				let user_name = self.args[0].clone();
-				let mut user_id: Option<i32> = None;
-
-				if let Ok(cache) = name_id_cache_arc.lock() {
-					if let Some(id) = cache.get(&user_name) {
-						user_id = Some(*id);
-					}
-				}
-
-				if user_id.is_none() {
-					user_id = Some(api::id_from_nick(&user_name, twitch_auth)
-						.await.ok().ok_or(UserChannelParseError::Unknown)?
-						.ok_or_else(|| UserChannelParseError::UserNotFound(user_name.clone()))?); 
-				}
-				
-				let user = Channel {
-					name: user_name,
-					id:   user_id.unwrap(),
-				};
+				let user = resolve_user(&self.args[0], state)
+					.await?
+					.ok_or_else(|| UserChannelParseError::UserNotFound(self.args[0].clone()))?;
 				let channel = self.channel.clone();
 
 				Ok((user, channel))
@@ -254,45 +339,13 @@ impl CommandSource {
 			//     the first arg is the target user
 			//     the second arg is the target channel
 			_ => {
-				let user_name = self.args[0].clone();
-				let mut user_id: Option<i32> = None;
-				
-				let channel_name = self.args[1].clone();
-				let mut channel_id: Option<i32> = None;
-				
-				if let Ok(cache) = name_id_cache_arc.lock() {
-					if let Some(id) = cache.get(&user_name) {
-						user_id = Some(*id);
-					}
-
-					if let Some(id) = cache.get(&channel_name) {
-						channel_id = Some(*id);
-					}
-				}
-				
-				if user_id.is_none() {
-					user_id = Some(api::id_from_nick(&user_name, twitch_auth)
-						.await.ok().ok_or(UserChannelParseError::Unknown)?
-						.ok_or_else(|| UserChannelParseError::UserNotFound(user_name.clone()))?);
-				}
-
-				if channel_id.is_none() {
-					channel_id = Some(api::id_from_nick(&channel_name, twitch_auth)
-						.await
-						.ok()
-						.ok_or(UserChannelParseError::Unknown)?
-						.ok_or_else(|| UserChannelParseError::ChannelNotFound(channel_name.clone()))?);
-				}
+				let user = resolve_user(&self.args[0], state)
+					.await?
+					.ok_or_else(|| UserChannelParseError::UserNotFound(self.args[0].clone()))?;
 
-				let user = Channel {
-					id:   user_id.unwrap(),
-					name: user_name,
-				};
-
-				let channel = Channel {
-					id: channel_id.unwrap(),
-					name: channel_name,
-				};
+				let channel = resolve_user(&self.args[1], state)
+					.await?
+					.ok_or_else(|| UserChannelParseError::ChannelNotFound(self.args[1].clone()))?;
 
 				Ok((user, channel))
 			},
@@ -300,6 +353,39 @@ impl CommandSource {
 	}
 }
 
+// find a user by their name; first in the cache, then through the Twitch API
+// and if Twitch doesn't know the name (anymore), among the names the user
+// has previously been seen with, so that old names resolve to the current id
+pub async fn resolve_user(
+	user_name: &str,
+	state:     &AppState,
+) -> Result<Option<Channel>, UserChannelParseError> {
+	if let Some(id) = state.name_id_cache.get(user_name) {
+		return Ok(Some(Channel { id: *id, name: user_name.to_owned() }));
+	}
+
+	if let Some(channel) = state.tracked_channel(user_name) {
+		return Ok(Some(channel));
+	}
+
+	let api_id = api::id_from_nick(user_name, &state.auth)
+		.await
+		.ok()
+		.ok_or(UserChannelParseError::Unknown)?;
+
+	if let Some(id) = api_id {
+		state.name_id_cache.insert(user_name.to_owned(), id);
+
+		return Ok(Some(Channel { id, name: user_name.to_owned() }));
+	}
+
+	match db::user_from_known_name(&state.pool, user_name).await {
+		Ok(Some((id, current_name))) => Ok(Some(Channel { id, name: current_name })),
+		Ok(None)                     => Ok(None),
+		Err(_)                       => Err(UserChannelParseError::Unknown),
+	}
+}
+
 #[derive(Debug, Error)]
 pub enum UserChannelParseError {
 	#[error("💢 User `{0}` was not found")]
@@ -397,15 +483,9 @@ impl EmoteCache {
 			Ok(new_cache) => {
 				self.channels = new_cache.channels;
 				self.globals = new_cache.globals;
-				println!(
-					"{}   Renewed the emote cache.",
-					"INFO   ".blue().bold()
-				);
+				info!("Renewed the emote cache");
 			},
-			Err(_) => println!(
-				"{}   Couldn't renew emote cache, keeping it the same.",
-				"ERROR  ".red().bold()
-			),
+			Err(e) => error!("Couldn't renew emote cache, keeping it the same; err: {e}"),
 		}
 
 		Ok(())
@@ -413,12 +493,15 @@ impl EmoteCache {
 }
 
 // store the users' Twitch ID
-pub type NameIdCache = HashMap<String, i32>;
+pub type NameIdCache = DashMap<String, i32>;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum HookMatchType {
 	Exact,
 	Substring,
+	// the catchphrase as whole words, e.g. "hi" in "oh hi there" but not in "this"
+	Word,
+	Regex,
 }
 
 impl std::str::FromStr for HookMatchType {
@@ -428,90 +511,386 @@ impl std::str::FromStr for HookMatchType {
 		match s.to_lowercase().as_str() {
 			"exact"  => Ok(Self::Exact),
 			"substr" => Ok(Self::Substring),
+			"word"   => Ok(Self::Word),
+			"regex"  => Ok(Self::Regex),
 			_        => Err(MyError::NotFound),
 		}
 	}
 }
 
-impl std::string::ToString for HookMatchType {
-	fn to_string(&self) -> String {
-		match self {
-			Self::Exact     => "exact".to_owned(),
-			Self::Substring => "substr".to_owned(),
-		}
+impl std::fmt::Display for HookMatchType {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(match self {
+			Self::Exact     => "exact",
+			Self::Substring => "substr",
+			Self::Word      => "word",
+			Self::Regex     => "regex",
+		})
 	}
 }
 
-
+// the most memory a compiled hook regex may take up
+const MAX_HOOK_REGEX_SIZE: usize = 1 << 16;
 
 #[derive(Clone, Debug)]
 pub struct MessageHook {
+	pub name:           String,
 	pub capture_string: String, 
 	pub content:        String,
 	pub h_type:         HookMatchType,
+	// how long the hook stays quiet after it has fired
+	pub cooldown_s:     u64,
+	// whose messages the hook reacts to (anyone's if empty)
+	pub users:          Vec<String>,
+	// the badges (any of which) the sender has to have (none if empty)
+	pub badges:         Vec<TwitchBadge>,
+	pub enabled:        bool,
+	// how many times the hook has fired
+	pub count:          i64,
+	pub last_fired:     Option<DateTime<Utc>>,
+	// the compiled `Word` and `Regex` catchphrases
+	regex:              Option<regex::Regex>,
+}
+
+impl MessageHook {
+	// a hook that hasn't fired yet; fails for an invalid regex
+	pub fn new(
+		name:           String,
+		h_type:         HookMatchType,
+		capture_string: String,
+		content:        String,
+	) -> Result<Self, regex::Error> {
+		let pattern = match h_type {
+			HookMatchType::Word  => Some(format!(r"(?i)\b{}\b", regex::escape(&capture_string))),
+			HookMatchType::Regex => Some(capture_string.clone()),
+			_                    => None,
+		};
+		let regex = match pattern {
+			Some(p) => Some(regex::RegexBuilder::new(&p).size_limit(MAX_HOOK_REGEX_SIZE).build()?),
+			None    => None,
+		};
+
+		Ok(Self {
+			name,
+			capture_string,
+			content,
+			h_type,
+			cooldown_s: 0,
+			users:      vec![],
+			badges:     vec![],
+			enabled:    true,
+			count:      0,
+			last_fired: None,
+			regex,
+		})
+	}
+
+	// if the hook reacts to a message of `sender` at `now`, what its regex captured
+	pub fn fires_on(&self, sender: &Sender, message: &str, now: DateTime<Utc>) -> Option<Vec<String>> {
+		let cooled_down = match self.last_fired {
+			Some(t) => (now - t).num_seconds() >= self.cooldown_s as i64,
+			None    => true,
+		};
+		let user_ok  = self.users.is_empty() || self.users.iter().any(|u| u.eq_ignore_ascii_case(&sender.name));
+		let badge_ok = self.badges.is_empty() || self.badges.iter().any(|b| sender.statuses.contains(b));
+
+		match self.enabled && cooled_down && user_ok && badge_ok {
+			true  => self.captures(message),
+			false => None,
+		}
+	}
+
+	// whether a message matches the catchphrase
+	pub fn matches(&self, message: &str) -> bool {
+		self.captures(message).is_some()
+	}
+
+	// if a message matches the catchphrase, the groups the regex captured in it
+	// (the whole match first, a group that didn't match is empty)
+	pub fn captures(&self, message: &str) -> Option<Vec<String>> {
+		match (&self.h_type, &self.regex) {
+			(HookMatchType::Exact, _)     => (message == self.capture_string).then(Vec::new),
+			(HookMatchType::Substring, _) => message.to_lowercase().contains(&self.capture_string.to_lowercase()).then(Vec::new),
+			(_, Some(regex))              => regex.captures(message).map(|c| c
+				.iter()
+				.map(|group| group.map(|g| g.as_str().to_owned()).unwrap_or_default())
+				.collect()
+			),
+			(_, None)                     => None,
+		}
+	}
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct ChannelSpecifics {
 	pub hooks:               Vec<MessageHook>,
 	pub ongoing_trivia_game: Option<TriviaGameInfo>, 
+	// whether a trivia question is being fetched (without the lock held),
+	// during which no other game can start
+	pub trivia_pending:      bool,
+}
+
+// every channel has its own lock, so that the channels
+// don't wait on each other and the lock can be held across awaits
+pub type ChannelSpecificsCache = DashMap<String, Arc<Mutex<ChannelSpecifics>>>;
+
+// spawns tasks and keeps count of the ones still running,
+// so that a shutdown can wait for them to finish
+#[derive(Clone, Default)]
+pub struct TaskTracker {
+	inner: Arc<TrackedTasks>,
+}
+
+#[derive(Default)]
+struct TrackedTasks {
+	running: AtomicUsize,
+	// notified whenever the last of the running tasks is done
+	done:    Notify,
 }
 
-pub type ChannelSpecificsCache = HashMap<String, ChannelSpecifics>;
+// a task counts as done when it gets dropped,
+// so also when it panicked or was aborted
+struct TrackedTask(Arc<TrackedTasks>);
+
+impl Drop for TrackedTask {
+	fn drop(&mut self) {
+		if self.0.running.fetch_sub(1, Ordering::SeqCst) == 1 {
+			self.0.done.notify_waiters();
+		}
+	}
+}
+
+impl TaskTracker {
+	pub fn spawn<F>(&self, task: F) -> tokio::task::JoinHandle<()>
+	where
+		F: Future<Output = ()> + Send + 'static,
+	{
+		self.inner.running.fetch_add(1, Ordering::SeqCst);
+		let tracked = TrackedTask(self.inner.clone());
+
+		tokio::spawn(async move {
+			let _tracked = tracked;
+			task.await;
+		})
+	}
+
+	pub fn running(&self) -> usize {
+		self.inner.running.load(Ordering::SeqCst)
+	}
+
+	// resolves once none of the tasks are running
+	pub async fn wait(&self) {
+		loop {
+			// (created before checking, so that the last one finishing in between isn't missed)
+			let done = self.inner.done.notified();
+			if self.running() == 0 {
+				return;
+			}
+			done.await;
+		}
+	}
+}
+
+// everything the tasks of the bot share
+pub struct AppState {
+	pub config:            Config,
+	pub auth:              TwitchAuth,
+	pub pool:              SqlitePool,
+	pub client:            TwitchClient,
+	pub log_writer:        db::LogWriter,
+	pub name_id_cache:     NameIdCache,
+	// the names the users were last seen with (by id),
+	// so that a name is only written down once it changes
+	pub user_names:        DashMap<i32, String>,
+	pub emote_cache:       RwLock<EmoteCache>,
+	pub channel_specifics: ChannelSpecificsCache,
+	pub markov_models:     markov::MarkovModelCache,
+	// the tasks handling messages and commands, which get to finish on shutdown
+	pub tasks:             TaskTracker,
+	// the ids of the channels the bot is in by their (lowercase) names,
+	// looked up once on startup
+	pub channel_ids:       HashMap<String, i32>,
+}
+
+impl AppState {
+	pub fn new(
+		config:      Config,
+		auth:        TwitchAuth,
+		pool:        SqlitePool,
+		client:      TwitchClient,
+		log_writer:  db::LogWriter,
+		emote_cache: EmoteCache,
+		channel_ids: HashMap<String, i32>,
+	) -> Self {
+		Self {
+			config,
+			auth,
+			pool,
+			client,
+			log_writer,
+			name_id_cache:     NameIdCache::new(),
+			user_names:        DashMap::new(),
+			emote_cache:       RwLock::new(emote_cache),
+			channel_specifics: ChannelSpecificsCache::new(),
+			markov_models:     markov::MarkovModelCache::new(),
+			tasks:             TaskTracker::default(),
+			channel_ids,
+		}
+	}
 
-// converts html entities to actual chars (only some selected ones, not all!!) 
+	// a channel the bot is in, by its name (in any case)
+	pub fn tracked_channel(&self, name: &str) -> Option<Channel> {
+		let name = name.to_lowercase();
+
+		self.channel_ids.get(&name).map(|&id| Channel { id, name })
+	}
+
+	// all of the channels the bot is in, in the order of the config
+	pub fn tracked_channels(&self) -> Vec<Channel> {
+		self.config.channels
+			.iter()
+			.filter_map(|name| self.tracked_channel(name))
+			.collect()
+	}
+
+	// runtime info of a channel (empty, if there was none yet)
+	pub fn channel(&self, channel_id: &str) -> Arc<Mutex<ChannelSpecifics>> {
+		self.channel_specifics
+			.entry(channel_id.to_owned())
+			.or_default()
+			.clone()
+	}
+}
+
+// the longest named html entity, `&CounterClockwiseContourIntegral;`
+const MAX_HTML_ENTITY_LEN: usize = 33;
+
+// the html5 named entities without their `&`, e.g. `amp;` -> `&`
+// (some of them also go without the `;` for legacy reasons, e.g. `amp`)
+fn html_entities() -> &'static HashMap<&'static str, &'static str> {
+	static ENTITIES: OnceLock<HashMap<&str, &str>> = OnceLock::new();
+
+	ENTITIES.get_or_init(|| entities::ENTITIES
+		.iter()
+		.map(|e| (&e.entity[1..], e.characters))
+		.collect()
+	)
+}
+
+// converts html entities (named ones, and numeric ones like `&#039;` or `&#x27;`)
+// to actual chars the way browsers do, anything else stays as it is
 pub fn convert_from_html_entities(s: String) -> String {
-    s
-        .replace("&nbsp;", " ")
-        .replace("&#039;", "'")
-        .replace("&quot;", "\"")
-		.replace("&Delta;", "d")
-		.replace("&deg;", " degrees")
-		.replace("&rsquo;", "’")
-		.replace("&hellip;", "…")
-		.replace("&rdquo;", "”")
-		.replace("&pi;", "π")
-}
-
-pub fn convert_to_html_encoding(s: String) -> String {
-	s
-		.replace('%',  "%25") // this one has to be always first!
-		.replace(' ',  "%20")
-		.replace('&',  "%26")
-		.replace('\'',  "%27")
-		.replace('(',  "%28")
-		.replace(')',  "%29")
-        .replace('/',  "%2F")
-		.replace('*',  "%2A")
-		.replace('+',  "%2B")
-		.replace(',',  "%2C")
-		.replace('-',  "%2D")
-		.replace('.',  "%2E")
-		.replace('/',  "%2F")
-		.replace(':',  "%3A")
-		.replace('<',  "%3C")
-		.replace('=',  "%3D")
-		.replace('>',  "%3E")
-		.replace('?',  "%3F")
-		.replace('@',  "%40")
-		.replace('[',  "%5B")
-		.replace('\\', "%5C")
-		.replace(']',  "%5D")
-		.replace('^',  "%5E")
-		.replace('_',  "%5F")
-		.replace('`',  "%60")
-		.replace('{',  "%7B")
-		.replace('|',  "%7C")
-		.replace('}',  "%7D")
-		.replace('~',  "%7E")
-		.replace('€',  "%E2%82%AC")
-		.replace('‚',  "%E2%80%9A")
-		.replace('„',  "%E2%80%9E")
-		.replace('ˆ',  "%CB%86")
-		.replace('‘',  "%E2%80%98")
-		.replace('’',  "%E2%80%99")
-		.replace('“',  "%E2%80%9C")
-		.replace('”',  "%E2%80%9D")
+	if !s.contains('&') {
+		return s;
+	}
+
+	let mut decoded = String::with_capacity(s.len());
+	let mut rest = s.as_str();
+
+	while let Some(idx) = rest.find('&') {
+		decoded.push_str(&rest[..idx]);
+		rest = &rest[idx..];
+
+		match decode_html_entity(rest) {
+			Some((chars, len)) => {
+				decoded.push_str(&chars);
+				rest = &rest[len..];
+			},
+			None => {
+				decoded.push('&');
+				rest = &rest[1..];
+			},
+		}
+	}
+	decoded.push_str(rest);
+
+	decoded
+}
+
+// the chars of the entity `s` starts with (if it's one), and how long the entity is
+fn decode_html_entity(s: &str) -> Option<(String, usize)> {
+	let body = s.strip_prefix('&')?;
+
+	if let Some(num) = body.strip_prefix('#') {
+		let (digits, radix, prefix_len) = match num.strip_prefix(['x', 'X']) {
+			Some(hex) => (hex, 16, 2),
+			None      => (num, 10, 1),
+		};
+
+		let len = digits.find(|c: char| !c.is_digit(radix)).unwrap_or(digits.len());
+		if len == 0 {
+			return None;
+		}
+
+		// (numbers too big for an u32 are no chars either)
+		let code = u32::from_str_radix(&digits[..len], radix).unwrap_or(u32::MAX);
+		let semicolon_len = digits[len..].starts_with(';') as usize;
+
+		return Some((numeric_html_entity(code).to_string(), 1 + prefix_len + len + semicolon_len));
+	}
+
+	let name_len = body
+		.find(|c: char| !c.is_ascii_alphanumeric())
+		.unwrap_or(body.len())
+		.min(MAX_HTML_ENTITY_LEN);
+
+	if body[name_len..].starts_with(';') {
+		if let Some(chars) = html_entities().get(&body[..=name_len]) {
+			return Some((chars.to_string(), name_len + 2));
+		}
+	}
+
+	// without the `;`, the longest legacy entity counts, e.g. `&notit;` is `¬it;`
+	(2..=name_len)
+		.rev()
+		.find_map(|len| html_entities().get(&body[..len]).map(|chars| (chars.to_string(), len + 1)))
+}
+
+// the char of a numeric entity; like in browsers, the C1 control codes
+// are read as windows-1252 and what isn't a valid char becomes `�`
+fn numeric_html_entity(code: u32) -> char {
+	const WINDOWS_1252: [char; 32] = [
+		'€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8D}', 'Ž', '\u{8F}',
+		'\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9D}', 'ž', 'Ÿ',
+	];
+
+	match code {
+		0           => char::REPLACEMENT_CHARACTER,
+		0x80..=0x9F => WINDOWS_1252[(code - 0x80) as usize],
+		_           => char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER),
+	}
+}
+
+// how trivia questions are timed;
+// is the `trivia` section of the config file
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct TriviaConfig {
+	// how long a question can go unanswered (0 for forever);
+	// there is a hint once half of it is up
+	pub timeout_s:      u64,
+	pub hint:           TriviaHint,
+	// whether a round goes on after a question times out
+	pub continue_round: bool,
+}
+
+impl Default for TriviaConfig {
+	fn default() -> Self {
+		Self {
+			timeout_s:      60,
+			hint:           TriviaHint::Options,
+			continue_round: true,
+		}
+	}
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TriviaHint {
+	// all of the possible answers (like `$hint`)
+	Options,
+	// the first letter of every word of the answer
+	Letters,
 }
 
 #[derive(Clone, Debug)]
@@ -519,27 +898,241 @@ pub struct TriviaGameInfo {
 	pub question: String,
 	pub correct_answer: String,
 	pub wrong_answers:  Vec<String>,
+	// easy / medium / hard
+	pub difficulty:     String,
+	pub round:          TriviaRound,
+	// when the question was asked and whether
+	// the hint for taking too long was given since
+	pub asked_at:       DateTime<Utc>,
+	pub hinted:         bool,
+	// all of the answers in the order they are shown by the hint
+	// and whether they were, so that they can be answered by their letter
+	pub options:        Vec<String>,
+	pub options_shown:  bool,
+	// the users who picked a wrong option (by its letter, or "true"/"false"),
+	// who can't just go on picking the others
+	pub wrong_pickers:  Vec<i32>,
+}
+
+// a series of questions started by `$trivia <count>`
+// (a lone question is a round of one)
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct TriviaRound {
+	// what the round was started with (category, difficulty, type),
+	// so that the next questions are the same kind
+	pub options: Vec<String>,
+	// which question of how many is being asked
+	pub current: u32,
+	pub length:  u32,
+	// the points scored in the round so far, by user name
+	pub scores:  Vec<(String, u32)>,
+}
+
+impl Default for TriviaRound {
+	fn default() -> Self {
+		Self {
+			options: vec![],
+			current: 1,
+			length:  1,
+			scores:  vec![],
+		}
+	}
+}
+
+impl TriviaRound {
+	pub fn add_points(&mut self, user_name: &str, points: u32) {
+		match self.scores.iter_mut().find(|(name, _)| name == user_name) {
+			Some((_, score)) => *score += points,
+			None             => self.scores.push((user_name.to_owned(), points)),
+		}
+
+		self.scores.sort_by_key(|(_, points)| std::cmp::Reverse(*points));
+	}
+
+	pub fn is_last_question(&self) -> bool {
+		self.current >= self.length
+	}
+
+	// e.g. "forsen (5), xqc (2)"
+	pub fn fmt_scores(&self) -> String {
+		match self.scores.is_empty() {
+			true  => "nobody scored LUL".into(),
+			false => self.scores
+				.iter()
+				.map(|(name, points)| format!("{name} ({points})"))
+				.collect::<Vec<_>>()
+				.join(", "),
+		}
+	}
 }
 
 impl TriviaGameInfo {
-	pub fn from_api_object(s: crate::api_models::TriviaQuestion) -> Self {
+	// a lone question, asked just now
+	pub fn new(
+		question:       String,
+		correct_answer: String,
+		wrong_answers:  Vec<String>,
+		difficulty:     String,
+	) -> Self {
+		// true/false questions only have a single wrong answer
+		let mut options = vec![correct_answer.clone()];
+		options.extend(wrong_answers.iter().cloned());
+		options.shuffle(&mut rand::thread_rng());
+
 		Self {
-			question: s.question,
-			correct_answer: s.correct_answer,
-			wrong_answers: s.incorrect_answers
+			question,
+			correct_answer,
+			wrong_answers,
+			difficulty,
+			round:         TriviaRound::default(),
+			asked_at:      Utc::now(),
+			hinted:        false,
+			options,
+			options_shown: false,
+			wrong_pickers: vec![],
+		}
+	}
+
+	pub fn from_api_object(s: crate::api_models::TriviaQuestion) -> Self {
+		Self::new(
+			convert_from_html_entities(s.question),
+			convert_from_html_entities(s.correct_answer),
+			s.incorrect_answers.into_iter().map(convert_from_html_entities).collect(),
+			s.difficulty,
+		)
+	}
+
+	// what a correct answer is worth
+	pub fn points(&self) -> u32 {
+		match self.difficulty.as_str() {
+			"hard"   => 3,
+			"medium" => 2,
+			_        => 1,
+		}
+	}
+
+	// the question, numbered if it's a part of a longer round
+	pub fn announcement(&self) -> String {
+		match self.round.length {
+			1 => self.question.clone(),
+			_ => format!("({}/{}) {}", self.round.current, self.round.length, self.question),
+		}
+	}
+
+	// e.g. `The options are: A) "Navi", B) "Fi", C) "Midna", D) "Tatl"`;
+	// from then on, the letters count as answers
+	pub fn options_hint(&mut self) -> String {
+		self.options_shown = true;
+
+		let c = self.options
+			.iter()
+			.zip('A'..='Z')
+			.map(|(a, letter)| format!("{letter}) \"{a}\""))
+			.collect::<Vec<String>>()
+			.join(", ");
+
+		format!("The options are: {}", c)
+	}
+
+	// whether a guess of a user is correct; a user who picks a wrong option
+	// gets remembered, and their guesses don't count from then on
+	pub fn guess(&mut self, user_id: i32, guess: &str) -> bool {
+		if self.wrong_pickers.contains(&user_id) {
+			return false;
+		}
+		if self.is_correct(guess) {
+			return true;
+		}
+
+		let is_boolean = |a: &str| matches!(normalize_answer(a).as_str(), "true" | "false");
+		let picked = self.chosen_option(guess).is_some()
+			|| is_boolean(&self.correct_answer) && is_boolean(guess);
+		if picked {
+			self.wrong_pickers.push(user_id);
 		}
+
+		false
 	}
 
-	pub fn shuffled_answers(&self) -> Vec<&String> {
-		let mut answers = vec![
-			&self.correct_answer,
-			&self.wrong_answers[0],
-			&self.wrong_answers[1],
-			&self.wrong_answers[2],
-		];
+	// whether a guess is (close enough to) the correct answer
+	pub fn is_correct(&self, guess: &str) -> bool {
+		if let Some(chosen) = self.chosen_option(guess) {
+			return chosen == &self.correct_answer;
+		}
+
+		let guess = normalize_answer(guess);
+		let correct = normalize_answer(&self.correct_answer);
+
+		if guess.is_empty() {
+			return false;
+		}
+		if guess == correct {
+			return true;
+		}
+
+		// a typo (or two swapped letters) is fine,
+		// as long as the guess isn't closer to a wrong answer
+		let distance = strsim::osa_distance(&guess, &correct);
+		let closest_wrong = self.wrong_answers
+			.iter()
+			.map(|a| strsim::osa_distance(&guess, &normalize_answer(a)))
+			.min()
+			.unwrap_or(usize::MAX);
+
+		distance <= typo_tolerance(&correct) && distance < closest_wrong
+	}
+
+	// the option a guess is the letter of (once the options are out)
+	fn chosen_option(&self, guess: &str) -> Option<&String> {
+		let letter = guess.trim().to_lowercase();
+		if !self.options_shown || letter.len() != 1 {
+			return None;
+		}
+
+		letter
+			.chars()
+			.next()
+			.and_then(|c| ('a'..='z').position(|l| l == c))
+			.and_then(|i| self.options.get(i))
+	}
+
+	// e.g. `The answer looks like: N___`
+	pub fn letters_hint(&self) -> String {
+		let letters = self.correct_answer
+			.split(' ')
+			.map(|word| word
+				.chars()
+				.enumerate()
+				.map(|(i, c)| if i == 0 || !c.is_alphanumeric() { c } else { '_' })
+				.collect::<String>()
+			)
+			.collect::<Vec<_>>()
+			.join(" ");
+
+		format!("The answer looks like: {letters}")
+	}
+}
+
+// an answer the way it gets compared: ascii, lowercase, without punctuation,
+// articles or HTML entities, e.g. "The Pok&eacute;mon &amp; Co." -> "pokemon co"
+pub fn normalize_answer(s: &str) -> String {
+	let text = convert_from_html_entities(s.to_owned());
+
+	deunicode::deunicode(&text)
+		.to_lowercase()
+		.split(|c: char| !c.is_ascii_alphanumeric())
+		.filter(|word| !word.is_empty() && !["a", "an", "the", "and"].contains(word))
+		.collect::<Vec<_>>()
+		.join(" ")
+}
 
-		answers.shuffle(&mut rand::thread_rng());
-		answers
+// how many typos an answer can have; numbers have to be exact
+fn typo_tolerance(answer: &str) -> usize {
+	match answer.len() {
+		_ if answer.chars().any(|c| c.is_ascii_digit()) => 0,
+		0..=4 => 0,
+		5..=8 => 1,
+		_     => 2,
 	}
 }
 
@@ -0,0 +1,84 @@
+use std::path::Path;
+
+use serde::{Serialize, Deserialize};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::fmt;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::EnvFilter;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+	// human readable lines
+	Pretty,
+	// one JSON object per line
+	Json,
+}
+
+// how (and where to) the bot logs;
+// is the `logging` section of the config file
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct LoggingConfig {
+	// filter directives, e.g. `info` or `twitch_bot=debug,sqlx=warn`
+	// (the `RUST_LOG` environment variable takes precedence)
+	pub level:  String,
+	pub format: LogFormat,
+	// if set, the logs also get appended to this file
+	pub file:   Option<String>,
+}
+
+impl Default for LoggingConfig {
+	fn default() -> Self {
+		Self {
+			level:  "info".into(),
+			format: LogFormat::Pretty,
+			file:   None,
+		}
+	}
+}
+
+// set up the global logger; the returned guard has to be held
+// for as long as the bot runs, else the file logs get cut off
+pub fn init(config: &LoggingConfig) -> anyhow::Result<Option<WorkerGuard>> {
+	let filter = match EnvFilter::try_from_default_env() {
+		Ok(f)  => f,
+		Err(_) => EnvFilter::try_new(&config.level)?,
+	};
+
+	let stdout = match config.format {
+		LogFormat::Pretty => fmt::layer().boxed(),
+		LogFormat::Json   => fmt::layer().json().boxed(),
+	};
+
+	let (file, guard) = match &config.file {
+		Some(path) => {
+			let path = Path::new(path);
+			let dir = match path.parent() {
+				Some(dir) if !dir.as_os_str().is_empty() => dir,
+				_                                        => Path::new("."),
+			};
+			let file_name = path
+				.file_name()
+				.ok_or_else(|| anyhow::anyhow!("log file path `{}` is not a file", path.display()))?;
+
+			let (writer, guard) = tracing_appender::non_blocking(tracing_appender::rolling::never(dir, file_name));
+
+			let layer = match config.format {
+				LogFormat::Pretty => fmt::layer().with_ansi(false).with_writer(writer).boxed(),
+				LogFormat::Json   => fmt::layer().json().with_writer(writer).boxed(),
+			};
+
+			(Some(layer), Some(guard))
+		},
+		None => (None, None),
+	};
+
+	tracing_subscriber::registry()
+		.with(filter)
+		.with(stdout)
+		.with(file)
+		.try_init()?;
+
+	Ok(guard)
+}
@@ -1,42 +1,40 @@
-mod api;
-mod api_models;
-mod commands;
-mod db;
-mod background;
-
 use twitch_bot::{
-	Config,
+	api,
+	db,
+	commands,
+	background,
+	logging,
+	markov,
+	server,
+	trivia,
+	AppState,
 	Channel,
-	MyError,
+	Config,
 	EmoteCache,
-	TwitchAuth, 
-	TwitchBadge,
-	NameIdCache,
+	TwitchAuth,
 	CommandSource,
-	TriviaGameInfo,
 	ChannelSpecifics,
-	ChannelSpecificsCache,
-	MessageHook,
-	HookMatchType,
+	Sender,
 	fmt_duration,
-	convert_from_html_entities,
-	convert_to_html_encoding,
-	binomial_p_exact,
-	binomial_p_exact_or_less,
 };
 use background as bg;
-use commands::handle_command;
+use commands::{handle_command, say};
+use twitch_bot::metrics::metrics;
 
-use std::sync::{Arc, Mutex};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
 
-use colored::*;
-use chrono::Local;
+use anyhow::{bail, Context};
+use chrono::{Local, Utc};
 use dotenv::dotenv;
 use sqlx::sqlite::SqlitePool;
-// use tracing::{info, error, warn};
+use tracing::{error, info, info_span, warn, Instrument};
 use twitch_irc::login::StaticLoginCredentials;
 use twitch_irc::{ClientConfig, SecureTCPTransport, TwitchIRCClient};
-use twitch_irc::message::ServerMessage;
+use twitch_irc::message::{PrivmsgMessage, ServerMessage};
+use tokio::sync::mpsc::{self, error::SendError, UnboundedSender};
+use tokio::sync::Mutex;
 
 // path to database file
 // for now, it is a singular file - 
@@ -44,43 +42,52 @@ use twitch_irc::message::ServerMessage;
 // in the future, idk
 const DB_PATH: &str = "sqlite:db.db";
 
+// how long the commands still being handled get to finish on shutdown
+const SHUTDOWN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
 	// load environment variables from `.env` file
 	dotenv().ok();
 
+	// `rebuild-markov [channel...]` rebuilds the markov indexes
+	// of the channels (all of them if none are given) and exits,
+	// `import-trivia <file...>` adds trivia questions to the local bank and exits
+	let args: Vec<String> = std::env::args().skip(1).collect();
+	match args.first().map(String::as_str) {
+		None | Some("rebuild-markov") | Some("import-trivia") => (),
+		Some(other) => bail!("Unknown subcommand \"{other}\", the subcommands are \"rebuild-markov\" and \"import-trivia\""),
+	}
+
 
 
 	// load all of the credentials and configurations
 	let config = Config::from_config_file()
-		.unwrap_or_else(|_| panic!("{}   Couldn't load config, aborting.", "ERROR  ".red().bold()));
-	let auth = TwitchAuth::from_env()
-		.unwrap_or_else(|_| panic!("{}   Couldn't load Twitch credentials from .env", "ERROR  ".red().bold()));
+		.context("Couldn't load config, aborting")?;
+
+	// the guard keeps the log file being written to
+	let _log_guard = logging::init(&config.logging)
+		.context("Couldn't set up logging, aborting")?;
 
-	println!("{}   Obtained credentials and config from local files", "INFO   ".blue().bold());
+	let auth = TwitchAuth::from_env()
+		.context("Couldn't load Twitch credentials from .env")?;
 
+	info!("Obtained credentials and config from local files");
 
+	api::set_api_urls(config.api_urls.clone().with_env_overrides());
 
-	// this will hold cached names and ids of users
-	// to prevent flooding the Twitch API too much
-	let name_id_cache = Arc::new(Mutex::new(NameIdCache::new()));
 
-	let emote_cache = Arc::new(Mutex::new({
-		match EmoteCache::init(&config, &auth).await {
-			Ok(e) => e,
-			Err(e) => panic!("{}", e),
-		}
-	}));
 
-	// holds channel-specific information crucial for runtime
-	let channel_specifics_arc = Arc::new(Mutex::new(ChannelSpecificsCache::new()));
+	let emote_cache = EmoteCache::init(&config, &auth)
+		.await
+		.context("Couldn't load the emotes of the channels, aborting")?;
 
 
 	
 	// instantiate database connection pool
     let pool = SqlitePool::connect(DB_PATH)
 		.await
-		.unwrap_or_else(|_| panic!("{}   Database connection could not be established, aborting.", "ERROR  ".red().bold()));
+		.context("Database connection could not be established, aborting")?;
 
 
 
@@ -88,7 +95,23 @@ async fn main() -> anyhow::Result<()> {
 	// to be present in database
 	db::init_db(&pool)
 		.await
-		.unwrap_or_else(|_| panic!("{}   Database could not be set up, aborting.", "ERROR  ".red().bold()));
+		.context("Database could not be set up, aborting")?;
+
+	if args.first().map(String::as_str) == Some("import-trivia") {
+		if args.len() < 2 {
+			bail!("No files of trivia questions to import were given");
+		}
+
+		for path in &args[1..] {
+			let questions = trivia::read_questions(Path::new(path))
+				.with_context(|| format!("Couldn't read the trivia questions in {path}"))?;
+			let imported = trivia::import_questions(&pool, &questions).await?;
+
+			info!("Imported {imported} trivia questions from {path}, {} were there already", questions.len() - imported);
+		}
+
+		return Ok(());
+	}
 
 	// create database tables for channels in config
 	// (if they do not already exist)
@@ -96,47 +119,38 @@ async fn main() -> anyhow::Result<()> {
 	for channel in &config.channels {
 		let channel_id = api::id_from_nick(channel, &auth)
 			.await?
-			.unwrap_or_else(||
-				panic!(
-					"{}   Channel \"{}\" wasn't found, aborting",
-               		"ERROR  ".red().bold(),
-               		channel.bold()
-				)
-			);
+			.with_context(|| format!("Channel \"{channel}\" wasn't found, aborting"))?;
 
 		db::try_create_tables_for_channel(&pool, channel_id)
 			.await
-			.unwrap_or_else(|_|
-				panic!("{}   Could not create tables for channel \"{}\", aborting",
-                    "ERROR  ".red().bold(),
-                    channel.bold(),
-				)
-			);
+			.with_context(|| format!("Could not create tables for channel \"{channel}\", aborting"))?;
 		
 		ids.push(channel_id);
 	}
-	println!("{}   Created tables in db", "INFO   ".blue().bold());
+	info!("Created tables in db");
 
+	if args.first().map(String::as_str) == Some("rebuild-markov") {
+		for (name, id) in config.channels.iter().zip(&ids) {
+			if args.len() > 1 && !args[1..].iter().any(|a| a.to_lowercase() == name.to_lowercase()) {
+				continue;
+			}
 
-	if let Ok(mut cache) = channel_specifics_arc.lock() {
-		for id in ids {
-			let hooks = match db::get_channel_hooks(&pool, id).await? {
-				Some(hs) => hs,
-				None     => vec![],
-			};
+			let channel = Channel { id: *id, name: name.to_lowercase() };
+			let stats = markov::rebuild_index(&pool, &channel, &config, &emote_cache, |done, total| {
+				info!("Rebuilding the markov index of #{name}: {done}/{total} messages");
+			})
+				.await
+				.with_context(|| format!("Couldn't rebuild the markov index of #{name}"))?
+				.with_context(|| format!("The markov index of #{name} is being rebuilt already"))?;
 
-			cache.insert(id.to_string(), ChannelSpecifics {
-				hooks,
-				ongoing_trivia_game: None,
-			});
+			info!("Rebuilt the markov index of #{name} out of {} messages, skipped {}", stats.indexed, stats.skipped);
 		}
-	} else {
-		panic!(
-			"{}   Could not initialize game specifics, aborting",
-            "ERROR  ".red().bold()
-		)
+
+		return Ok(());
 	}
 
+
+
 	// instantiate Twitch client
 	let client_config: ClientConfig<StaticLoginCredentials> = ClientConfig::new_simple(
 		StaticLoginCredentials::new(
@@ -148,209 +162,403 @@ async fn main() -> anyhow::Result<()> {
         TwitchIRCClient::<SecureTCPTransport, StaticLoginCredentials>::new(client_config);
 
 	// join all channels in config
+	// (the client rejoins them by itself after reconnecting)
 	for channel in &config.channels {
 		client.join(channel.into());
-		println!("{}   Joined #{}", "INFO   ".blue().bold(), channel.bold());
+		info!("Joining #{channel}");
+	}
+
+
+
+	// chat logs and markov entries get written in batches in the background
+	let (log_writer, log_writer_handle) = db::LogWriter::spawn(pool.clone());
+
+	// everything the tasks below share
+	let channel_ids = config.channels
+		.iter()
+		.map(|name| name.to_lowercase())
+		.zip(ids.iter().copied())
+		.collect();
+
+	let state = Arc::new(AppState::new(config, auth, pool, client, log_writer, emote_cache, channel_ids));
+
+	// holds channel-specific information crucial for runtime
+	for &id in &ids {
+		let hooks = db::get_channel_hooks(&state.pool, id).await?;
+
+		state.channel_specifics.insert(id.to_string(), Arc::new(Mutex::new(ChannelSpecifics {
+			hooks,
+			ongoing_trivia_game: None,
+			trivia_pending:      false,
+		})));
+	}
+
+	// pick up the trivia games that were going on before the last shutdown
+	for (channel_id, game) in db::take_saved_trivia_games(&state.pool).await? {
+		state.channel(&channel_id).lock().await.ongoing_trivia_game = Some(game);
 	}
 
 
 
 	// set up tasks running periodcally in thebackground
 	{
-		let _pool = pool.clone();
-		let _config = config.clone();
-		let _auth = auth.clone();
-		let _name_id_cache = name_id_cache.clone();
-		
-		if config.track_offliners {
+		if state.config.track_offliners {
+			let state = state.clone();
+
 			tokio::spawn(async move {
 				loop {
-					match bg::check_for_offliners(&_pool, &_config, &_auth, &_name_id_cache).await {
-						Ok(num)  => println!("{}   Checked for offliners ({} occurences)", "INFO   ".blue().bold(), format!("{}", num).bold()),
-						Err(e)   => println!("{}   Error checking for offliners; err: {e}", "ERROR    ".red().bold()),
+					match bg::check_for_offliners(&state).await {
+						Ok(num)  => info!("Checked for offliners ({num} occurences)"),
+						Err(e)   => error!("Error checking for offliners; err: {e}"),
 					}
-					std::thread::sleep(std::time::Duration::from_secs(60));
+					tokio::time::sleep(std::time::Duration::from_secs(60)).await;
 				}
 			});
 		}
 
-		let _name_id_cache = name_id_cache.clone();
+		if state.config.trivia.timeout_s > 0 {
+			let state = state.clone();
+			let channels = state.tracked_channels();
+
+			tokio::spawn(async move {
+				loop {
+					if let Err(e) = bg::check_trivia_timers(&state, &channels).await {
+						error!("Error checking trivia timers; err: {e}");
+					}
+
+					tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+				}
+			});
+		}
+
+		let state = state.clone();
 
 		tokio::spawn(async move {
 			loop {
-				match bg::clear_name_id_cache(&_name_id_cache).await {
-					Ok(num) => println!("{}   Cleared name-id cache ({} items)", "INFO   ".blue().bold(), num),
-					Err(e)  => println!("{}   Error clearing name-id cache; err: {e}", "ERROR    ".red().bold()),
+				match bg::clear_name_id_cache(&state.name_id_cache).await {
+					Ok(num) => info!("Cleared name-id cache ({num} items)"),
+					Err(e)  => error!("Error clearing name-id cache; err: {e}"),
 				}
 
-				std::thread::sleep(std::time::Duration::from_secs(15 * 60));
+				tokio::time::sleep(std::time::Duration::from_secs(15 * 60)).await;
 			}
 		});
 	}
-	println!("{}   Set up scheduled tasks", "INFO   ".blue().bold());
+	info!("Set up scheduled tasks");
+
+
+
+	// serve metrics (and whatever else) over HTTP if enabled by config
+	if let Some(addr) = state.config.server.listen {
+		let (addr, _) = server::spawn(state.clone(), addr)
+			.with_context(|| format!("Couldn't start the HTTP server on {addr}, aborting"))?;
+
+		info!("Serving HTTP on http://{addr}");
+	}
 
 
 
 	// handle incoming messages
-    let message_listener_handle = {
-		let auth = auth.clone();
-		let nameid_cache_arc = name_id_cache.clone();
-		let emote_cache_arc = emote_cache.clone();
-		let channel_specifics_arc = channel_specifics_arc.clone();
+    let mut message_listener_handle = {
+		let state = state.clone();
 
 		tokio::spawn(async move {
-			while let Some(message) = incoming_messages.recv().await {
-				// privmsg == chat message
-				if let ServerMessage::Privmsg(privmsg) = message {
+			// each channel gets its own task handling its messages in order,
+			// so that a busy (or slow) channel doesn't hold up the other ones
+			let mut channel_queues: HashMap<String, UnboundedSender<PrivmsgMessage>> = HashMap::new();
 
-					if config.disregarded_users.contains(&privmsg.sender.login) {
-						continue;
-					}
-
-					// log chat messages into database
-					// (messages by the bot itself are not here,
-					//	, so that's taken care off)
-					match db::log(&pool, &privmsg).await {
-						Ok(_) => (),
-						Err(e) => println!("{}   Uncaught error; message: {e}", "ERROR    ".red().bold()),
-					};
-
-
-					if let Some(duration) = db::is_lurker(
-						&pool,
-						privmsg.sender.id.parse::<i32>().unwrap()
-					).await.unwrap() {
-						client.say(
-							privmsg.source.params[0][1..].to_owned(),
-							format!("{} is no longer AFK ({})", privmsg.sender.name, fmt_duration(duration, false)),
-						).await.unwrap();
-					};
-
-					// check if user has any reminders set for them
-					let reminders = 
-						db::check_for_reminders(
-							&pool,
-							privmsg.sender.id.parse::<i32>().unwrap(),
-						).await.unwrap();
-
-					if let Some(rs) = reminders {
-						for r in &rs {
-							let from_user = if r.from_user_id == r.for_user_id {
-								"yourself".into()
-							} else {
-								api::nick_from_id(r.from_user_id, &auth)
-								.await
-								.unwrap()
-							};
-							
-							let for_user = api::nick_from_id(r.for_user_id, &auth)
-								.await
-								.unwrap();
-							
-							client.clone().say(
-								privmsg.source.params[0][1..].to_owned(),
-								format!("@{for_user} 🔔🗨 {from_user}: {}", r.message)
-							).await.unwrap();
+			while let Some(message) = incoming_messages.recv().await {
+				match message {
+					// privmsg == chat message
+					ServerMessage::Privmsg(privmsg) => {
+						if state.config.disregarded_users.contains(&privmsg.sender.login) {
+							continue;
 						}
-					}
 
-					// if message is a command, handle it
-					if privmsg.message_text.chars().next().unwrap() == config.prefix {
-						let cmd_src = CommandSource::from_privmsg(privmsg.clone());
-						handle_command(
-							&pool,
-							client.clone(),
-							&config,
-							&auth,
-							nameid_cache_arc.clone(),
-							cmd_src,
-							channel_specifics_arc.clone()
-						).await;
-					} else {
-						// index for markov if enabled by config
-						if config.index_markov {
-							db::log_markov(&pool, &emote_cache_arc, &privmsg).await.unwrap();
+						// log chat messages into database
+						// (messages by the bot itself are not here,
+						//	, so that's taken care off), and index them for markov if enabled by config
+						if state.config.index_markov && !privmsg.message_text.starts_with(state.config.prefix) {
+							state.log_writer.log_with_markov(&*state.emote_cache.read().await, &privmsg, state.config.markov.order);
+						} else {
+							state.log_writer.log(&privmsg);
 						}
 
-						let channel_id = &privmsg.source.tags.0.get("room-id");
-						if let Some(Some(room_id)) = channel_id {
-							let mut correct = false;
-							if let Ok(mut cache) = channel_specifics_arc.lock() {
-								if
-									(*cache).get(room_id).is_some() &&
-									(*cache).get(room_id).unwrap().ongoing_trivia_game.is_some()
-								{
-									let trivia_info = &(*cache)
-										.get(room_id)
-										.unwrap()
-										.ongoing_trivia_game
-										.clone();
-
-									if let Some(ti) = trivia_info {
-										if ti.correct_answer.to_lowercase() == privmsg.message_text.to_lowercase() {
-											(*cache).remove(room_id);
-											correct = true;
-										}
-									}
-								}
-							}
-
-							if correct {
-								client.say(
-									privmsg.source.params[0][1..].to_owned(),
-									format!("@{} Correct!", privmsg.sender.name),
-								).await.unwrap();
-							}
+						// the model imitating the user is outdated now
+						if let (Ok(channel_id), Ok(sender_id)) = (privmsg.channel_id.parse(), privmsg.sender.id.parse()) {
+							state.markov_models.remove(&(channel_id, sender_id));
 						}
-					}
 
-					// check if message doesn't match any of the channel hooks
-					let mut matches                      = false;
-					let mut match_phrase: Option<String> = None;
-					if let Ok(cache) = channel_specifics_arc.lock() {
-						if (*cache).get(&privmsg.channel_id).is_some() {
-							let hooks = (*cache).get(&privmsg.channel_id).unwrap().hooks.clone();
-							
-							for hook in &hooks {
-								match hook.h_type {
-									crate::HookMatchType::Substring => {
-										if privmsg.message_text.to_lowercase().contains(&hook.capture_string.to_lowercase()) {
-											matches = true;
-											match_phrase = Some(hook.content.clone());
-										}
-									},
-									crate::HookMatchType::Exact     => {
-										if privmsg.message_text == hook.capture_string {
-											matches = true;
-											match_phrase = Some(hook.content.clone());
-										}
-									},
-								}
-							}
-						}
-					}
+						metrics().messages_received.with_label_values(&[&privmsg.channel_login]).inc();
+						metrics().channel_queue_depth.with_label_values(&[&privmsg.channel_login]).inc();
 
-					if matches {
-						client.say(
-							privmsg.source.params[0][1..].to_owned(),
-							match_phrase.unwrap(),
-						).await.unwrap();
-					}
+						let queue = channel_queues
+							.entry(privmsg.channel_id.clone())
+							.or_insert_with(|| spawn_channel_worker(state.clone()));
+
+						// the worker only goes away if it panicked, so start a new one
+						if let Err(SendError(privmsg)) = queue.send(privmsg) {
+							error!(channel = %privmsg.channel_login, "Channel worker died, restarting it");
+
+							let new_queue = spawn_channel_worker(state.clone());
+							new_queue.send(privmsg).ok();
+							*queue = new_queue;
+						}
+					},
+					// the bot (re)joined a channel
+					ServerMessage::Join(join) => {
+						info!("Joined #{}", join.channel_login);
+					},
+					ServerMessage::Part(part) => {
+						warn!("Left #{}", part.channel_login);
+					},
+					// the client reconnects (and rejoins) on its own
+					ServerMessage::Reconnect(_) => {
+						warn!("Twitch asked for a reconnect, reconnecting");
+					},
+					ServerMessage::Notice(notice) => {
+						warn!(channel = ?notice.channel_login, "Notice from Twitch: {}", notice.message_text);
+					},
+					_ => (),
 				}
 			}
+
+			error!("Connection to Twitch was closed");
 		})
 	};
 
 	let t = format!("{}", Local::now());
 	std::env::set_var("STARTUP_TIME", &t);
 
-	println!(
-		"{}   Bot is now running!\n          Local time is {}\n\n",
-		"SUCCESS".green().bold(),
-		&t[..t.len()-17]
-	);
+	info!("Bot is now running! Local time is {}", &t[..t.len()-17]);
+
+	// run until the listener stops or until asked to stop
+	tokio::select! {
+		_ = &mut message_listener_handle => (),
+		signal = shutdown_signal() => {
+			info!("Received {signal}, shutting down");
+		},
+	}
+
+	// stop taking in new messages
+	message_listener_handle.abort();
 
-    message_listener_handle.await.unwrap();
+	shutdown(state, log_writer_handle).await;
 
     Ok(())
-}
\ No newline at end of file
+}
+
+// resolves once the process is asked to terminate
+async fn shutdown_signal() -> &'static str {
+	#[cfg(unix)]
+	{
+		use tokio::signal::unix::{signal, SignalKind};
+
+		let mut sigterm = match signal(SignalKind::terminate()) {
+			Ok(s)  => s,
+			Err(e) => {
+				error!("Couldn't listen for SIGTERM; err: {e}");
+				tokio::signal::ctrl_c().await.ok();
+				return "SIGINT";
+			},
+		};
+
+		tokio::select! {
+			_ = tokio::signal::ctrl_c() => "SIGINT",
+			_ = sigterm.recv()          => "SIGTERM",
+		}
+	}
+
+	#[cfg(not(unix))]
+	{
+		tokio::signal::ctrl_c().await.ok();
+		"SIGINT"
+	}
+}
+
+// write everything that is still pending and keep
+// the state that would otherwise be lost on exit
+async fn shutdown(
+	state:             Arc<AppState>,
+	log_writer_handle: tokio::task::JoinHandle<()>,
+) {
+	// the channel workers finish the messages already queued to them
+	// (they stop once the listener is gone) and the commands let the chat know
+	if tokio::time::timeout(SHUTDOWN_TIMEOUT, state.tasks.wait()).await.is_err() {
+		warn!("{} task(s) didn't finish in time, stopping anyway", state.tasks.running());
+	}
+
+	state.log_writer.flush().await;
+
+	let channels = state.channel_specifics
+		.iter()
+		.map(|c| (c.key().clone(), c.value().clone()))
+		.collect::<Vec<_>>();
+
+	let mut saved_games = 0;
+	for (channel_id, channel) in channels {
+		if let Some(game) = &channel.lock().await.ongoing_trivia_game {
+			match db::save_trivia_game(&state.pool, &channel_id, game).await {
+				Ok(_)  => saved_games += 1,
+				Err(e) => error!("Couldn't save trivia game of channel {channel_id}; err: {e}"),
+			}
+		}
+	}
+
+	info!("Saved {saved_games} ongoing trivia game(s)");
+
+	// the writer stops once nothing can queue to it anymore
+	log_writer_handle.abort();
+	state.pool.close().await;
+
+	info!("Bot stopped");
+}
+// start a task handling the messages of a channel one by one
+fn spawn_channel_worker(state: Arc<AppState>) -> UnboundedSender<PrivmsgMessage> {
+	let (tx, mut rx) = mpsc::unbounded_channel::<PrivmsgMessage>();
+
+	state.tasks.clone().spawn(async move {
+		while let Some(privmsg) = rx.recv().await {
+			metrics().channel_queue_depth.with_label_values(&[&privmsg.channel_login]).dec();
+
+			let span = info_span!(
+				"message",
+				channel = %privmsg.channel_login,
+				user    = %privmsg.sender.login,
+			);
+
+			handle_privmsg(&state, privmsg).instrument(span).await;
+		}
+	});
+
+	tx
+}
+
+async fn handle_privmsg(state: &Arc<AppState>, privmsg: PrivmsgMessage) {
+	let AppState { pool, client, config, auth, .. } = &**state;
+	let channel_name = &privmsg.channel_login;
+
+	let sender_id = match privmsg.sender.id.parse::<i32>() {
+		Ok(id) => id,
+		Err(_) => {
+			error!("Malformed sender id \"{}\", skipping message", privmsg.sender.id);
+			return;
+		},
+	};
+
+	// keep track of the names users go by, so that
+	// a renamed user can still be found by the old one
+	// (the database only gets asked the first time a user is seen and once their name changes)
+	let name_is_known = state.user_names
+		.get(&sender_id)
+		.is_some_and(|name| *name == privmsg.sender.login);
+
+	if !name_is_known {
+		match db::update_user_name(
+			pool,
+			sender_id,
+			&privmsg.sender.login,
+			privmsg.server_timestamp,
+		).await {
+			Ok(old_name) => {
+				if let Some(old_name) = old_name {
+					info!("{old_name} is now known as {}", privmsg.sender.login);
+
+					state.name_id_cache.remove(&old_name);
+				}
+				state.user_names.insert(sender_id, privmsg.sender.login.clone());
+			},
+			Err(e) => error!("Couldn't update user name; err: {e}"),
+		};
+	}
+
+
+	match db::is_lurker(pool, sender_id).await {
+		Ok(Some(duration)) => say(
+			client,
+			channel_name,
+			format!("{} is no longer AFK ({})", privmsg.sender.name, fmt_duration(duration, false)),
+		).await,
+		Ok(None) => (),
+		Err(e)   => error!("Couldn't check lurk status; err: {e}"),
+	};
+
+	// check if user has any reminders set for them
+	let reminders = match db::check_for_reminders(pool, sender_id).await {
+		Ok(rs) => rs.unwrap_or_default(),
+		Err(e) => {
+			error!("Couldn't check for reminders; err: {e}");
+			vec![]
+		},
+	};
+
+	for r in &reminders {
+		let from_user = if r.from_user_id == r.for_user_id {
+			"yourself".into()
+		} else {
+			api::nick_from_id(r.from_user_id, auth)
+				.await
+				.unwrap_or_else(|_| r.from_user_id.to_string())
+		};
+
+		let sent = client.say(
+			channel_name.to_owned(),
+			commands::fit_message(format!("@{} 🔔🗨 {from_user}: {}", privmsg.sender.name, r.message))
+		).await;
+
+		// the reminder is already gone from the db, so put it
+		// back to be delivered next time instead of losing it
+		if let Err(e) = sent {
+			error!("Couldn't deliver a reminder, keeping it; err: {e}");
+
+			if let Err(e) = db::insert_reminder(pool, r).await {
+				error!("Couldn't save the reminder back; err: {e}");
+			}
+		}
+	}
+
+	// if message is a command, handle it
+	if privmsg.message_text.starts_with(config.prefix) {
+		let cmd_src = CommandSource::from_privmsg(privmsg.clone());
+
+		if commands::needs_channel_order(&cmd_src.cmd) {
+			handle_command(state, cmd_src).await;
+		} else {
+			// the rest doesn't need to hold up the channel
+			let state = state.clone();
+
+			state.tasks.clone().spawn(async move {
+				handle_command(&state, cmd_src).await;
+			}.in_current_span());
+		}
+	} else if let Ok(channel_id) = privmsg.channel_id.parse() {
+		let channel = Channel { id: channel_id, name: channel_name.to_owned() };
+
+		match commands::check_trivia_answer(state, &channel, sender_id, &privmsg.sender.name, &privmsg.message_text).await {
+			Ok(Some(response)) => say(client, channel_name, response).await,
+			Ok(None)           => (),
+			Err(e)             => error!("Couldn't check a trivia answer; err: {e}"),
+		}
+	}
+
+	// answer with the hooks of the channel the message sets off
+	if let Ok(channel_id) = privmsg.channel_id.parse() {
+		let channel = Channel { id: channel_id, name: channel_name.to_owned() };
+		let sender = Sender::from_privmsg(&privmsg);
+
+		let fired = commands::fire_hooks(state, &channel, &sender, &privmsg.message_text, Utc::now()).await;
+
+		// the commands of the responses don't hold up the channel either
+		if !fired.is_empty() {
+			let state = state.clone();
+
+			state.tasks.clone().spawn(async move {
+				for hook in fired {
+					if let Some(response) = commands::hook_response(&state, hook).await {
+						say(&state.client, &channel.name, response).await;
+					}
+				}
+			}.in_current_span());
+		}
+	}
+}
@@ -0,0 +1,674 @@
+use crate::{db, AppState, Channel, Config, EmoteCache, MyError};
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use dashmap::DashMap;
+use rand::Rng;
+use serde::{Serialize, Deserialize};
+use sqlx::sqlite::SqlitePool;
+use thiserror::Error;
+use tracing::error;
+use twitch_irc::message::PrivmsgMessage;
+
+// mark the start and the end of a message in the markov index
+// (control characters never make it into chat messages)
+pub const START: &str = "\u{2}";
+pub const END:   &str = "\u{3}";
+
+// the most words a chain can have
+pub const MAX_WORDS: usize = 100;
+// how many chains get built before giving up on finding
+// one that is long enough (and not a copy of a message)
+const ATTEMPTS: usize = 10;
+// how many of those may turn out to be copies before giving up
+// (each one is looked up in the chat logs of every channel drawn from)
+const COPY_ATTEMPTS: usize = 3;
+// at most how many (of the latest) messages of a user their model is built from
+const MAX_MODEL_MESSAGES: i64 = 10_000;
+// at most how many user models are kept around
+const MAX_CACHED_MODELS: usize = 64;
+// how many logged messages get indexed at once when rebuilding
+const REBUILD_BATCH_SIZE: i64 = 5_000;
+// `channel:all` draws from every channel that shares its corpus
+// (no Twitch name is this short)
+pub const ALL_CHANNELS: &str = "all";
+
+// how the markov chains are built;
+// is the `markov` section of the config file
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct MarkovConfig {
+	// how many of the preceding words decide the next one;
+	// only affects the messages indexed from then on
+	pub order:        usize,
+	// the defaults of the `$markov` options
+	pub min_words:    usize,
+	pub max_words:    usize,
+	pub allow_copies: bool,
+	// whether other channels may draw from a channel's corpus,
+	// unless the channel itself says otherwise with `$markov share`
+	pub share:        bool,
+}
+
+impl Default for MarkovConfig {
+	fn default() -> Self {
+		Self {
+			order:        2,
+			min_words:    3,
+			max_words:    30,
+			allow_copies: false,
+			share:        true,
+		}
+	}
+}
+
+// what kind of a chain to build
+#[derive(Debug, Clone)]
+pub struct MarkovRequest {
+	// the word to start with, else the chain starts
+	// the way some message has started
+	pub seed:         Option<String>,
+	// imitate only this user
+	pub user:         Option<String>,
+	// draw from this (tracked) channel instead,
+	// or from all of them if it's `ALL_CHANNELS`
+	pub channel:      Option<String>,
+	pub min_words:    usize,
+	pub max_words:    usize,
+	// whether the chain may be the same as some logged message
+	pub allow_copies: bool,
+}
+
+impl MarkovRequest {
+	pub fn new(config: &MarkovConfig) -> Self {
+		Self {
+			seed:         None,
+			user:         None,
+			channel:      None,
+			min_words:    config.min_words,
+			max_words:    config.max_words,
+			allow_copies: config.allow_copies,
+		}
+	}
+
+	// parse from the arguments of `$markov`, which are
+	// `[seed] [max words] [user:<name>] [channel:<name>] [min:<n>] [max:<n>] [copies:yes|no]`
+	pub fn from_args(
+		args:   &[String],
+		config: &MarkovConfig,
+	) -> Result<Self, String> {
+		let mut req = Self::new(config);
+		let mut positional = vec![];
+
+		for arg in args {
+			let (key, value) = match arg.split_once(':') {
+				Some((k, v)) => (k.to_lowercase(), v),
+				None         => {
+					positional.push(arg);
+					continue;
+				},
+			};
+
+			match key.as_str() {
+				"min"     => req.min_words = value.parse().map_err(|_| "expected positive integer".to_owned())?,
+				"max"     => req.max_words = value.parse().map_err(|_| "expected positive integer".to_owned())?,
+				"copies"  => req.allow_copies = matches!(value.to_lowercase().as_str(), "yes" | "true" | "on"),
+				"user"    => req.user = Some(value.trim_start_matches('@').to_lowercase()),
+				"channel" => req.channel = Some(value.trim_start_matches('#').to_lowercase()),
+				// (a link or whatever else, not an option)
+				_         => positional.push(arg),
+			}
+		}
+
+		if let Some(seed) = positional.first() {
+			req.seed = Some(seed.to_string());
+		}
+
+		if let Some(max) = positional.get(1) {
+			req.max_words = max.parse().map_err(|_| "expected positive integer".to_owned())?;
+		}
+
+		req.clamp_lengths();
+
+		Ok(req)
+	}
+
+	// keep the lengths within what makes sense
+	pub fn clamp_lengths(&mut self) {
+		self.max_words = self.max_words.clamp(1, MAX_WORDS);
+		self.min_words = self.min_words.min(self.max_words);
+	}
+}
+
+// the words of a message the way they get indexed
+pub fn tokens(
+	privmsg:     &PrivmsgMessage,
+	emote_cache: &EmoteCache,
+) -> Vec<String> {
+	privmsg.message_text
+		.split(' ')
+		.filter_map(|word| format_word(word, |w| emote_cache.self_or_privmsg_has_emote(privmsg, w)).ok().flatten())
+		.collect()
+}
+
+// the words of a logged message the way they get indexed;
+// since the message itself is gone, only the emotes
+// in the cache (not the Twitch ones) keep their case
+pub fn text_tokens(
+	text:         &str,
+	channel_name: &str,
+	emote_cache:  &EmoteCache,
+) -> Vec<String> {
+	let channel_emotes = emote_cache.channels.get(channel_name);
+	let is_emote = |w: &str| {
+		channel_emotes.is_some_and(|e| e.iter().any(|e| e == w)) ||
+		emote_cache.globals.iter().any(|e| e == w)
+	};
+
+	text
+		.split(' ')
+		.filter_map(|word| format_word(word, is_emote).ok().flatten())
+		.collect()
+}
+
+// the (state, successor) pairs of a message for every order up to `order`,
+// where the state is the words preceding the successor joined by spaces;
+// the lower orders are there to fall back on when the higher ones lead nowhere
+pub fn transitions(
+	tokens: &[String],
+	order:  usize,
+) -> Vec<(String, String)> {
+	if tokens.is_empty() {
+		return vec![];
+	}
+
+	let mut framed = vec![START];
+	framed.extend(tokens.iter().map(|t| t.as_str()));
+	framed.push(END);
+
+	let mut out = vec![];
+	for i in 1..framed.len() {
+		for o in 1..=order.min(i) {
+			out.push((framed[i-o..i].join(" "), framed[i].to_owned()));
+		}
+	}
+
+	out
+}
+
+// a markov model built in memory, out of the messages of a single user
+#[derive(Debug, Default)]
+pub struct MarkovModel {
+	// the (lowercase) states and their successors along with their counts
+	transitions: HashMap<String, Vec<(String, u32)>>,
+}
+
+impl MarkovModel {
+	// build from the tokens of each message
+	pub fn build(
+		messages: &[Vec<String>],
+		order:    usize,
+	) -> Self {
+		let mut counts: HashMap<String, HashMap<String, u32>> = HashMap::new();
+
+		for tokens in messages {
+			for (state, succ) in transitions(tokens, order) {
+				*counts
+					.entry(state.to_lowercase())
+					.or_default()
+					.entry(succ)
+					.or_default() += 1;
+			}
+		}
+
+		let transitions = counts
+			.into_iter()
+			.map(|(state, succs)| (state, succs.into_iter().collect()))
+			.collect();
+
+		Self { transitions }
+	}
+
+	// a random successor of a state, weighted the same way as in the index
+	fn rand_succ(&self, state: &str) -> Option<String> {
+		let succs = self.transitions.get(&state.to_lowercase())?;
+		let total: u32 = succs.iter().map(|(_, count)| count).sum();
+		let mut point = rand::thread_rng().gen_range(0..total);
+
+		for (succ, count) in succs {
+			if point < *count {
+				return Some(succ.clone());
+			}
+			point -= count;
+		}
+
+		None
+	}
+}
+
+// the user models built so far, by (channel id, user id)
+pub type MarkovModelCache = DashMap<(i32, i32), Arc<MarkovModel>>;
+
+// what a chain gets built from
+pub enum Corpus<'a> {
+	// the markov indexes of channels, merged together
+	Index(Vec<i32>),
+	// a model of a channel (by its id), in memory
+	Model(i32, &'a MarkovModel),
+}
+
+impl Corpus<'_> {
+	async fn rand_succ(
+		&self,
+		pool:  &SqlitePool,
+		state: &str,
+	) -> anyhow::Result<Option<String>> {
+		match self {
+			Self::Index(channel_ids) => db::get_rand_markov_succ(pool, channel_ids, state).await,
+			Self::Model(_, model)    => Ok(model.rand_succ(state)),
+		}
+	}
+
+	// the channels the chain gets built from
+	fn channel_ids(&self) -> &[i32] {
+		match self {
+			Self::Index(channel_ids)   => channel_ids,
+			Self::Model(channel_id, _) => std::slice::from_ref(channel_id),
+		}
+	}
+}
+
+#[derive(Debug, Error)]
+pub enum MarkovError {
+	#[error("💢 User `{0}` was not found")]
+	UserNotFound(String),
+	#[error("❌ {0} doesn't want to be imitated")]
+	OptedOut(String),
+	#[error("💢 Channel `{0}` isn't tracked")]
+	ChannelNotTracked(String),
+	#[error("❌ {0} doesn't share its markov chains")]
+	NotShared(String),
+	#[error("❌ users can only be imitated in one channel at a time")]
+	UserInAllChannels,
+	#[error(transparent)]
+	Other(#[from] anyhow::Error),
+}
+
+// build a chain the way `req` asks for, from the channel `channel`
+// unless `req` says otherwise
+pub async fn generate_for(
+	state:   &AppState,
+	channel: &Channel,
+	req:     &MarkovRequest,
+) -> Result<Option<String>, MarkovError> {
+	let order = state.config.markov.order;
+
+	let channels = match req.channel.as_deref() {
+		None               => vec![channel.clone()],
+		Some(ALL_CHANNELS) => {
+			// the channel itself is always in, no matter what it shares
+			let mut channels = vec![channel.clone()];
+
+			for other in state.tracked_channels() {
+				if other.id != channel.id && shares_corpus(state, other.id).await? {
+					channels.push(other);
+				}
+			}
+
+			channels
+		},
+		Some(name)         => {
+			let other = tracked_channel(state, name)?;
+
+			if other.id != channel.id && !shares_corpus(state, other.id).await? {
+				return Err(MarkovError::NotShared(other.name));
+			}
+
+			vec![other]
+		},
+	};
+
+	let user_name = match &req.user {
+		Some(name) => name,
+		None       => {
+			let channel_ids = channels.iter().map(|c| c.id).collect();
+
+			return Ok(generate(&state.pool, &Corpus::Index(channel_ids), order, req).await?);
+		},
+	};
+
+	let channel = match channels.as_slice() {
+		[channel] => channel,
+		_         => return Err(MarkovError::UserInAllChannels),
+	};
+
+	let user = match crate::resolve_user(user_name, state).await {
+		Ok(Some(user)) => user,
+		_              => return Err(MarkovError::UserNotFound(user_name.clone())),
+	};
+
+	if db::is_markov_opted_out(&state.pool, user.id).await? {
+		return Err(MarkovError::OptedOut(user.name));
+	}
+
+	let model = user_model(state, channel, user.id).await?;
+
+	Ok(generate(&state.pool, &Corpus::Model(channel.id, &model), order, req).await?)
+}
+
+// a channel the bot is in, by its name
+fn tracked_channel(
+	state: &AppState,
+	name:  &str,
+) -> Result<Channel, MarkovError> {
+	state
+		.tracked_channel(name)
+		.ok_or_else(|| MarkovError::ChannelNotTracked(name.to_owned()))
+}
+
+// whether other channels may draw from the corpus of a channel
+async fn shares_corpus(
+	state:      &AppState,
+	channel_id: i32,
+) -> anyhow::Result<bool> {
+	let shared = db::get_markov_sharing(&state.pool, channel_id)
+		.await?
+		.unwrap_or(state.config.markov.share);
+
+	Ok(shared)
+}
+
+// the model of a user's messages in a channel; built from the logs
+// the first time it's needed, then kept until the user says something new
+async fn user_model(
+	state:   &AppState,
+	channel: &Channel,
+	user_id: i32,
+) -> anyhow::Result<Arc<MarkovModel>> {
+	if let Some(model) = state.markov_models.get(&(channel.id, user_id)) {
+		return Ok(model.clone());
+	}
+
+	let messages = db::get_user_messages(&state.pool, channel.id, user_id, MAX_MODEL_MESSAGES).await?;
+
+	let tokens = {
+		let emote_cache = state.emote_cache.read().await;
+
+		messages
+			.iter()
+			.filter(|m| !m.starts_with(state.config.prefix))
+			.map(|m| text_tokens(m, &channel.name, &emote_cache))
+			.collect::<Vec<_>>()
+	};
+
+	let model = Arc::new(MarkovModel::build(&tokens, state.config.markov.order));
+
+	// (no need for anything smarter, building one is quick)
+	if state.markov_models.len() >= MAX_CACHED_MODELS {
+		state.markov_models.clear();
+	}
+	state.markov_models.insert((channel.id, user_id), model.clone());
+
+	Ok(model)
+}
+
+// build a chain from a corpus;
+// returns `None` if there was nothing to build it from
+pub async fn generate(
+	pool:   &SqlitePool,
+	corpus: &Corpus<'_>,
+	order:  usize,
+	req:    &MarkovRequest,
+) -> anyhow::Result<Option<String>> {
+	let mut copies = 0;
+
+	for _ in 0..ATTEMPTS {
+		let words = match chain(pool, corpus, order, req).await? {
+			Some(words) => words,
+			None        => return Ok(None),
+		};
+
+		if words.len() < req.min_words {
+			continue;
+		}
+
+		let text = words.join(" ");
+
+		if !req.allow_copies && was_logged(pool, corpus.channel_ids(), &text).await? {
+			copies += 1;
+			if copies >= COPY_ATTEMPTS {
+				break;
+			}
+
+			continue;
+		}
+
+		return Ok(Some(text));
+	}
+
+	Ok(None)
+}
+
+// how a rebuild of an index went
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct RebuildStats {
+	// messages that got indexed
+	pub indexed: usize,
+	// commands and messages of disregarded users
+	pub skipped: usize,
+}
+
+// index all of the logged messages of a channel anew, the same way
+// (and with the same order) as incoming messages get indexed now;
+// the old index is used until the new one is complete (including the messages
+// that came in in the meantime), `progress` gets called after every batch
+// with (messages done, messages in total); `None` if the channel is being rebuilt already
+pub async fn rebuild_index(
+	pool:        &SqlitePool,
+	channel:     &Channel,
+	config:      &Config,
+	emote_cache: &EmoteCache,
+	progress:    impl FnMut(usize, usize),
+) -> anyhow::Result<Option<RebuildStats>> {
+	if !db::start_markov_rebuild(pool, channel.id).await? {
+		return Ok(None);
+	}
+
+	let res = fill_rebuilt_index(pool, channel, config, emote_cache, progress).await;
+
+	if res.is_err() {
+		if let Err(e) = db::abort_markov_rebuild(pool, channel.id).await {
+			error!("Couldn't clean up the markov rebuild of #{}; err: {e}", channel.name);
+		}
+	}
+
+	res.map(Some)
+}
+
+async fn fill_rebuilt_index(
+	pool:         &SqlitePool,
+	channel:      &Channel,
+	config:       &Config,
+	emote_cache:  &EmoteCache,
+	mut progress: impl FnMut(usize, usize),
+) -> anyhow::Result<RebuildStats> {
+	let total = db::count_channel_messages(pool, channel.id).await? as usize;
+	let mut stats = RebuildStats::default();
+	let mut last_id = 0;
+
+	loop {
+		let batch = db::get_channel_messages_after(pool, channel.id, last_id, REBUILD_BATCH_SIZE).await?;
+		let last = match batch.last() {
+			Some(last) => last.id,
+			None       => break,
+		};
+
+		let pairs = count_pairs(&batch, channel, config, emote_cache, &mut stats);
+		db::write_markov_rebuild_batch(pool, channel.id, &pairs).await?;
+
+		last_id = last;
+		progress(stats.indexed + stats.skipped, total);
+	}
+
+	db::finish_markov_rebuild(pool, channel.id, last_id, |logged| {
+		count_pairs(logged, channel, config, emote_cache, &mut stats)
+	}).await?;
+
+	Ok(stats)
+}
+
+// the markov pairs of logged messages, counted
+fn count_pairs(
+	batch:       &[db::LoggedMessage],
+	channel:     &Channel,
+	config:      &Config,
+	emote_cache: &EmoteCache,
+	stats:       &mut RebuildStats,
+) -> HashMap<(String, String), i32> {
+	let mut pairs: HashMap<(String, String), i32> = HashMap::new();
+
+	for logged in batch {
+		let text = logged.message.as_deref().unwrap_or_default();
+
+		if text.starts_with(config.prefix) || config.disregarded_users.contains(&logged.sender_nick.to_lowercase()) {
+			stats.skipped += 1;
+			continue;
+		}
+
+		let tokens = text_tokens(text, &channel.name, emote_cache);
+		for pair in transitions(&tokens, config.markov.order) {
+			*pairs.entry(pair).or_default() += 1;
+		}
+
+		stats.indexed += 1;
+	}
+
+	pairs
+}
+
+// whether a message with exactly this text was logged in any of the channels
+async fn was_logged(
+	pool:        &SqlitePool,
+	channel_ids: &[i32],
+	text:        &str,
+) -> anyhow::Result<bool> {
+	for channel_id in channel_ids {
+		if db::message_was_logged(pool, *channel_id, text).await? {
+			return Ok(true);
+		}
+	}
+
+	Ok(false)
+}
+
+// walk the index until a message would end (or `max_words` is reached),
+// each time using as many of the preceding words as there is data for
+async fn chain(
+	pool:   &SqlitePool,
+	corpus: &Corpus<'_>,
+	order:  usize,
+	req:    &MarkovRequest,
+) -> anyhow::Result<Option<Vec<String>>> {
+	// the seed is one of the words, the start token isn't
+	let (mut tokens, max_tokens) = match &req.seed {
+		Some(seed) => (vec![seed.clone()],    req.max_words),
+		None       => (vec![START.to_owned()], req.max_words + 1),
+	};
+
+	while tokens.len() < max_tokens {
+		let mut next = None;
+
+		for o in (1..=order.min(tokens.len())).rev() {
+			let state = tokens[tokens.len()-o..].join(" ");
+
+			if let Some(succ) = corpus.rand_succ(pool, &state).await? {
+				next = Some(succ);
+				break;
+			}
+		}
+
+		match next {
+			Some(succ) if succ != END => tokens.push(succ),
+			// the message ends here (or nothing ever followed)
+			_                         => break,
+		}
+	}
+
+	let words = match req.seed {
+		Some(_) => tokens,
+		None    => tokens.split_off(1),
+	};
+
+	// nothing followed the seed (or the start)
+	if words.len() <= req.seed.is_some() as usize {
+		return Ok(None);
+	}
+
+	Ok(Some(words))
+}
+
+// format a word from a message into its form
+// in the index; `None` if it shouldn't be indexed
+fn format_word(
+	s:        &str,
+	is_emote: impl Fn(&str) -> bool,
+) -> anyhow::Result<Option<String>> {
+    let mut out = s.to_owned();
+    let invalid_front_chars = vec![
+		'"',
+		'\'',
+		'«',
+		'「',
+		'“',
+		'‘',
+		'(',
+		'[',
+		'{',
+		',',
+		'.',
+		';',
+		' ',
+		'⠀' // this is a "blank" braille character
+	];
+    let invalid_back_chars = vec![
+		'"',
+		'\'',
+		'»',
+		'」',
+		'”',
+		'’',
+		')',
+		']',
+		'}',
+		',',
+		'.',
+		';',
+		' ', // space
+		'⠀', // this is a "blank" braille character
+		'!',
+		'?'
+	];
+    // the invisible braille char
+
+    // shave off all trailing unwanted chars
+    while invalid_front_chars.contains(&out.chars().next().ok_or(MyError::OutOfBounds)?) {
+        out.remove(0);
+    }
+
+    while invalid_back_chars.contains(&out.chars().last().ok_or(MyError::OutOfBounds)?) {
+        out.pop();
+    }
+
+    // if there is still the blank braille's or it is a link
+	// don't remove anything; else remove the formatted word
+    if out.contains('⠀') || out.contains("//") || out.contains("www.") || out == *"" {
+        Ok(None)
+    } else {
+		match is_emote(&out) {
+			true  => Ok(Some(out)),
+			false => Ok(Some(out.to_lowercase()))
+		}
+
+    }
+}
@@ -0,0 +1,131 @@
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use prometheus::{
+	Encoder,
+	HistogramOpts,
+	HistogramVec,
+	Histogram,
+	IntCounterVec,
+	IntGauge,
+	IntGaugeVec,
+	Opts,
+	Registry,
+	TextEncoder,
+};
+
+// all of the metrics the bot keeps track of,
+// exposed in the Prometheus text format at `/metrics`
+// (see the `server` section of the config file)
+pub struct Metrics {
+	registry: Registry,
+
+	pub messages_received:    IntCounterVec,
+	pub commands_executed:    IntCounterVec,
+	pub commands_failed:      IntCounterVec,
+	pub command_duration:     HistogramVec,
+	pub api_request_duration: HistogramVec,
+	pub api_errors:           IntCounterVec,
+	pub db_write_duration:    Histogram,
+	pub log_queue_depth:      IntGauge,
+	pub channel_queue_depth:  IntGaugeVec,
+}
+
+// the buckets (in seconds) for everything that waits on something remote
+const LATENCY_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+impl Metrics {
+	fn new() -> Self {
+		let messages_received = IntCounterVec::new(
+			Opts::new("twitch_bot_messages_received_total", "Chat messages received"),
+			&["channel"],
+		).unwrap();
+		let commands_executed = IntCounterVec::new(
+			Opts::new("twitch_bot_commands_executed_total", "Commands executed"),
+			&["command"],
+		).unwrap();
+		let commands_failed = IntCounterVec::new(
+			Opts::new("twitch_bot_commands_failed_total", "Commands that ended with an error"),
+			&["command"],
+		).unwrap();
+		let command_duration = HistogramVec::new(
+			HistogramOpts::new("twitch_bot_command_duration_seconds", "How long commands take to execute")
+				.buckets(LATENCY_BUCKETS.to_vec()),
+			&["command"],
+		).unwrap();
+		let api_request_duration = HistogramVec::new(
+			HistogramOpts::new("twitch_bot_api_request_duration_seconds", "How long requests to external APIs take")
+				.buckets(LATENCY_BUCKETS.to_vec()),
+			&["host"],
+		).unwrap();
+		let api_errors = IntCounterVec::new(
+			Opts::new("twitch_bot_api_errors_total", "Failed requests to external APIs (retries included)"),
+			&["host", "kind"],
+		).unwrap();
+		let db_write_duration = Histogram::with_opts(
+			HistogramOpts::new("twitch_bot_db_write_duration_seconds", "How long writing a batch of logs takes")
+				.buckets(LATENCY_BUCKETS.to_vec()),
+		).unwrap();
+		let log_queue_depth = IntGauge::new(
+			"twitch_bot_log_queue_depth",
+			"Log and markov writes waiting to be written",
+		).unwrap();
+		let channel_queue_depth = IntGaugeVec::new(
+			Opts::new("twitch_bot_channel_queue_depth", "Chat messages waiting to be handled"),
+			&["channel"],
+		).unwrap();
+
+		let registry = Registry::new();
+		registry.register(Box::new(messages_received.clone())).unwrap();
+		registry.register(Box::new(commands_executed.clone())).unwrap();
+		registry.register(Box::new(commands_failed.clone())).unwrap();
+		registry.register(Box::new(command_duration.clone())).unwrap();
+		registry.register(Box::new(api_request_duration.clone())).unwrap();
+		registry.register(Box::new(api_errors.clone())).unwrap();
+		registry.register(Box::new(db_write_duration.clone())).unwrap();
+		registry.register(Box::new(log_queue_depth.clone())).unwrap();
+		registry.register(Box::new(channel_queue_depth.clone())).unwrap();
+
+		Self {
+			registry,
+			messages_received,
+			commands_executed,
+			commands_failed,
+			command_duration,
+			api_request_duration,
+			api_errors,
+			db_write_duration,
+			log_queue_depth,
+			channel_queue_depth,
+		}
+	}
+
+	// record a finished command
+	pub fn observe_command(&self, command: &str, latency: Duration, failed: bool) {
+		self.commands_executed.with_label_values(&[command]).inc();
+		self.command_duration.with_label_values(&[command]).observe(latency.as_secs_f64());
+
+		if failed {
+			self.commands_failed.with_label_values(&[command]).inc();
+		}
+	}
+
+	// everything in the Prometheus text exposition format
+	pub fn render(&self) -> String {
+		let mut buf = vec![];
+
+		if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut buf) {
+			tracing::error!("Couldn't encode metrics; err: {e}");
+		}
+
+		String::from_utf8(buf).unwrap_or_default()
+	}
+}
+
+static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+// the metrics of the bot; they are collected whether
+// or not anything is serving them, which is dirt cheap
+pub fn metrics() -> &'static Metrics {
+	METRICS.get_or_init(Metrics::new)
+}
@@ -0,0 +1,680 @@
+use crate::commands::{channel_command_name, CHANNEL_COMMAND_TYPES};
+use crate::metrics::metrics;
+use crate::{db, markov, AppState, Channel, HookMatchType, MessageHook, TwitchBadge};
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use chrono::Utc;
+use hyper::body::HttpBody;
+use hyper::header::{AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::server::conn::AddrIncoming;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use percent_encoding::percent_decode_str;
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, warn};
+
+// how many items a page has unless asked otherwise
+const DEFAULT_PAGE_SIZE: i64 = 50;
+// the most items a page can have
+const MAX_PAGE_SIZE: i64 = 500;
+// the biggest body a request can have (in bytes)
+const MAX_BODY_SIZE: usize = 64 * 1024;
+
+// the optional HTTP server of the bot;
+// is the `server` section of the config file
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ServerConfig {
+	// where to listen, e.g. `127.0.0.1:9100`;
+	// the server doesn't run unless this is set; anyone who can reach it
+	// can read the dashboard, so anything but localhost makes it public
+	pub listen:     Option<SocketAddr>,
+	// whether to serve the metrics at `/metrics`
+	pub metrics:    bool,
+	// whether to serve the dashboard at `/` (and its data at `/dashboard`)
+	pub dashboard:  bool,
+	// who may edit the commands and hooks through the dashboard
+	pub mod_tokens: Vec<ModToken>,
+	// whether to serve the JSON API at `/api/v1`
+	pub api:        bool,
+	// who may use the JSON API
+	pub api_keys:   Vec<ApiKey>,
+}
+
+impl Default for ServerConfig {
+	fn default() -> Self {
+		Self {
+			listen:     None,
+			metrics:    true,
+			dashboard:  false,
+			mod_tokens: vec![],
+			api:        false,
+			api_keys:   vec![],
+		}
+	}
+}
+
+// a secret a mod sends along (as `Authorization: Bearer <token>`)
+// to be allowed to make changes in the listed channels
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ModToken {
+	// who the token belongs to, only used for logging
+	pub name:     String,
+	pub token:    String,
+	pub channels: Vec<String>,
+}
+
+// a key an external tool sends along (as `X-Api-Key: <key>`)
+// to be allowed to use the JSON API
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ApiKey {
+	// who the key belongs to, only used for logging
+	pub name: String,
+	pub key:  String,
+}
+
+// bind to `addr` and serve in the background;
+// returns the address actually bound to (in case the port was 0)
+pub fn spawn(
+	state: Arc<AppState>,
+	addr:  SocketAddr,
+) -> anyhow::Result<(SocketAddr, JoinHandle<()>)> {
+	let incoming = AddrIncoming::bind(&addr)?;
+	let local_addr = incoming.local_addr();
+
+	if state.config.server.dashboard && !addr.ip().is_loopback() {
+		warn!("The dashboard (with the logs of the channels) can be read without a token by anyone who can reach {addr}");
+	}
+
+	let make_service = make_service_fn(move |_| {
+		let state = state.clone();
+
+		async move {
+			Ok::<_, Infallible>(service_fn(move |req| respond(state.clone(), req)))
+		}
+	});
+
+	let handle = tokio::spawn(async move {
+		if let Err(e) = Server::builder(incoming).serve(make_service).await {
+			error!("HTTP server stopped; err: {e}");
+		}
+	});
+
+	Ok((local_addr, handle))
+}
+
+// an error response
+#[derive(Debug)]
+pub struct HttpError {
+	status:  StatusCode,
+	message: String,
+}
+
+impl HttpError {
+	fn new(status: StatusCode, message: impl Into<String>) -> Self {
+		Self { status, message: message.into() }
+	}
+
+	fn not_found() -> Self {
+		Self::new(StatusCode::NOT_FOUND, "not found")
+	}
+
+	fn bad_request(message: impl Into<String>) -> Self {
+		Self::new(StatusCode::BAD_REQUEST, message)
+	}
+}
+
+impl From<anyhow::Error> for HttpError {
+	fn from(e: anyhow::Error) -> Self {
+		error!("Couldn't handle HTTP request; err: {e}");
+
+		Self::new(StatusCode::INTERNAL_SERVER_ERROR, "internal error")
+	}
+}
+
+type HttpResult = Result<Response<Body>, HttpError>;
+
+async fn respond(
+	state: Arc<AppState>,
+	req:   Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+	let res = route(&state, req)
+		.await
+		.unwrap_or_else(|e| json_response(e.status, &serde_json::json!({ "error": e.message })));
+
+	Ok(res)
+}
+
+async fn route(
+	state: &AppState,
+	req:   Request<Body>,
+) -> HttpResult {
+	let config = &state.config.server;
+
+	let path = req
+		.uri()
+		.path()
+		.trim_matches('/')
+		.split('/')
+		.map(|s| percent_decode_str(s).decode_utf8_lossy().into_owned())
+		.collect::<Vec<String>>();
+	let path = path.iter().map(|s| s.as_str()).collect::<Vec<&str>>();
+
+	match (req.method(), path.as_slice()) {
+		(&Method::GET, ["metrics"]) if config.metrics => Response::builder()
+			.header(CONTENT_TYPE, "text/plain; version=0.0.4")
+			.body(Body::from(metrics().render()))
+			.map_err(|e| anyhow::anyhow!(e).into()),
+		(_, [""]) | (_, ["dashboard", ..]) if config.dashboard => dashboard(state, req, &path).await,
+		(_, ["api", "v1", ..]) if config.api => api(state, req, &path[2..]).await,
+		_ => Err(HttpError::not_found()),
+	}
+}
+
+// —————————————————————————————————————————
+//               Dashboard
+// —————————————————————————————————————————
+
+async fn dashboard(
+	state: &AppState,
+	req:   Request<Body>,
+	path:  &[&str],
+) -> HttpResult {
+	let query = query_params(&req);
+
+	match (req.method(), path) {
+		(&Method::GET, [""]) => Response::builder()
+			.header(CONTENT_TYPE, "text/html; charset=utf-8")
+			.body(Body::from(include_str!("../assets/dashboard.html")))
+			.map_err(|e| anyhow::anyhow!(e).into()),
+
+		(&Method::GET, ["dashboard", "channels"]) => Ok(json_response(StatusCode::OK, &state.tracked_channels())),
+
+		(&Method::GET, ["dashboard", "channels", channel, "logs"]) => {
+			let channel = find_channel(state, channel)?;
+			let (before, limit) = page_params(&query)?;
+			let sender_id = match query.get("user") {
+				Some(user) => Some(find_user(state, user).await?.id),
+				None       => None,
+			};
+
+			let messages = db::get_channel_messages(&state.pool, channel.id, sender_id, before, limit).await?;
+
+			Ok(page_response(messages, limit, |m| m.id))
+		},
+
+		(&Method::GET, ["dashboard", "channels", channel, "chatstats"]) => {
+			let channel = find_channel(state, channel)?;
+			let period = match query.get("period") {
+				Some(p) => db::ChatStatPeriod::from_str(p).map_err(|_| HttpError::bad_request("unknown period"))?,
+				None    => db::ChatStatPeriod::Alltime,
+			};
+			let top = match query.get("top") {
+				Some(n) => n.parse::<u8>().map_err(|_| HttpError::bad_request("`top` has to be a number"))?,
+				None    => 10,
+			};
+
+			let stats = db::get_channel_chat_stats(
+				&state.pool,
+				&channel,
+				&state.auth,
+				period,
+				db::ChatStatsMode::Top(top),
+			).await?;
+
+			#[derive(Serialize)]
+			struct ChatStat {
+				user_id:   i32,
+				user_name: Option<String>,
+				messages:  i32,
+			}
+
+			let mut out = vec![];
+			for stat in stats {
+				out.push(ChatStat {
+					user_id:   stat.0,
+					user_name: db::user_name_from_id(&state.pool, stat.0).await?,
+					messages:  stat.1,
+				});
+			}
+
+			Ok(json_response(StatusCode::OK, &out))
+		},
+
+		(&Method::GET, ["dashboard", "channels", channel, "commands"]) => {
+			let channel = find_channel(state, channel)?;
+
+			Ok(json_response(StatusCode::OK, &db::get_channel_commands(&state.pool, channel.id).await?))
+		},
+
+		(&Method::PUT, ["dashboard", "channels", channel, "commands", name]) => {
+			let (channel, mod_name) = authorize(state, &req, channel).await?;
+
+			#[derive(Deserialize)]
+			struct NewCommand {
+				#[serde(rename = "type")]
+				cmd_type:   String,
+				expression: String,
+			}
+
+			let name = channel_command_name(name, state.config.prefix).map_err(HttpError::bad_request)?;
+
+			let cmd: NewCommand = json_body(req).await?;
+			if !CHANNEL_COMMAND_TYPES.contains(&cmd.cmd_type.as_str()) {
+				return Err(HttpError::bad_request("command type not recognized"));
+			}
+
+			db::set_cmd(&state.pool, channel.id, &name, &cmd.cmd_type, &cmd.expression, &mod_name, Utc::now()).await?;
+			info!(channel = %channel.name, "{mod_name} set command `{name}` through the dashboard");
+
+			Ok(json_response(StatusCode::OK, &serde_json::json!({ "name": name })))
+		},
+
+		(&Method::DELETE, ["dashboard", "channels", channel, "commands", name]) => {
+			let (channel, mod_name) = authorize(state, &req, channel).await?;
+
+			if db::remove_channel_command(&state.pool, channel.id, name).await? == 0 {
+				return Err(HttpError::not_found());
+			}
+			info!(channel = %channel.name, "{mod_name} removed command `{name}` through the dashboard");
+
+			Ok(json_response(StatusCode::OK, &serde_json::json!({ "name": name })))
+		},
+
+		(&Method::GET, ["dashboard", "channels", channel, "hooks"]) => {
+			let channel = find_channel(state, channel)?;
+
+			Ok(json_response(StatusCode::OK, &db::get_saved_hooks(&state.pool, channel.id).await?))
+		},
+
+		(&Method::PUT, ["dashboard", "channels", channel, "hooks", name]) => {
+			let (channel, mod_name) = authorize(state, &req, channel).await?;
+
+			#[derive(Deserialize)]
+			struct NewHook {
+				#[serde(rename = "type")]
+				h_type:         String,
+				capture_string: String,
+				content:        String,
+				#[serde(default)]
+				cooldown_s:     u64,
+				#[serde(default)]
+				users:          Vec<String>,
+				#[serde(default)]
+				badges:         Vec<String>,
+				#[serde(default = "enabled")]
+				enabled:        bool,
+			}
+
+			fn enabled() -> bool { true }
+
+			let new_hook: NewHook = json_body(req).await?;
+			let h_type = HookMatchType::from_str(&new_hook.h_type).map_err(|_| HttpError::bad_request("hook type not valid"))?;
+			let mut hook = MessageHook::new(name.to_string(), h_type, new_hook.capture_string, new_hook.content)
+				.map_err(|_| HttpError::bad_request("the catchphrase is not a valid regex"))?;
+
+			hook.cooldown_s = new_hook.cooldown_s;
+			hook.users      = new_hook.users.iter().map(|u| u.to_lowercase()).collect();
+			hook.badges     = new_hook.badges.iter().map(|b| TwitchBadge::from_name(b)).collect();
+			hook.enabled    = new_hook.enabled;
+
+			if hook.badges.contains(&TwitchBadge::Unrecognized) {
+				return Err(HttpError::bad_request("unknown badge"));
+			}
+
+			// the hook replaces the one of the same name
+			db::set_hook(&state.pool, channel.id, &hook).await?;
+
+			{
+				let channel = state.channel(&channel.id.to_string());
+				let mut channel = channel.lock().await;
+
+				if let Some(old) = channel.hooks.iter().find(|h| h.name == hook.name) {
+					hook.count = old.count;
+				}
+				channel.hooks.retain(|h| h.name != hook.name);
+				channel.hooks.push(hook);
+			}
+			info!(channel = %channel.name, "{mod_name} set hook `{name}` through the dashboard");
+
+			Ok(json_response(StatusCode::OK, &serde_json::json!({ "name": name })))
+		},
+
+		(&Method::DELETE, ["dashboard", "channels", channel, "hooks", name]) => {
+			let (channel, mod_name) = authorize(state, &req, channel).await?;
+
+			if !db::remove_hook(&state.pool, channel.id, name).await? {
+				return Err(HttpError::not_found());
+			}
+
+			state.channel(&channel.id.to_string())
+				.lock()
+				.await
+				.hooks
+				.retain(|h| h.name != *name);
+			info!(channel = %channel.name, "{mod_name} removed hook `{name}` through the dashboard");
+
+			Ok(json_response(StatusCode::OK, &serde_json::json!({ "name": name })))
+		},
+
+		(&Method::GET, ["dashboard", "reminders"]) => {
+			let (before, limit) = page_params(&query)?;
+			let reminders = db::get_pending_reminders(&state.pool, before, limit).await?;
+
+			Ok(page_response(reminders, limit, |r| r.id as i64))
+		},
+
+		(&Method::GET, ["dashboard", "command_history"]) => {
+			let (before, limit) = page_params(&query)?;
+			let history = db::get_command_history(&state.pool, before, limit).await?;
+
+			Ok(page_response(history, limit, |c| c.id))
+		},
+
+		_ => Err(HttpError::not_found()),
+	}
+}
+
+// —————————————————————————————————————————
+//               JSON API
+// —————————————————————————————————————————
+
+// the same data the chat commands give, for other tools to use
+async fn api(
+	state: &AppState,
+	req:   Request<Body>,
+	path:  &[&str],
+) -> HttpResult {
+	let key = req
+		.headers()
+		.get("X-Api-Key")
+		.and_then(|h| h.to_str().ok())
+		.ok_or_else(|| HttpError::new(StatusCode::UNAUTHORIZED, "missing API key"))?;
+
+	let key = state.config.server.api_keys
+		.iter()
+		.find(|k| secrets_match(&k.key, key))
+		.ok_or_else(|| HttpError::new(StatusCode::UNAUTHORIZED, "invalid API key"))?;
+
+	debug!(key = %key.name, path = %req.uri().path(), "API request");
+
+	let query = query_params(&req);
+
+	match (req.method(), path) {
+		(&Method::GET, ["users", user, "last_seen"]) => {
+			let user = find_user(state, user).await?;
+			let channels = state.tracked_channels();
+
+			let (channel, timestamp) = db::last_seen(&state.pool, &channels, user.id)
+				.await?
+				.ok_or_else(|| HttpError::new(StatusCode::NOT_FOUND, "user not found in records"))?;
+
+			Ok(json_response(StatusCode::OK, &serde_json::json!({
+				"user":      user,
+				"channel":   channel,
+				"timestamp": timestamp,
+			})))
+		},
+
+		(&Method::GET, ["channels", channel, "users", user, "first_message"]) => {
+			let channel = find_channel(state, channel)?;
+			let user = find_user(state, user).await?;
+
+			let message = db::get_first_message(&state.pool, user.id, channel.id)
+				.await?
+				.ok_or_else(|| HttpError::new(StatusCode::NOT_FOUND, "no messages of the user logged"))?;
+
+			Ok(json_response(StatusCode::OK, &serde_json::json!({
+				"user":    user,
+				"channel": channel,
+				"message": message,
+			})))
+		},
+
+		(&Method::GET, ["channels", channel, "users", user, "messages"]) => {
+			let channel = find_channel(state, channel)?;
+			let user = find_user(state, user).await?;
+			let (before, limit) = page_params(&query)?;
+
+			let messages = db::get_channel_messages(&state.pool, channel.id, Some(user.id), before, limit).await?;
+
+			Ok(page_response(messages, limit, |m| m.id))
+		},
+
+		(&Method::GET, ["channels", channel, "users", user, "offline_time"]) => {
+			let channel = find_channel(state, channel)?;
+			let user = find_user(state, user).await?;
+
+			let time = db::get_offline_time(&state.pool, channel.id, user.id).await?;
+
+			Ok(json_response(StatusCode::OK, &serde_json::json!({
+				"user":    user,
+				"channel": channel,
+				"seconds": time.num_seconds(),
+			})))
+		},
+
+		(&Method::GET, ["channels", channel, "chatstats"]) => {
+			let channel = find_channel(state, channel)?;
+			let period = match query.get("period") {
+				Some(p) => db::ChatStatPeriod::from_str(p).map_err(|_| HttpError::bad_request("unknown period"))?,
+				None    => db::ChatStatPeriod::Alltime,
+			};
+			let offset = match query.get("offset") {
+				Some(o) => o.parse::<usize>().map_err(|_| HttpError::bad_request("`offset` has to be a number"))?,
+				None    => 0,
+			};
+			let (_, limit) = page_params(&query)?;
+
+			// the ranking is only ever so long
+			let top = (offset + limit as usize).min(u8::MAX as usize) as u8;
+
+			let stats = db::get_channel_chat_stats(
+				&state.pool,
+				&channel,
+				&state.auth,
+				period,
+				db::ChatStatsMode::Top(top),
+			).await?;
+
+			let mut items = vec![];
+			for (i, stat) in stats.iter().enumerate().skip(offset) {
+				items.push(serde_json::json!({
+					"rank":      i + 1,
+					"user_id":   stat.0,
+					"user_name": db::user_name_from_id(&state.pool, stat.0).await?,
+					"messages":  stat.1,
+				}));
+			}
+
+			let next = match stats.len() == top as usize && top < u8::MAX {
+				true  => Some(offset + items.len()),
+				false => None,
+			};
+
+			Ok(json_response(StatusCode::OK, &serde_json::json!({ "items": items, "next": next })))
+		},
+
+		(&Method::GET, ["channels", channel, "markov"]) => {
+			let channel = find_channel(state, channel)?;
+			let config = &state.config.markov;
+			let mut req = markov::MarkovRequest::new(config);
+			req.seed = query.get("seed").cloned();
+			if let Some(w) = query.get("words") {
+				req.max_words = w.parse::<usize>().map_err(|_| HttpError::bad_request("`words` has to be a number"))?;
+			}
+			if let Some(w) = query.get("min_words") {
+				req.min_words = w.parse::<usize>().map_err(|_| HttpError::bad_request("`min_words` has to be a number"))?;
+			}
+			if let Some(c) = query.get("allow_copies") {
+				req.allow_copies = c == "true";
+			}
+			req.user = query.get("user").map(|u| u.to_lowercase());
+			req.channel = query.get("from").map(|c| c.to_lowercase());
+			req.clamp_lengths();
+
+			let text = markov::generate_for(state, &channel, &req)
+				.await
+				.map_err(|e| match e {
+					markov::MarkovError::Other(e)             => HttpError::from(e),
+					markov::MarkovError::OptedOut(_) |
+					markov::MarkovError::NotShared(_)         => HttpError::new(StatusCode::FORBIDDEN, e.to_string()),
+					markov::MarkovError::UserNotFound(_) |
+					markov::MarkovError::ChannelNotTracked(_) => HttpError::new(StatusCode::NOT_FOUND, e.to_string()),
+					markov::MarkovError::UserInAllChannels    => HttpError::bad_request(e.to_string()),
+				})?
+				.ok_or_else(|| HttpError::new(StatusCode::NOT_FOUND, "nothing to build a chain from"))?;
+
+			Ok(json_response(StatusCode::OK, &serde_json::json!({ "text": text })))
+		},
+
+		_ => Err(HttpError::not_found()),
+	}
+}
+
+// look up any user by their (current or previous) name
+async fn find_user(
+	state: &AppState,
+	name:  &str,
+) -> Result<Channel, HttpError> {
+	match crate::resolve_user(&name.to_lowercase(), state).await {
+		Ok(Some(user)) => Ok(user),
+		Ok(None)       => Err(HttpError::new(StatusCode::NOT_FOUND, "user not found")),
+		Err(_)         => Err(HttpError::new(StatusCode::BAD_GATEWAY, "couldn't look up the user")),
+	}
+}
+
+// look up a channel the bot is in by its name
+fn find_channel(
+	state: &AppState,
+	name:  &str,
+) -> Result<Channel, HttpError> {
+	state
+		.tracked_channel(name)
+		.ok_or_else(|| HttpError::new(StatusCode::NOT_FOUND, "channel not found"))
+}
+
+// check that the request comes from a mod of the channel;
+// returns the channel and the name of the mod
+async fn authorize(
+	state:   &AppState,
+	req:     &Request<Body>,
+	channel: &str,
+) -> Result<(Channel, String), HttpError> {
+	let token = req
+		.headers()
+		.get(AUTHORIZATION)
+		.and_then(|h| h.to_str().ok())
+		.and_then(|h| h.strip_prefix("Bearer "))
+		.ok_or_else(|| HttpError::new(StatusCode::UNAUTHORIZED, "missing token"))?;
+
+	let mod_token = state.config.server.mod_tokens
+		.iter()
+		.find(|t| secrets_match(&t.token, token))
+		.ok_or_else(|| HttpError::new(StatusCode::UNAUTHORIZED, "invalid token"))?;
+
+	let channel = find_channel(state, channel)?;
+
+	if !mod_token.channels.iter().any(|c| c.to_lowercase() == channel.name) {
+		return Err(HttpError::new(StatusCode::FORBIDDEN, "not a mod of this channel"));
+	}
+
+	Ok((channel, mod_token.name.clone()))
+}
+
+// —————————————————————————————————————————
+//               Helpers
+// —————————————————————————————————————————
+
+// compare a secret with what was sent in a time that doesn't depend on
+// how much of it was right, so that it can't be guessed byte by byte
+fn secrets_match(secret: &str, sent: &str) -> bool {
+	let (secret, sent) = (secret.as_bytes(), sent.as_bytes());
+
+	secret.len() == sent.len()
+		&& secret.iter().zip(sent).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+fn query_params(req: &Request<Body>) -> HashMap<String, String> {
+	url::form_urlencoded::parse(req.uri().query().unwrap_or_default().as_bytes())
+		.into_owned()
+		.collect()
+}
+
+// the `before` cursor and the `limit` of a paginated request
+fn page_params(query: &HashMap<String, String>) -> Result<(Option<i64>, i64), HttpError> {
+	let before = match query.get("before") {
+		Some(b) => Some(b.parse::<i64>().map_err(|_| HttpError::bad_request("`before` has to be a number"))?),
+		None    => None,
+	};
+
+	let limit = match query.get("limit") {
+		Some(l) => l.parse::<i64>().map_err(|_| HttpError::bad_request("`limit` has to be a number"))?,
+		None    => DEFAULT_PAGE_SIZE,
+	};
+
+	Ok((before, limit.clamp(1, MAX_PAGE_SIZE)))
+}
+
+// a page of items along with the cursor of the next page
+// (if there might be one)
+fn page_response<T: Serialize>(
+	items: Vec<T>,
+	limit: i64,
+	id:    impl Fn(&T) -> i64,
+) -> Response<Body> {
+	let next = match items.len() as i64 == limit {
+		true  => items.last().map(id),
+		false => None,
+	};
+
+	json_response(StatusCode::OK, &serde_json::json!({ "items": items, "next": next }))
+}
+
+fn json_response<T: Serialize + ?Sized>(
+	status: StatusCode,
+	body:   &T,
+) -> Response<Body> {
+	let body = serde_json::to_vec(body).unwrap_or_default();
+
+	Response::builder()
+		.status(status)
+		.header(CONTENT_TYPE, "application/json")
+		.body(Body::from(body))
+		.unwrap_or_else(|_| Response::new(Body::empty()))
+}
+
+// the JSON body of a request, read only up to `MAX_BODY_SIZE`
+async fn json_body<T: DeserializeOwned>(req: Request<Body>) -> Result<T, HttpError> {
+	let too_large = || HttpError::new(StatusCode::PAYLOAD_TOO_LARGE, "body too large");
+
+	let length = req
+		.headers()
+		.get(CONTENT_LENGTH)
+		.and_then(|h| h.to_str().ok())
+		.and_then(|h| h.parse::<usize>().ok());
+	if length.is_some_and(|l| l > MAX_BODY_SIZE) {
+		return Err(too_large());
+	}
+
+	// (the body may be chunked, i.e. without a length up front)
+	let mut body = req.into_body();
+	let mut bytes = vec![];
+	while let Some(chunk) = body.data().await {
+		let chunk = chunk.map_err(|_| HttpError::bad_request("couldn't read the body"))?;
+
+		if bytes.len() + chunk.len() > MAX_BODY_SIZE {
+			return Err(too_large());
+		}
+		bytes.extend_from_slice(&chunk);
+	}
+
+	serde_json::from_slice(&bytes).map_err(|e| HttpError::bad_request(format!("bad body: {e}")))
+}
@@ -0,0 +1,235 @@
+use crate::{api, commands, fmt_duration, AppState, CommandSource};
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use async_recursion::async_recursion;
+use rand::prelude::SliceRandom;
+use tracing::warn;
+
+// how deep commands of templates may run commands of their own
+// (which also keeps a command that runs itself from going on forever)
+pub const MAX_TEMPLATE_DEPTH: u32 = 4;
+// how many commands a template may run in all (including the ones its commands run)
+pub const MAX_TEMPLATE_COMMANDS: u32 = 10;
+
+tokio::task_local! {
+	// the commands run by the template being filled in (and by the ones above it)
+	static NESTING: Nesting;
+}
+
+#[derive(Clone, Default)]
+struct Nesting {
+	// how many templates deep the command being run is
+	depth: u32,
+	// how many commands have been run since the outermost template
+	runs:  Arc<AtomicU32>,
+}
+
+// what a template of a channel command (or the response of a hook) gets filled in with
+pub struct TemplateContext<'a> {
+	pub state: &'a AppState,
+	// the command being run (whose sender, channel and args the template gets);
+	// for a hook, the args are the groups its regex captured
+	pub cmd:   &'a CommandSource,
+	// how many times the command has been used (or the hook has gone off)
+	pub count: i64,
+}
+
+// fill in a template of a channel command; it knows
+// - `{sender}`, `{channel}`, `{count}` (of the uses of the command) and `{uptime}` (of the channel's stream)
+// - `{args}` (all of them) and `{arg:2}` or `{2}` (the second one)
+// - `{random:a|b|c}`, one of the options
+// - `{$command args}`, the output of another command (whose args may be templates themselves)
+// - `{variable=default}`, which is the default when there's no such value
+// the `{variables}` it doesn't know stay as they are
+pub async fn fill(ctx: &TemplateContext<'_>, template: &str) -> String {
+	// (the outermost template keeps count of the commands run by all of them)
+	match NESTING.try_with(|_| ()) {
+		Ok(_)  => fill_in(ctx, template).await,
+		Err(_) => NESTING.scope(Nesting::default(), fill_in(ctx, template)).await,
+	}
+}
+
+// run a command the way `{$command}` in a template does
+pub async fn run(ctx: &TemplateContext<'_>, command: &str) -> Option<String> {
+	match NESTING.try_with(|_| ()) {
+		Ok(_)  => run_command(ctx, command).await,
+		Err(_) => NESTING.scope(Nesting::default(), run_command(ctx, command)).await,
+	}
+}
+
+#[async_recursion]
+async fn fill_in(ctx: &TemplateContext<'_>, template: &str) -> String {
+	let mut filled = String::with_capacity(template.len());
+	let mut rest = template;
+
+	while let Some(start) = rest.find('{') {
+		filled.push_str(&rest[..start]);
+		rest = &rest[start..];
+
+		let end = match closing_brace(rest) {
+			Some(end) => end,
+			None      => break,
+		};
+
+		match evaluate(ctx, &rest[1..end]).await {
+			Some(value) => filled.push_str(&value),
+			None        => filled.push_str(&rest[..=end]),
+		}
+		rest = &rest[end + 1..];
+	}
+	filled.push_str(rest);
+
+	filled
+}
+
+// the value of what's in between a pair of braces, `None` if it isn't known
+async fn evaluate(ctx: &TemplateContext<'_>, expr: &str) -> Option<String> {
+	if let Some(command) = expr.strip_prefix(ctx.state.config.prefix) {
+		return run_command(ctx, command).await;
+	}
+
+	let (var, default) = match split_once_outside_braces(expr, '=') {
+		Some((var, default)) => (var, Some(default)),
+		None                 => (expr, None),
+	};
+	let (name, param) = match var.split_once(':') {
+		Some((name, param)) => (name, Some(param)),
+		None                => (var, None),
+	};
+
+	let value = match (name, param) {
+		("sender", None)      => Some(ctx.cmd.sender.name.clone()),
+		("channel", None)     => Some(ctx.cmd.channel.name.clone()),
+		("count", None)       => Some(ctx.count.to_string()),
+		("args", None)        => Some(ctx.cmd.args.join(" ")),
+		("arg", Some(n))      => nth_arg(ctx.cmd, n)?,
+		(n, None) if n.parse::<usize>().is_ok() => nth_arg(ctx.cmd, n)?,
+		("uptime", None)      => uptime(ctx).await,
+		("random", Some(options)) => {
+			let options = split_outside_braces(options, '|');
+			let option = *options.choose(&mut rand::thread_rng())?;
+
+			Some(fill_in(ctx, option).await)
+		},
+		_                     => return None,
+	};
+
+	match (value.filter(|v| !v.is_empty()), default) {
+		(Some(value), _)      => Some(value),
+		(None, Some(default)) => Some(fill_in(ctx, default).await),
+		(None, None)          => Some(String::new()),
+	}
+}
+
+// the n-th (from 1) arg of a command; `None` if `n` isn't a number
+fn nth_arg(cmd: &CommandSource, n: &str) -> Option<Option<String>> {
+	let n = n.parse::<usize>().ok()?;
+
+	Some(n.checked_sub(1).and_then(|i| cmd.args.get(i)).cloned())
+}
+
+// how long the channel has been live for, `None` if it's offline
+async fn uptime(ctx: &TemplateContext<'_>) -> Option<String> {
+	match api::get_stream_uptime(&ctx.state.auth, &ctx.cmd.channel.name).await {
+		Ok(uptime) => uptime.map(|u| fmt_duration(u, false)),
+		Err(e)     => {
+			warn!("Couldn't get the uptime of {}; err: {e}", ctx.cmd.channel.name);
+			None
+		},
+	}
+}
+
+// run a command of a template, as a pipe so that its output comes back;
+// the command is split into args before they get filled in,
+// so that whatever a variable turns out to be, it's one arg
+async fn run_command(ctx: &TemplateContext<'_>, command: &str) -> Option<String> {
+	let nesting = NESTING.try_with(|n| n.clone()).unwrap_or_default();
+	if nesting.depth >= MAX_TEMPLATE_DEPTH {
+		return Some("❌ commands nested too deep".into());
+	}
+	if nesting.runs.fetch_add(1, Ordering::Relaxed) >= MAX_TEMPLATE_COMMANDS {
+		return Some("❌ too many commands in one template".into());
+	}
+
+	let mut args = vec![];
+	for word in split_outside_braces(command, ' ') {
+		let arg = fill_in(ctx, word).await;
+
+		if !arg.is_empty() {
+			args.push(arg);
+		}
+	}
+	if args.is_empty() {
+		return None;
+	}
+
+	let cmd = CommandSource {
+		cmd:       args.remove(0).to_lowercase(),
+		args,
+		sender:    ctx.cmd.sender.clone(),
+		channel:   ctx.cmd.channel.clone(),
+		timestamp: ctx.cmd.timestamp,
+		is_pipe:   true,
+	};
+
+	let nested = Nesting { depth: nesting.depth + 1, runs: nesting.runs };
+
+	Some(
+		NESTING
+			.scope(nested, commands::handle_command(ctx.state, cmd))
+			.await
+			.unwrap_or_default()
+	)
+}
+
+// where the brace that `s` starts with gets closed
+fn closing_brace(s: &str) -> Option<usize> {
+	let mut depth = 0;
+
+	for (i, c) in s.char_indices() {
+		match c {
+			'{' => depth += 1,
+			'}' => {
+				depth -= 1;
+				if depth == 0 {
+					return Some(i);
+				}
+			},
+			_   => (),
+		}
+	}
+
+	None
+}
+
+// split a string at a character, except where it's in between braces
+fn split_outside_braces(s: &str, sep: char) -> Vec<&str> {
+	let mut parts = vec![];
+	let (mut depth, mut start) = (0, 0);
+
+	for (i, c) in s.char_indices() {
+		match c {
+			'{'                  => depth += 1,
+			'}'                  => depth -= 1,
+			c if c == sep && depth == 0 => {
+				parts.push(&s[start..i]);
+				start = i + c.len_utf8();
+			},
+			_                    => (),
+		}
+	}
+	parts.push(&s[start..]);
+
+	parts
+}
+
+fn split_once_outside_braces(s: &str, sep: char) -> Option<(&str, &str)> {
+	let first = split_outside_braces(s, sep)[0];
+
+	match first.len() < s.len() {
+		true  => Some((first, &s[first.len() + sep.len_utf8()..])),
+		false => None,
+	}
+}
@@ -0,0 +1,161 @@
+use crate::{api, db, convert_from_html_entities, TriviaGameInfo, TriviaRound};
+use crate::api_models::TriviaQuestion;
+
+use std::fs::read_to_string;
+use std::path::Path;
+
+use anyhow::bail;
+use serde::Deserialize;
+use sqlx::sqlite::SqlitePool;
+use tracing::warn;
+
+// the option that makes a round ask only the channel's own questions
+pub const CUSTOM: &str = "custom";
+
+// the next question of a round; from opentdb, or from the local question bank
+// if opentdb doesn't come up with one (or if the round is of the channel's own questions);
+// `None` if neither has a question like that
+pub async fn fetch_question(
+	pool:       &SqlitePool,
+	channel_id: i32,
+	round:      TriviaRound,
+) -> anyhow::Result<Option<TriviaGameInfo>> {
+	let cat = api::TriviaCategory::from_vec(&round.options);
+	let dif = api::TriviaDifficulty::from_vec(&round.options);
+	let typ = api::TriviaType::from_vec(&round.options);
+	let only_own = round.options.iter().any(|o| o.eq_ignore_ascii_case(CUSTOM));
+
+	let difficulty = Some(dif.to_opentdb_index()).filter(|d| *d != "any");
+	// (the channel's own questions only ever go by difficulty)
+	let (category, q_type) = match only_own {
+		true  => (None, None),
+		false => (cat.to_opentdb_name(), Some(typ.to_opentdb_index()).filter(|t| *t != "any")),
+	};
+
+	if !only_own {
+		match api::fetch_trivia_question(cat, dif, typ).await {
+			Ok(question) => {
+				let mut game = TriviaGameInfo::from_api_object(question);
+				game.round = round;
+
+				return Ok(Some(game));
+			},
+			Err(e) => warn!("Couldn't get a question from opentdb, trying the local ones; err: {e}"),
+		}
+	}
+
+	let question = db::get_random_trivia_question(pool, channel_id, only_own, category, difficulty, q_type).await?;
+
+	match question {
+		Some(q) => {
+			let mut game = TriviaGameInfo::new(
+				q.question,
+				q.correct_answer,
+				serde_json::from_str(&q.wrong_answers)?,
+				q.difficulty,
+			);
+			game.round = round;
+
+			Ok(Some(game))
+		},
+		None => Ok(None),
+	}
+}
+
+// what opentdb responds with, or just its results
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonQuestions {
+	Response { results: Vec<TriviaQuestion> },
+	List(Vec<TriviaQuestion>),
+}
+
+// a line of a CSV file of questions
+#[derive(Deserialize)]
+struct CsvQuestion {
+	category:          Option<String>,
+	#[serde(rename = "type")]
+	type_field:        String,
+	difficulty:        String,
+	question:          String,
+	correct_answer:    String,
+	// separated by `|`
+	incorrect_answers: String,
+}
+
+// read questions to import into the local question bank, either from the JSON
+// opentdb responds with (or just the array of its `results`), or from a CSV file with
+// the columns `category,type,difficulty,question,correct_answer,incorrect_answers`
+pub fn read_questions(
+	path: &Path,
+) -> anyhow::Result<Vec<TriviaQuestion>> {
+	let questions = match path.extension().and_then(|e| e.to_str()) {
+		Some("json") => match serde_json::from_str(&read_to_string(path)?)? {
+			JsonQuestions::Response { results } => results,
+			JsonQuestions::List(list)           => list,
+		},
+		Some("csv")  => csv::Reader::from_path(path)?
+			.deserialize::<CsvQuestion>()
+			.map(|q| q.map(|q| TriviaQuestion {
+				category:          q.category.unwrap_or_default(),
+				type_field:        q.type_field,
+				difficulty:        q.difficulty,
+				question:          q.question,
+				correct_answer:    q.correct_answer,
+				incorrect_answers: q.incorrect_answers.split('|').map(|a| a.trim().to_owned()).collect(),
+			}))
+			.collect::<Result<_, _>>()?,
+		_            => bail!("Expected a .json or a .csv file"),
+	};
+
+	questions
+		.into_iter()
+		.map(|q| {
+			// (opentdb encodes everything by default)
+			let q = TriviaQuestion {
+				category:          convert_from_html_entities(q.category),
+				type_field:        q.type_field.to_lowercase(),
+				difficulty:        q.difficulty.to_lowercase(),
+				question:          convert_from_html_entities(q.question),
+				correct_answer:    convert_from_html_entities(q.correct_answer),
+				incorrect_answers: q.incorrect_answers.into_iter().map(convert_from_html_entities).collect(),
+			};
+
+			check_question(&q)?;
+
+			Ok(q)
+		})
+		.collect()
+}
+
+// whether a question can be asked the same way the ones from opentdb are
+pub fn check_question(
+	q: &TriviaQuestion,
+) -> anyhow::Result<()> {
+	if !["easy", "medium", "hard"].contains(&q.difficulty.as_str()) {
+		bail!("\"{}\": the difficulty has to be easy, medium or hard", q.question);
+	}
+
+	match q.type_field.as_str() {
+		"multiple" if !q.incorrect_answers.is_empty() => Ok(()),
+		"boolean"  if q.incorrect_answers.len() == 1  => Ok(()),
+		_ => bail!("\"{}\": the type has to be multiple (with some wrong answers) or boolean (with one)", q.question),
+	}
+}
+
+// save questions into the local question bank for every channel;
+// returns how many of them weren't there yet
+pub async fn import_questions(
+	pool:      &SqlitePool,
+	questions: &[TriviaQuestion],
+) -> anyhow::Result<usize> {
+	let mut imported = 0;
+
+	for question in questions {
+		if db::add_trivia_question(pool, 0, question, None).await? {
+			imported += 1;
+		}
+	}
+
+	Ok(imported)
+}
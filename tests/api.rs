@@ -0,0 +1,304 @@
+mod common;
+
+use twitch_bot::{convert_from_html_entities, TriviaGameInfo, TwitchAuth};
+use twitch_bot::api::{self, ApiError};
+use twitch_bot::api_models::TriviaResponse;
+
+fn auth() -> TwitchAuth {
+	TwitchAuth {
+		client_id: "test".into(),
+		oauth:     "test".into(),
+		nick:      "test".into(),
+	}
+}
+
+// —————————————————————————————————————————
+//               Twitch API
+// —————————————————————————————————————————
+
+#[tokio::test]
+async fn twitch_user_lookups() {
+	common::server();
+
+	assert_eq!(api::id_from_nick("forsen", &auth()).await.unwrap(), Some(22484632));
+	assert_eq!(api::id_from_nick("nobody", &auth()).await.unwrap(), None);
+	assert_eq!(api::nick_from_id(22484632, &auth()).await.unwrap(), "forsen");
+	assert!(api::get_acc_creation_date("forsen", &auth()).await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn unknown_user_id_is_an_error_not_a_panic() {
+	common::server();
+
+	assert!(api::nick_from_id(0, &auth()).await.is_err());
+}
+
+#[tokio::test]
+async fn stream_info() {
+	common::server();
+
+	assert!(api::streamer_is_live(&auth(), "forsen").await.unwrap());
+	assert!(!api::streamer_is_live(&auth(), "offline").await.unwrap());
+}
+
+#[tokio::test]
+async fn followage() {
+	common::server();
+
+	assert!(api::get_followage(&auth(), 71092938, 22484632).await.unwrap().is_some());
+	assert!(api::get_followage(&auth(), 1, 22484632).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn chatters() {
+	common::server();
+
+	let chatters = api::get_chatters("forsen").await.unwrap().unwrap();
+
+	assert_eq!(chatters.len(), 6);
+	assert_eq!(chatters[0], "forsen");
+}
+
+// —————————————————————————————————————————
+//               Twitch-related APIs
+// —————————————————————————————————————————
+
+#[tokio::test]
+async fn emotes() {
+	common::server();
+
+	assert_eq!(api::get_7tv_channel_emotes("forsen").await.unwrap().unwrap(), vec!["forsenPls", "Okayeg"]);
+	assert_eq!(api::get_7tv_global_emotes().await.unwrap().unwrap(), vec!["reckH", "EZ"]);
+	assert_eq!(api::get_bttv_channel_emotes(22484632).await.unwrap().unwrap(), vec!["forsenE", "FeelsGoodMan"]);
+	assert_eq!(api::get_bttv_global_emotes().await.unwrap().unwrap(), vec!["OhMyGoodness", ":tf:"]);
+	assert_eq!(api::get_ffz_channel_emotes(22484632).await.unwrap().unwrap(), vec!["OMEGALUL"]);
+	assert_eq!(api::get_ffz_global_emotes().await.unwrap().unwrap(), vec!["BeanieHipster", "ZreknarF"]);
+	assert_eq!(api::get_all_channel_emotes(22484632).await.unwrap().unwrap().len(), 3);
+}
+
+// —————————————————————————————————————————
+//               Other APIs
+// —————————————————————————————————————————
+
+#[tokio::test]
+async fn weather() {
+	common::server();
+
+	let report = api::get_weather_report("London").await.unwrap().unwrap();
+	assert!(report.starts_with("Weather in London, United Kingdom: 🌡️ 11°C"));
+
+	// a response without any areas used to panic on `[0]`
+	assert!(api::get_weather_report("Atlantis").await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn wikipedia() {
+	common::server();
+
+	let res = api::query_wikipedia("Rust").await.unwrap().unwrap();
+	let page = res.query.pages.values().next().unwrap();
+
+	assert_eq!(page.title, "Rust (programming language)");
+}
+
+#[tokio::test]
+async fn dictionary() {
+	common::server();
+
+	let def = api::query_dictionary("hello").await.unwrap().unwrap();
+	assert_eq!(def, "həˈləʊ used as a greeting or to begin a phone conversation.");
+
+	assert!(api::query_dictionary("asdfgh").await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn urban_dictionary() {
+	common::server();
+
+	let def = api::query_urban_dictionary("yeet").await.unwrap().unwrap();
+	assert!(def.starts_with("Yeet - To discard an item at a high velocity"));
+	assert!(def.ends_with("(1 more definitions)"));
+
+	assert!(api::query_urban_dictionary("asdfgh").await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn reddit() {
+	common::server();
+
+	let relevancy = api::RedditPostRelevancy::Week;
+	let posts = api::get_reddit_posts("rust", &relevancy).await.unwrap();
+
+	assert_eq!(posts.data.children.len(), 2);
+}
+
+#[tokio::test]
+async fn time() {
+	common::server();
+
+	let time = api::get_time("London").await.unwrap().unwrap();
+
+	assert_eq!(time, "2022-04-20 17:42:08, (timezone Europe/London GMT+1)");
+}
+
+#[tokio::test]
+async fn holy_books() {
+	common::server();
+
+	let quran = api::get_rand_holy_book_verse(api::HolyBook::Quran).await.unwrap();
+
+	assert_eq!(quran.book, "Al-Fatiha");
+	assert_eq!(quran.book_number, None);
+}
+
+#[tokio::test]
+async fn trivia() {
+	common::server();
+
+	let question = api::fetch_trivia_question(
+		api::TriviaCategory::Any,
+		api::TriviaDifficulty::Any,
+		api::TriviaType::Multiple,
+	).await.unwrap();
+
+	assert_eq!(question.correct_answer, "Navi");
+	assert_eq!(question.incorrect_answers.len(), 3);
+
+	// OpenTDB answers with no results when it runs out of questions
+	let no_question = api::fetch_trivia_question(
+		api::TriviaCategory::EntertainmentCartoonAndAnimations,
+		api::TriviaDifficulty::Any,
+		api::TriviaType::Any,
+	).await;
+
+	assert!(no_question.is_err());
+}
+
+#[tokio::test]
+async fn wolframalpha() {
+	common::server();
+
+	assert_eq!(api::query_generic("2+2").await.unwrap(), Some("4".into()));
+	assert_eq!(api::query_generic("asdfgh").await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn github() {
+	let server = common::server();
+
+	let repo = api::get_github_repo_info(&format!("{}/github/repos/dynamo58/twitch-bot", server.url))
+		.await
+		.unwrap();
+
+	assert_eq!(repo.full_name, "dynamo58/twitch-bot");
+}
+
+#[tokio::test]
+async fn plain_text_apis() {
+	common::server();
+
+	assert!(api::get_inspire_image().await.unwrap().starts_with("https://generated.inspirobot.me/"));
+	assert_eq!(api::upload_to_pastebin("Kappa").await.unwrap(), "https://pastebin.com/Zk3qJ4Wd");
+}
+
+// —————————————————————————————————————————
+//               HTML entities
+// —————————————————————————————————————————
+
+#[test]
+fn opentdb_questions_are_decoded() {
+	let json = std::fs::read_to_string("tests/fixtures/opentdb/entities.json").unwrap();
+	let res: TriviaResponse = serde_json::from_str(&json).unwrap();
+
+	let games = res.results
+		.into_iter()
+		.map(TriviaGameInfo::from_api_object)
+		.collect::<Vec<_>>();
+
+	let questions = games.iter().map(|g| g.question.as_str()).collect::<Vec<_>>();
+	assert_eq!(questions, vec![
+		"What is the name of the main character in \"The Legend of Zelda\"?",
+		"Which band released the album \"Motörhead\" in 1977?",
+		"In Pokémon Red & Blue, which Pokémon is #151?",
+		"What is the value of π rounded to 2 decimals, and what is 90° in radians (π⁄2)?",
+		"In “The Hobbit”, Bilbo’s sword is named ‘Sting’…",
+		"Which of these cities is the capital of Côte d'Ivoire?",
+		"In HTML, what does the entity &lt; stand for, and is 5 < 7 > 3?",
+		"Who was the first Emperor of the Français after the Révolution—with the “Code Civil”?",
+		"What is the name of the pirate crew in \"One Piece\"'s main story ((麦わらの一味))?",
+		"What is the chemical formula of water, H₂O, written with the ΔH of its µ-scale \u{AD}evaporation�?",
+	]);
+
+	assert_eq!(games[1].correct_answer, "Motörhead");
+	assert!(games[1].wrong_answers.contains(&"Blue Öyster Cult".to_owned()));
+	assert_eq!(games[6].correct_answer, "< (less than)");
+	assert_eq!(games[9].wrong_answers, vec!["H₂O₂", "CO₂", "O₃"]);
+
+	// the decoded answers are the ones to type
+	assert!(games[5].is_correct("Yamoussoukro"));
+	assert!(games[7].is_correct("napoleon bonaparte"));
+}
+
+#[test]
+fn html_entity_edge_cases() {
+	let cases = [
+		("&#65;&#x42;&#X43;",           "ABC"),
+		// windows-1252 in numeric entities, like in browsers
+		("&#150; &#153;",               "– ™"),
+		// surrogates, zero and too big numbers aren't chars
+		("&#xD800;&#0;&#99999999999;",  "���"),
+		// legacy entities go without the `;`
+		("&amp &copy 2022 &lt3",        "& © 2022 <3"),
+		("I&notit; &notin; it",         "I¬it; ∉ it"),
+		("&NotNestedGreaterGreater;",   "⪢\u{338}"),
+		// (not entities)
+		("AT&T & co; &foo; &#; &#x;",   "AT&T & co; &foo; &#; &#x;"),
+		("&",                           "&"),
+		("no entities",                 "no entities"),
+	];
+
+	for (encoded, decoded) in cases {
+		assert_eq!(convert_from_html_entities(encoded.into()), decoded, "{encoded}");
+	}
+}
+
+// —————————————————————————————————————————
+//               Failures
+// —————————————————————————————————————————
+
+#[tokio::test]
+async fn unavailable_service_is_retried_then_reported() {
+	let server = common::server();
+	let started = std::time::Instant::now();
+
+	let err = api::get_weather_report("Down").await.unwrap_err();
+
+	assert!(matches!(err.downcast_ref::<ApiError>(), Some(ApiError::ServiceUnavailable(_))));
+	// the first attempt and all of the retries
+	assert_eq!(server.requests_to("/wttr/Down").len(), 4);
+	// (backing off 0.5s, 1s and 2s, since only a 429 gets to say when to come back)
+	assert!(started.elapsed() >= std::time::Duration::from_millis(3500));
+}
+
+#[tokio::test]
+async fn only_idempotent_requests_are_retried() {
+	let server = common::server();
+
+	let get = api::send(api::client().get(format!("{}/unavailable/get", server.url))).await;
+	let post = api::send(api::client().post(format!("{}/unavailable/post", server.url))).await;
+
+	assert!(matches!(get, Err(ApiError::ServiceUnavailable(_))));
+	assert!(matches!(post, Err(ApiError::ServiceUnavailable(_))));
+	assert_eq!(server.requests_to("/unavailable/get").len(), 4);
+	// (a paste that failed might still have been created)
+	assert_eq!(server.requests_to("/unavailable/post").len(), 1);
+}
+
+#[tokio::test]
+async fn rate_limited_service_is_reported() {
+	common::server();
+
+	let err = api::query_urban_dictionary("spam").await.unwrap_err();
+
+	assert!(matches!(err.downcast_ref::<ApiError>(), Some(ApiError::RateLimited(_))));
+}
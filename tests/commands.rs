@@ -0,0 +1,763 @@
+mod common;
+
+use common::{Bot, CHANNEL_ID};
+use twitch_bot::{commands, db, Channel, Sender, TriviaGameInfo, TriviaHint, TwitchBadge};
+
+use std::sync::Arc;
+
+use chrono::{Duration, Utc};
+
+#[tokio::test]
+async fn weather() {
+	let bot = Bot::new().await;
+
+	let out = bot.run("$weather London").await.unwrap();
+	assert!(out.starts_with("Weather in London, United Kingdom"));
+
+	let out = bot.run("$weather Atlantis").await.unwrap();
+	assert_eq!(out, "❌ location not identified");
+}
+
+// what the mock server got as `param` in the queries of the requests to `prefix`
+fn received_params(prefix: &str, param: &str) -> Vec<String> {
+	common::server()
+		.requests_to(prefix)
+		.iter()
+		.filter_map(|r| {
+			let (_, query) = r.split_once('?')?;
+
+			url::form_urlencoded::parse(query.as_bytes())
+				.find(|(k, _)| k == param)
+				.map(|(_, v)| v.into_owned())
+		})
+		.collect()
+}
+
+#[tokio::test]
+async fn user_input_is_encoded_in_urls() {
+	let bot = Bot::new().await;
+	let inputs = ["São Paulo", "東京 🗼 forsenE", "a&b=c?d#e/f%20+g;h\\i", "..%2F..", ".."];
+
+	for input in inputs {
+		bot.run(&format!("$weather {input}")).await.unwrap();
+		bot.run(&format!("$wiki {input}")).await.unwrap();
+		bot.run(&format!("$urban {input}")).await.unwrap();
+		bot.run(&format!("$time {input}")).await.unwrap();
+	}
+
+	// (every test shares the server, so the requests of the others are there too)
+	for input in inputs {
+		assert!(received_params("/wikipedia/w/api.php", "titles").contains(&input.to_owned()), "{input}");
+		assert!(received_params("/urban/v0/define", "term").contains(&input.to_owned()), "{input}");
+		assert!(received_params("/ipgeolocation/timezone", "location").contains(&input.to_owned()), "{input}");
+
+		// the location is a part of the path, all in one segment
+		let locations = common::server()
+			.requests_to("/wttr/")
+			.iter()
+			.filter_map(|r| r["/wttr/".len()..].strip_suffix("?format=j1").map(str::to_owned))
+			.collect::<Vec<_>>();
+		assert!(locations.iter().all(|l| !l.contains('/')), "{locations:?}");
+		if input != ".." {
+			assert!(
+				locations.iter().any(|l| percent_encoding::percent_decode_str(l).decode_utf8().unwrap() == input),
+				"{input}"
+			);
+		}
+	}
+
+	// (`..` can't be a segment at all, the weather of it isn't looked up)
+	assert!(common::server().requests_to("/?format=j1").is_empty());
+	assert_eq!(bot.run("$weather ..").await.unwrap(), "❌ location not identified");
+
+	// nothing in the user input got to be a parameter of its own
+	let time = common::server().requests_to("/ipgeolocation/timezone?apiKey=test&location=a%26b");
+	assert_eq!(time.len(), 1, "{time:?}");
+	assert_eq!(url::form_urlencoded::parse(time[0].split_once('?').unwrap().1.as_bytes()).count(), 2);
+}
+
+#[test]
+fn user_input_in_url_paths() {
+	assert_eq!(twitch_bot::api::path_segment("São Paulo").unwrap(), "S%C3%A3o%20Paulo");
+	assert_eq!(twitch_bot::api::path_segment("a/b?c#d%e\\f").unwrap(), "a%2Fb%3Fc%23d%25e%5Cf");
+	assert_eq!(twitch_bot::api::path_segment("🗼").unwrap(), "%F0%9F%97%BC");
+	assert_eq!(twitch_bot::api::path_segment("...").unwrap(), "...");
+	assert_eq!(twitch_bot::api::path_segment(".."), None);
+	assert_eq!(twitch_bot::api::path_segment("."), None);
+}
+
+#[tokio::test]
+async fn unavailable_service() {
+	let bot = Bot::new().await;
+
+	let out = bot.run("$weather Down").await.unwrap();
+
+	assert!(out.contains("is currently unavailable"));
+}
+
+#[tokio::test]
+async fn dictionaries() {
+	let bot = Bot::new().await;
+
+	assert_eq!(bot.run("$define asdfgh").await.unwrap(), "❌ word not found");
+	let urban = bot.run("$urban yeet").await.unwrap();
+	assert!(urban.starts_with("Yeet - "));
+	assert!(urban.contains("Example: Person 1: Aye I'm done with this can of soda & stuff"), "{urban}");
+	assert_eq!(bot.run("$urban asdfgh").await.unwrap(), "❌ not found");
+}
+
+#[tokio::test]
+async fn wiki() {
+	let bot = Bot::new().await;
+
+	let out = bot.run("$wiki Rust").await.unwrap();
+
+	assert_eq!(out, "Rust is a multi-paradigm, general-purpose programming language designed for performance and safety, especially safe concurrency");
+}
+
+#[tokio::test]
+async fn reddit() {
+	let bot = Bot::new().await;
+
+	let out = bot.run("$reddit r/rust upvotes").await.unwrap();
+	assert_eq!(out, "Announcing Rust 1.60.0: Cargo & rustc got faster [ https://blog.rust-lang.org/2022/04/07/Rust-1.60.0.html ]");
+
+	// (a post without any text only gets its title)
+	let out = bot.run("$reddit rust random media").await.unwrap();
+	assert_eq!(out, "Ferris in the wild [ https://i.redd.it/ferris.png ]");
+}
+
+#[tokio::test]
+async fn twitch_info() {
+	let bot = Bot::new().await;
+
+	assert!(bot.run("$uptime").await.unwrap().starts_with("⏱️ forsen has been live for"));
+	assert_eq!(bot.run("$uptime offline").await.unwrap(), "❌ streamer not live");
+	assert!(bot.run("$accage forsen").await.unwrap().starts_with("⏱️ forsen's account is"));
+	assert!(bot.run("$followage forsen xqcow").await.unwrap().starts_with("⏱️ forsen has been following xqcow"));
+}
+
+#[tokio::test]
+async fn ping() {
+	let bot = Bot::new().await;
+
+	assert!(bot.run("$ping").await.unwrap().contains("last update:"));
+}
+
+#[tokio::test]
+async fn holy_book() {
+	let bot = Bot::new().await;
+
+	assert_eq!(
+		bot.run("$quran").await.unwrap(),
+		"(Al-Fatiha ch. 1) In the name of Allah, the Entirely Merciful, the Especially Merciful."
+	);
+}
+
+#[tokio::test]
+async fn trivia_game() {
+	let bot = Bot::new().await;
+
+	let question = bot.run("$trivia").await.unwrap();
+	assert_eq!(question, "In \"The Legend of Zelda: Ocarina of Time\", what is the name of Link's fairy companion?");
+
+	assert_eq!(bot.run("$trivia").await.unwrap(), "❌ there is currently a game going on!");
+	assert!(bot.run("$hint").await.unwrap().contains("\"Navi\""));
+	assert_eq!(bot.run("$giveup").await.unwrap(), "So bad LUL | The answer was 'Navi'");
+
+	// while a question is being fetched (without holding up the channel), it's as good as asked
+	bot.state.channel(&CHANNEL_ID.to_string()).lock().await.trivia_pending = true;
+	assert_eq!(bot.run("$trivia").await.unwrap(), "❌ there is currently a game going on!");
+	bot.state.channel(&CHANNEL_ID.to_string()).lock().await.trivia_pending = false;
+	bot.run("$trivia").await.unwrap();
+	assert!(!bot.state.channel(&CHANNEL_ID.to_string()).lock().await.trivia_pending);
+}
+
+#[tokio::test]
+async fn trivia_rounds() {
+	let bot = Bot::new().await;
+	let channel = Channel { id: CHANNEL_ID, name: "forsen".into() };
+	bot.run("$sethook greeting substr catch=\"forsenE\" content=\"hi\"").await.unwrap();
+
+	let question = bot.run("$trivia 2").await.unwrap();
+	assert!(question.starts_with("(1/2) In \"The Legend of Zelda"));
+
+	let wrong = commands::check_trivia_answer(&bot.state, &channel, 1, "xqc", "Tatl").await.unwrap();
+	assert_eq!(wrong, None);
+
+	// medium questions are worth 2 points
+	let next = commands::check_trivia_answer(&bot.state, &channel, 1, "xqc", "navi").await.unwrap().unwrap();
+	assert!(next.starts_with("@xqc Correct! (+2) | (2/2) In"), "{next}");
+
+	let last = commands::check_trivia_answer(&bot.state, &channel, 2, "nymn", "Navi").await.unwrap().unwrap();
+	assert_eq!(last, "@nymn Correct! (+2) | The round is over: xqc (2), nymn (2)");
+	assert!(!bot.state.channel(&CHANNEL_ID.to_string()).lock().await.trivia_pending);
+	assert_eq!(bot.run("$giveup").await.unwrap(), "❌ there was no game going on LUL");
+
+	// the hooks of the channel outlive the game
+	assert_eq!(bot.state.channel(&CHANNEL_ID.to_string()).lock().await.hooks.len(), 1);
+
+	bot.run("$trivia").await.unwrap();
+	commands::check_trivia_answer(&bot.state, &channel, 1, "xqc", "Navi").await.unwrap();
+
+	assert_eq!(bot.run("$trivia top").await.unwrap(), "🏆 1. xqc (4), 2. nymn (2)");
+	assert_eq!(bot.run("$trivia score").await.unwrap(), "❌ forsen hasn't answered anything yet");
+}
+
+#[tokio::test]
+async fn custom_trivia_questions() {
+	let bot = Bot::new().await;
+
+	assert_eq!(bot.run("$trivia custom").await.unwrap(), "❌ there are no questions like that");
+
+	let add = "$trivia add question=\"Who is the best streamer?\" answer=\"forsen\" wrong=\"xqc|nymn\" difficulty=\"hard\"";
+	assert_eq!(bot.run(add).await.unwrap(), "🔧 Added the question");
+	assert_eq!(bot.run(add).await.unwrap(), "❌ The channel has that question already");
+	assert!(bot.run("$trivia add question=\"?\" answer=\"forsen\"").await.unwrap().starts_with("❌ usage"));
+
+	assert_eq!(bot.run("$trivia custom").await.unwrap(), "Who is the best streamer?");
+	let channel = Channel { id: CHANNEL_ID, name: "forsen".into() };
+	let correct = commands::check_trivia_answer(&bot.state, &channel, 1, "xqc", "forsen").await.unwrap();
+	assert_eq!(correct.as_deref(), Some("@xqc Correct! (+3)"));
+}
+
+#[tokio::test]
+async fn trivia_falls_back_to_local_questions() {
+	let bot = Bot::new().await;
+
+	// opentdb has no cartoon questions
+	assert_eq!(bot.run("$trivia cartoons").await.unwrap(), "❌ there are no questions like that");
+
+	let question = twitch_bot::api_models::TriviaQuestion {
+		category:          "Entertainment: Cartoon & Animations".into(),
+		type_field:        "multiple".into(),
+		difficulty:        "easy".into(),
+		question:          "What is the name of the dog in \"Scooby-Doo\"?".into(),
+		correct_answer:    "Scooby-Doo".into(),
+		incorrect_answers: vec!["Snoopy".into(), "Odie".into()],
+	};
+	db::add_trivia_question(&bot.state.pool, 0, &question, None).await.unwrap();
+
+	assert_eq!(bot.run("$trivia cartoons").await.unwrap(), "What is the name of the dog in \"Scooby-Doo\"?");
+}
+
+#[test]
+fn trivia_answers_dont_have_to_be_exact() {
+	let game = TriviaGameInfo::new(
+		"Who sang \"Hey Jude\"?".into(),
+		"The Beatles".into(),
+		vec!["The Rolling Stones".into(), "Queen".into(), "ABBA".into()],
+		"easy".into(),
+	);
+
+	for guess in ["the beatles", "Beatles!", "beatels", "  BEATLES  ", "thé beatles"] {
+		assert!(game.is_correct(guess), "{guess}");
+	}
+	for guess in ["queen", "stones", "beat", "", "a"] {
+		assert!(!game.is_correct(guess), "{guess}");
+	}
+
+	// leftover entities and "&" don't matter
+	let game = TriviaGameInfo::new("?".into(), "Tom &amp; Jerry".into(), vec![], "easy".into());
+	assert!(game.is_correct("tom and jerry"));
+
+	// numbers have to be exact, and a typo can't be closer to a wrong answer
+	let game = TriviaGameInfo::new("?".into(), "1991".into(), vec!["1990".into()], "easy".into());
+	assert!(!game.is_correct("1990") && !game.is_correct("1992") && game.is_correct("1991"));
+	let game = TriviaGameInfo::new("?".into(), "Mercury".into(), vec!["Mercurx".into()], "easy".into());
+	assert!(!game.is_correct("mercurx"));
+}
+
+#[tokio::test]
+async fn trivia_answers_by_letter() {
+	let bot = Bot::new().await;
+	let channel = Channel { id: CHANNEL_ID, name: "forsen".into() };
+
+	bot.run("$trivia").await.unwrap();
+	let options = bot.state.channel(&CHANNEL_ID.to_string()).lock().await.ongoing_trivia_game.clone().unwrap().options;
+	let letter = ["A", "B", "C", "D"][options.iter().position(|o| o == "Navi").unwrap()];
+	let wrong = ["A", "B", "C", "D"][options.iter().position(|o| o != "Navi").unwrap()];
+
+	// the letters only mean something once the options are out
+	assert_eq!(commands::check_trivia_answer(&bot.state, &channel, 1, "xqc", letter).await.unwrap(), None);
+
+	let hint = bot.run("$hint").await.unwrap();
+	assert!(hint.contains(&format!("{letter}) \"Navi\"")), "{hint}");
+
+	assert_eq!(commands::check_trivia_answer(&bot.state, &channel, 1, "xqc", wrong).await.unwrap(), None);
+	// (one wrong pick and the other options don't count any more)
+	assert_eq!(commands::check_trivia_answer(&bot.state, &channel, 1, "xqc", letter).await.unwrap(), None);
+	assert_eq!(commands::check_trivia_answer(&bot.state, &channel, 1, "xqc", "Navi").await.unwrap(), None);
+
+	let correct = commands::check_trivia_answer(&bot.state, &channel, 2, "nymn", &letter.to_lowercase()).await.unwrap();
+	assert_eq!(correct.as_deref(), Some("@nymn Correct! (+2)"));
+}
+
+#[test]
+fn trivia_options_cant_all_be_tried() {
+	let mut game = TriviaGameInfo::new("?".into(), "True".into(), vec!["False".into()], "easy".into());
+
+	// just chatting (or guessing away on a question without options) doesn't count as a pick
+	assert!(!game.guess(1, "forsenE"));
+	assert!(!game.guess(2, "false"));
+	assert!(!game.guess(2, "true"));
+	assert!(game.guess(1, "true"));
+
+	let mut game = TriviaGameInfo::new("?".into(), "Navi".into(), vec!["Tatl".into(), "Midna".into()], "easy".into());
+	game.options_hint();
+	let letter = |answer: &str| ["a", "b", "c"][game.options.iter().position(|o| o == answer).unwrap()];
+	let (right, wrong) = (letter("Navi"), letter("Tatl"));
+
+	assert!(!game.guess(1, wrong));
+	assert!(!game.guess(1, right));
+	assert!(game.guess(2, right));
+	assert_eq!(game.wrong_pickers, vec![1]);
+
+	let mut game = TriviaGameInfo::new("?".into(), "Navi".into(), vec!["Tatl".into()], "easy".into());
+	assert!(!game.guess(1, "tatl"));
+	assert!(game.guess(1, "navi"));
+}
+
+#[tokio::test]
+async fn trivia_questions_time_out() {
+	let bot = Bot::new().await;
+	let channel = Channel { id: CHANNEL_ID, name: "forsen".into() };
+	let later = |s| Utc::now() + Duration::seconds(s);
+
+	bot.run("$trivia 2").await.unwrap();
+	assert_eq!(commands::check_trivia_timer(&bot.state, &channel, later(10)).await.unwrap(), None);
+
+	// a hint at half time, only once
+	let hint = commands::check_trivia_timer(&bot.state, &channel, later(31)).await.unwrap().unwrap();
+	assert!(hint.starts_with("💡 The options are:") && hint.contains("\"Navi\""), "{hint}");
+	assert_eq!(commands::check_trivia_timer(&bot.state, &channel, later(32)).await.unwrap(), None);
+
+	let next = commands::check_trivia_timer(&bot.state, &channel, later(61)).await.unwrap().unwrap();
+	assert!(next.starts_with("⏰ Time's up! The answer was 'Navi' | (2/2) In"), "{next}");
+
+	let end = commands::check_trivia_timer(&bot.state, &channel, later(61)).await.unwrap().unwrap();
+	assert_eq!(end, "⏰ Time's up! The answer was 'Navi' | The round is over: nobody scored LUL");
+	assert_eq!(commands::check_trivia_timer(&bot.state, &channel, later(200)).await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn trivia_timeouts_can_end_the_round() {
+	let mut bot = Bot::new().await;
+	let config = &mut Arc::get_mut(&mut bot.state).unwrap().config.trivia;
+	config.hint = TriviaHint::Letters;
+	config.continue_round = false;
+	let channel = Channel { id: CHANNEL_ID, name: "forsen".into() };
+	let later = |s| Utc::now() + Duration::seconds(s);
+
+	bot.run("$trivia 5").await.unwrap();
+
+	let hint = commands::check_trivia_timer(&bot.state, &channel, later(30)).await.unwrap();
+	assert_eq!(hint.as_deref(), Some("💡 The answer looks like: N___"));
+
+	let end = commands::check_trivia_timer(&bot.state, &channel, later(60)).await.unwrap();
+	assert_eq!(end.as_deref(), Some("⏰ Time's up! The answer was 'Navi' | The round is over: nobody scored LUL"));
+}
+
+#[tokio::test]
+async fn true_false_trivia_hint() {
+	let bot = Bot::new().await;
+
+	bot.run("$trivia true false").await.unwrap();
+
+	// true/false questions only have one wrong answer, which used to panic
+	let hint = bot.run("$hint").await.unwrap();
+	assert!(hint.contains("\"True\"") && hint.contains("\"False\""));
+}
+
+#[tokio::test]
+async fn markov_without_an_index() {
+	let bot = Bot::new().await;
+
+	assert_eq!(bot.run("$markov").await.unwrap(), "❌ couldn't come up with anything (new) yet");
+	assert_eq!(bot.run("$markov forsen").await.unwrap(), "❌ word not indexed yet | E1");
+	assert_eq!(bot.run("$markov forsen many").await.unwrap(), "❌ expected positive integer");
+}
+
+#[tokio::test]
+async fn markov_imitating_a_user() {
+	let bot = Bot::new().await;
+
+	assert_eq!(bot.run("$markov user:forsen").await.unwrap(), "❌ not enough messages to imitate yet");
+	assert_eq!(bot.run("$markov channel:xqcow").await.unwrap(), "💢 Channel `xqcow` isn't tracked");
+
+	sqlx::query(&format!(
+		"INSERT INTO CHANNEL_{CHANNEL_ID} (sender_id, sender_nick, badges, timestamp, message) VALUES (?1, 'forsen', '', '2022-04-20 12:00:00', 'forsen is live')"
+	))
+		.bind(CHANNEL_ID)
+		.execute(&bot.state.pool)
+		.await
+		.unwrap();
+	// the empty model from before is still cached
+	assert_eq!(bot.run("$markov user:forsen copies:yes").await.unwrap(), "❌ not enough messages to imitate yet");
+
+	bot.state.markov_models.clear();
+	assert_eq!(bot.run("$markov user:forsen copies:yes").await.unwrap(), "🔮 forsen is live");
+
+	assert_eq!(bot.run("$markov optout").await.unwrap(), "✅ nobody can imitate you anymore");
+	assert_eq!(bot.run("$markov user:forsen copies:yes").await.unwrap(), "❌ forsen doesn't want to be imitated");
+	bot.run("$markov optin").await.unwrap();
+	assert_eq!(bot.run("$markov user:forsen copies:yes").await.unwrap(), "🔮 forsen is live");
+}
+
+#[tokio::test]
+async fn markov_from_other_channels() {
+	let mut bot = Bot::new().await;
+	let xqc_id = 71092938;
+	let state = Arc::get_mut(&mut bot.state).unwrap();
+	state.config.channels.push("xqcow".into());
+	state.channel_ids.insert("xqcow".into(), xqc_id);
+	db::try_create_tables_for_channel(&bot.state.pool, xqc_id).await.unwrap();
+
+	sqlx::query(&format!(
+		"INSERT INTO CHANNEL_{xqc_id}_MARKOV (word, succ) VALUES ('\u{2}', 'xqcL'), ('xqcL', '\u{3}')"
+	))
+		.execute(&bot.state.pool)
+		.await
+		.unwrap();
+
+	assert_eq!(bot.run("$markov channel:xqcow min:1").await.unwrap(), "🔮 xqcL");
+	assert_eq!(bot.run("$markov channel:all min:1").await.unwrap(), "🔮 xqcL");
+	assert_eq!(bot.run("$markov channel:all user:forsen").await.unwrap(), "❌ users can only be imitated in one channel at a time");
+
+	// xqc doesn't want to share anymore
+	db::set_markov_sharing(&bot.state.pool, xqc_id, false).await.unwrap();
+	assert_eq!(bot.run("$markov channel:xqcow").await.unwrap(), "❌ xqcow doesn't share its markov chains");
+	assert_eq!(bot.run("$markov channel:all min:1").await.unwrap(), "❌ couldn't come up with anything (new) yet");
+
+	// but forsen's own chains are always there for forsen
+	assert_eq!(bot.run("$markov share no").await.unwrap(), "✅ the markov chains of this channel stay here");
+	assert_eq!(bot.run("$markov channel:forsen").await.unwrap(), "❌ couldn't come up with anything (new) yet");
+}
+
+#[tokio::test]
+async fn markov_index_rebuild() {
+	let mut bot = Bot::new().await;
+	let state = Arc::get_mut(&mut bot.state).unwrap();
+	state.config.disregarded_users.push("supibot".into());
+	state.emote_cache.get_mut().globals.push("forsenE".into());
+
+	// indexed before the emote was known, and never again since
+	sqlx::query(&format!("INSERT INTO CHANNEL_{CHANNEL_ID}_MARKOV (word, succ) VALUES ('stale', 'forsene')"))
+		.execute(&bot.state.pool)
+		.await
+		.unwrap();
+	for (nick, message) in [("forsen", "FORSENE forsenE"), ("forsen", "$ping"), ("Supibot", "forsenE is a bot")] {
+		sqlx::query(&format!(
+			"INSERT INTO CHANNEL_{CHANNEL_ID} (sender_id, sender_nick, badges, timestamp, message) VALUES (1, ?1, '', '2022-04-20 12:00:00', ?2)"
+		))
+			.bind(nick)
+			.bind(message)
+			.execute(&bot.state.pool)
+			.await
+			.unwrap();
+	}
+
+	assert_eq!(
+		bot.run("$markov rebuild").await.unwrap(),
+		"✅ rebuilt the markov index out of 1 messages in 0s (skipped 2 commands and messages of disregarded users)",
+	);
+
+	db::start_markov_rebuild(&bot.state.pool, CHANNEL_ID).await.unwrap();
+	assert_eq!(bot.run("$markov rebuild").await.unwrap(), "❌ the markov index of this channel is being rebuilt already");
+	db::abort_markov_rebuild(&bot.state.pool, CHANNEL_ID).await.unwrap();
+
+	let pairs: Vec<(String, String, i64)> = sqlx::query_as(&format!(
+		"SELECT word, succ, count FROM CHANNEL_{CHANNEL_ID}_MARKOV WHERE word NOT LIKE '% %' ORDER BY word, succ"
+	))
+		.fetch_all(&bot.state.pool)
+		.await
+		.unwrap();
+
+	// (emotes are case sensitive, so `FORSENE` isn't one)
+	assert_eq!(pairs, vec![
+		("\u{2}".into(), "forsene".into(), 1),
+		("forsenE".into(), "\u{3}".into(), 1),
+		("forsene".into(), "forsenE".into(), 1),
+	]);
+}
+
+fn chatter(name: &str, statuses: Vec<TwitchBadge>) -> Sender {
+	Sender { id: 1, name: name.into(), statuses }
+}
+
+#[tokio::test]
+async fn hooks() {
+	let bot = Bot::new().await;
+	let channel = Channel { id: CHANNEL_ID, name: "forsen".into() };
+	let now = Utc::now();
+	let xqc = chatter("xqc", vec![]);
+
+	bot.run("$sethook greeting word catch=\"hi\" content=\"hello\"").await.unwrap();
+	bot.run("$sethook laugh regex catch=\"^(LUL|KEKW)+$\" content=\"xD\"").await.unwrap();
+	bot.run("$sethook emote substr catch=\"lul\" content=\"LUL\"").await.unwrap();
+	assert_eq!(bot.run("$sethook bad regex catch=\"(\" content=\"x\"").await.unwrap(), "❌ the catchphrase is not a valid regex");
+
+	assert_eq!(commands::check_hooks(&bot.state, &channel, &xqc, "oh hi there", now).await, vec!["hello"]);
+	assert!(commands::check_hooks(&bot.state, &channel, &xqc, "this", now).await.is_empty());
+	// every hook that matches goes off
+	assert_eq!(commands::check_hooks(&bot.state, &channel, &xqc, "LULKEKW", now).await, vec!["xD", "LUL"]);
+
+	assert_eq!(bot.run("$hooks").await.unwrap(), "🪝 emote (substr), greeting (word), laugh (regex)");
+	assert_eq!(bot.run("$disablehook laugh").await.unwrap(), "🔧 hook `laugh` disabled");
+	assert_eq!(commands::check_hooks(&bot.state, &channel, &xqc, "LUL", now).await, vec!["LUL"]);
+	assert_eq!(bot.run("$rmhook emote").await.unwrap(), "🔧 hook removed successfully");
+	assert_eq!(bot.run("$rmhook emote").await.unwrap(), "❌ there is no hook `emote`");
+	assert!(commands::check_hooks(&bot.state, &channel, &xqc, "LUL", now).await.is_empty());
+	bot.run("$enablehook laugh").await.unwrap();
+	assert_eq!(bot.run("$hooks").await.unwrap(), "🪝 greeting (word), laugh (regex)");
+}
+
+#[tokio::test]
+async fn hook_cooldowns_and_filters() {
+	let bot = Bot::new().await;
+	let channel = Channel { id: CHANNEL_ID, name: "forsen".into() };
+	let now = Utc::now();
+	let (xqc, nymn) = (chatter("xqc", vec![]), chatter("nymn", vec![TwitchBadge::Vip]));
+
+	bot.run("$sethook greeting substr catch=\"forsenE\" content=\"hi\" cooldown=\"30\"").await.unwrap();
+	assert_eq!(commands::check_hooks(&bot.state, &channel, &xqc, "forsenE", now).await, vec!["hi"]);
+	assert!(commands::check_hooks(&bot.state, &channel, &nymn, "forsenE", now + Duration::seconds(29)).await.is_empty());
+	assert_eq!(commands::check_hooks(&bot.state, &channel, &nymn, "forsenE", now + Duration::seconds(30)).await, vec!["hi"]);
+
+	// setting a hook of the same name replaces it
+	bot.run("$sethook greeting substr catch=\"forsenE\" content=\"hi\" users=\"@XQC\"").await.unwrap();
+	assert!(commands::check_hooks(&bot.state, &channel, &nymn, "forsenE", now).await.is_empty());
+	assert_eq!(commands::check_hooks(&bot.state, &channel, &xqc, "forsenE", now).await, vec!["hi"]);
+
+	bot.run("$sethook greeting substr catch=\"forsenE\" content=\"hi\" badges=\"sub,vip\"").await.unwrap();
+	assert!(commands::check_hooks(&bot.state, &channel, &xqc, "forsenE", now).await.is_empty());
+	assert_eq!(commands::check_hooks(&bot.state, &channel, &nymn, "forsenE", now).await, vec!["hi"]);
+	assert_eq!(bot.state.channel(&CHANNEL_ID.to_string()).lock().await.hooks.len(), 1);
+
+	assert_eq!(bot.run("$sethook x substr catch=\"a\" content=\"b\" badges=\"gamer\"").await.unwrap(), "❌ unknown badge `gamer`");
+}
+
+#[tokio::test]
+async fn hook_templates_and_commands() {
+	let bot = Bot::new().await;
+	let channel = Channel { id: CHANNEL_ID, name: "forsen".into() };
+	let now = Utc::now();
+	let xqc = chatter("xqc", vec![]);
+
+	bot.run("$sethook greet regex catch=\"^hi ([a-z]+)( now)?$\" content=\"hello {1}{2}, {sender} in {channel} #{count} {x}\"").await.unwrap();
+	assert_eq!(commands::check_hooks(&bot.state, &channel, &xqc, "hi nymn", now).await, vec!["hello nymn, xqc in forsen #1 {x}"]);
+	assert_eq!(commands::check_hooks(&bot.state, &channel, &xqc, "hi nymn now", now).await, vec!["hello nymn now, xqc in forsen #2 {x}"]);
+
+	// replacing a hook keeps its count
+	bot.run("$sethook greet regex catch=\"^hi ([a-z]+)$\" content=\"#{count}\"").await.unwrap();
+	assert_eq!(commands::check_hooks(&bot.state, &channel, &xqc, "hi nymn", now).await, vec!["#3"]);
+	assert_eq!(db::get_channel_hooks(&bot.state.pool, CHANNEL_ID).await.unwrap()[0].count, 3);
+
+	bot.run("$sethook w regex catch=\"^weather ([a-z]+)$\" content=\"$weather {1}\"").await.unwrap();
+	let out = commands::check_hooks(&bot.state, &channel, &xqc, "weather london", now).await;
+	assert!(out[0].starts_with("Weather in London"));
+
+	// a hook counts as fired right away, its command can run later
+	let fired = commands::fire_hooks(&bot.state, &channel, &xqc, "weather london", now).await;
+	assert_eq!(fired.len(), 1);
+	assert_eq!(bot.state.channel(&CHANNEL_ID.to_string()).lock().await.hooks.iter().find(|h| h.name == "w").unwrap().count, 2);
+	let out = commands::hook_response(&bot.state, fired.into_iter().next().unwrap()).await.unwrap();
+	assert!(out.starts_with("Weather in London"));
+
+	// a capture is always one arg of the command
+	bot.run("$sethook roll regex catch=\"^roll (.+)$\" content=\"$random  {1}\"").await.unwrap();
+	let out = commands::check_hooks(&bot.state, &channel, &xqc, "roll 7", now).await;
+	assert!((1..=7).contains(&out[0].parse::<i32>().unwrap()), "{out:?}");
+	assert_eq!(commands::check_hooks(&bot.state, &channel, &xqc, "roll 7 7", now).await, vec!["❌ number expected"]);
+
+	// hooks know the rest of the template language too
+	bot.run("$sethook t exact catch=\"t\" content=\"{random:ok} {arg:1=none}\"").await.unwrap();
+	assert_eq!(commands::check_hooks(&bot.state, &channel, &xqc, "t", now).await, vec!["ok none"]);
+
+	// a capture can't turn a hook into a command
+	bot.run("$sethook echo regex catch=\"^say (.+)$\" content=\"{1}\"").await.unwrap();
+	assert_eq!(commands::check_hooks(&bot.state, &channel, &xqc, "say $ping", now).await, vec!["$ping"]);
+}
+
+#[tokio::test]
+async fn command_templates() {
+	let bot = Bot::new().await;
+
+	assert_eq!(bot.run("$setcmd greet templ hi {sender}, {arg:1=stranger} and {2=nobody} ({args}) {x}").await.unwrap(), "🔧 command created successfully");
+	assert_eq!(bot.run("$greet").await.unwrap(), "hi forsen, stranger and nobody () {x}");
+	// (args don't get filled in themselves)
+	assert_eq!(bot.run("$greet nymn {sender}").await.unwrap(), "hi forsen, nymn and {sender} (nymn {sender}) {x}");
+
+	bot.run("$setcmd used templ used {count} times in {channel}").await.unwrap();
+	bot.run("$used").await.unwrap();
+	assert_eq!(bot.run("$used").await.unwrap(), "used 2 times in forsen");
+
+	bot.run("$setcmd old incr {} {count}").await.unwrap();
+	assert_eq!(bot.run("$old").await.unwrap(), "1 1");
+	bot.run("$setcmd raw paste {sender}").await.unwrap();
+	assert_eq!(bot.run("$raw").await.unwrap(), "{sender}");
+
+	bot.run("$setcmd pick templ {random:a|{sender}}").await.unwrap();
+	assert!(["a", "forsen"].contains(&bot.run("$pick").await.unwrap().as_str()));
+
+	bot.run("$setcmd live templ live for {uptime=never}").await.unwrap();
+	assert_ne!(bot.run("$live").await.unwrap(), "live for never");
+}
+
+#[tokio::test]
+async fn command_templates_run_commands() {
+	let bot = Bot::new().await;
+
+	bot.run("$setcmd w templ {$weather {arg:1=London}}").await.unwrap();
+	assert!(bot.run("$w").await.unwrap().starts_with("Weather in London"));
+
+	bot.run("$setcmd greet templ hi {1=stranger}").await.unwrap();
+	bot.run("$setcmd greetme templ {$greet {sender}}!").await.unwrap();
+	assert_eq!(bot.run("$greetme").await.unwrap(), "hi forsen!");
+
+	// a command that runs itself doesn't go on forever
+	bot.run("$setcmd loop templ x{$loop}").await.unwrap();
+	assert_eq!(bot.run("$loop").await.unwrap(), "xxxxx❌ commands nested too deep");
+
+	// nor does one that runs a lot of others
+	bot.run("$setcmd x templ x").await.unwrap();
+	bot.run(&format!("$setcmd fan templ {}", "{$x}".repeat(12))).await.unwrap();
+	assert_eq!(bot.run("$fan").await.unwrap(), format!("{}{}", "x".repeat(10), "❌ too many commands in one template".repeat(2)));
+
+	// what the chatter gives is one arg of the command, however many words it is
+	bot.run("$setcmd roll templ {$random   {args}}").await.unwrap();
+	assert!((1..=7).contains(&bot.run("$roll 7").await.unwrap().parse::<i32>().unwrap()));
+	assert_eq!(bot.run("$roll 7 7").await.unwrap(), "❌ number expected");
+}
+
+#[tokio::test]
+async fn command_management() {
+	let bot = Bot::new().await;
+	let today = Utc::now().format("%Y-%m-%d");
+
+	assert_eq!(bot.run("$cmds").await.unwrap(), "❌ the channel has no commands");
+	assert_eq!(bot.run("$setcmd greet templ hi").await.unwrap(), "🔧 command created successfully");
+	assert_eq!(bot.run("$setcmd greet templ hello {sender}").await.unwrap(), "🔧 command updated successfully");
+	bot.run("$setcmd count incr {}").await.unwrap();
+	assert_eq!(bot.run("$setcmd ping templ pong").await.unwrap(), "❌ there is a built-in command with that name");
+	assert_eq!(bot.run("$setcmd $count templ hi").await.unwrap(), "❌ the name can't have the prefix in it");
+	// (a typo in the type isn't taken as the start of a template)
+	assert_eq!(bot.run("$setcmd hi tmpl hello").await.unwrap(), "❌ command type not recognized");
+	assert_eq!(bot.run("$setcmd hi").await.unwrap(), "❌ no type provided");
+	assert_eq!(bot.run("$setcmd hi templ").await.unwrap(), "❌ no expression provided");
+	assert_eq!(bot.run("$cmds").await.unwrap(), "🛠️ count, greet");
+
+	bot.run("$greet").await.unwrap();
+	assert_eq!(bot.run("$greet").await.unwrap(), "hello forsen");
+	assert_eq!(bot.run("$cmdinfo greet").await.unwrap(), format!("🛠️ greet | templ | created by forsen on {today} | used 2 times | for everyone"));
+	assert_eq!(bot.run("$cmdinfo nothing").await.unwrap(), "❌ there is no command `nothing`");
+
+	assert_eq!(bot.run("$editcmd greet permission=\"mod\" cooldown=\"30\"").await.unwrap(), "🔧 command `greet` updated successfully");
+	assert_eq!(bot.run("$editcmd greet permission=\"gamer\"").await.unwrap(), "❌ unknown permission `gamer` (everyone/sub/vip/mod/broadcaster)");
+	assert_eq!(bot.run("$cmdinfo greet").await.unwrap(), format!("🛠️ greet | templ | created by forsen on {today} | used 2 times | for moderator | 30s cooldown"));
+
+	let mut cmd = bot.command("$greet");
+	cmd.sender = chatter("xqc", vec![TwitchBadge::Vip]);
+	assert_eq!(commands::handle_command(&bot.state, cmd).await.unwrap(), "❌ requires moderator status");
+
+	// the cooldown started with the last use
+	assert!(bot.run("$greet").await.is_none());
+	let mut cmd = bot.command("$greet");
+	cmd.timestamp = cmd.timestamp + Duration::seconds(31);
+	assert_eq!(commands::handle_command(&bot.state, cmd).await.unwrap(), "hello forsen");
+
+	let mut cmd = bot.command("$delcmd greet");
+	cmd.sender = chatter("xqc", vec![TwitchBadge::Subscriber]);
+	assert_eq!(commands::handle_command(&bot.state, cmd).await.unwrap(), "❌ requires MVB privileges | E4");
+	assert_eq!(bot.run("$cmds").await.unwrap(), "🛠️ count, greet");
+	assert_eq!(bot.run("$delcmd greet").await.unwrap(), "✅ removed successfully");
+}
+
+#[tokio::test]
+async fn command_uses_are_counted_apart_from_increments() {
+	let bot = Bot::new().await;
+
+	bot.run("$setcmd c incr {} {count}").await.unwrap();
+	bot.run("$c").await.unwrap();
+	assert_eq!(bot.run("$c").await.unwrap(), "2 2");
+
+	// only `incr` commands count up `{}`
+	bot.run("$setcmd c templ {count}").await.unwrap();
+	assert_eq!(bot.run("$c").await.unwrap(), "3");
+	bot.run("$setcmd c incr {} {count}").await.unwrap();
+	assert_eq!(bot.run("$c").await.unwrap(), "3 4");
+}
+
+#[tokio::test]
+async fn long_command_lists_are_pasted() {
+	let bot = Bot::new().await;
+
+	for i in 0..20 {
+		bot.run(&format!("$setcmd a_command_with_quite_a_long_name_{i} templ hi")).await.unwrap();
+	}
+
+	assert_eq!(bot.run("$cmds").await.unwrap(), "🛠️ https://pastebin.com/Zk3qJ4Wd");
+}
+
+#[tokio::test]
+async fn shutdown_waits_for_running_commands() {
+	let bot = Bot::new().await;
+	let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+
+	bot.state.tasks.spawn(async move {
+		rx.await.ok();
+	});
+	// (a panicking task counts as done too)
+	bot.state.tasks.spawn(async { panic!("forsenE") }).await.unwrap_err();
+	assert_eq!(bot.state.tasks.running(), 1);
+	assert!(tokio::time::timeout(std::time::Duration::from_millis(50), bot.state.tasks.wait()).await.is_err());
+
+	tx.send(()).unwrap();
+	tokio::time::timeout(std::time::Duration::from_secs(1), bot.state.tasks.wait()).await.unwrap();
+	assert_eq!(bot.state.tasks.running(), 0);
+}
+
+#[tokio::test]
+async fn name_changes() {
+	let bot = Bot::new().await;
+
+	assert_eq!(bot.run("$namechanges").await.unwrap(), "❌ no name changes of forsen recorded");
+
+	let day = |d: u32| chrono::TimeZone::ymd(&Utc, 2022, 4, d).and_hms(12, 0, 0);
+	db::update_user_name(&bot.state.pool, 1, "renamed_before", day(1)).await.unwrap();
+	db::update_user_name(&bot.state.pool, 1, "renamed_now", day(2)).await.unwrap();
+
+	// Twitch doesn't know the old name anymore, the names seen before do
+	let user = twitch_bot::resolve_user("renamed_before", &bot.state).await.unwrap().unwrap();
+	assert_eq!((user.id, user.name.as_str()), (1, "renamed_now"));
+
+	assert_eq!(
+		bot.run("$namechanges renamed_before").await.unwrap(),
+		"📛 name changes of renamed_now: renamed_before → renamed_now (2022-04-02)"
+	);
+	assert_eq!(bot.run("$namechanges renamed_never").await.unwrap(), "💢 User `renamed_never` was not found");
+}
+
+#[test]
+fn messages_are_cut_down_to_what_twitch_takes() {
+	assert_eq!(commands::fit_message("forsenE".into()), "forsenE");
+	// (by chars, not bytes)
+	let long = commands::fit_message("ö".repeat(commands::MAX_MESSAGE_LENGTH + 1));
+	assert_eq!(long, "ö".repeat(commands::MAX_MESSAGE_LENGTH));
+}
+
+#[tokio::test]
+async fn users_are_looked_up_once() {
+	let bot = Bot::new().await;
+
+	// the channels the bot is in are known since startup
+	let channel = twitch_bot::resolve_user("forsen", &bot.state).await.unwrap().unwrap();
+	assert_eq!((channel.id, channel.name.as_str()), (CHANNEL_ID, "forsen"));
+	assert_eq!(bot.state.tracked_channel("FORSEN").map(|c| c.id), Some(CHANNEL_ID));
+	assert!(bot.state.tracked_channel("xqcow").is_none());
+
+	// and the others get remembered
+	assert!(bot.state.name_id_cache.get("xqcow").is_none());
+	twitch_bot::resolve_user("xqcow", &bot.state).await.unwrap().unwrap();
+	assert!(bot.state.name_id_cache.get("xqcow").is_some());
+}
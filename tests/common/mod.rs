@@ -0,0 +1,290 @@
+#![allow(dead_code)]
+
+// a tiny local HTTP server answering with recorded API responses
+// (see `tests/fixtures`), so that the API calls and the commands
+// built on top of them can be tested without the network
+
+use twitch_bot::api::{self, ApiUrls};
+use twitch_bot::commands::handle_command;
+use twitch_bot::server::{ApiKey, ModToken, ServerConfig};
+use twitch_bot::{
+	db,
+	AppState,
+	Channel,
+	CommandSource,
+	Config,
+	EmoteCache,
+	Sender,
+	TwitchAuth,
+	TwitchBadge,
+};
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use chrono::Utc;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server};
+use sqlx::sqlite::SqlitePoolOptions;
+use twitch_irc::login::StaticLoginCredentials;
+use twitch_irc::{ClientConfig, SecureTCPTransport, TwitchIRCClient};
+
+struct Route {
+	// matched against the start of the requested path + query
+	prefix:  &'static str,
+	status:  u16,
+	fixture: &'static str,
+}
+
+// the first matching route wins, so the more specific ones go first
+const ROUTES: &[Route] = &[
+	// Twitch
+	Route { prefix: "/helix/users?login=nobody",      status: 200, fixture: "twitch/users_empty.json"         },
+	Route { prefix: "/helix/users?login=renamed_",   status: 200, fixture: "twitch/users_empty.json"         },
+	Route { prefix: "/helix/users?id=0",              status: 200, fixture: "twitch/users_empty.json"         },
+	Route { prefix: "/helix/users/follows?to_id=1&",  status: 200, fixture: "twitch/follows_none.json"        },
+	Route { prefix: "/helix/users/follows",           status: 200, fixture: "twitch/follows.json"             },
+	Route { prefix: "/helix/users",                   status: 200, fixture: "twitch/users.json"               },
+	Route { prefix: "/helix/streams?user_login=offline", status: 200, fixture: "twitch/streams_offline.json"  },
+	Route { prefix: "/helix/streams",                 status: 200, fixture: "twitch/streams.json"             },
+	Route { prefix: "/tmi/group/user/",               status: 200, fixture: "twitch/chatters.json"            },
+	// emotes
+	Route { prefix: "/7tv/v2/emotes/global",          status: 200, fixture: "emotes/7tv_global.json"          },
+	Route { prefix: "/7tv/v2/users/",                 status: 200, fixture: "emotes/7tv_channel.json"         },
+	Route { prefix: "/bttv/3/cached/emotes/global",   status: 200, fixture: "emotes/bttv_global.json"         },
+	Route { prefix: "/bttv/3/cached/users/twitch/",   status: 200, fixture: "emotes/bttv_channel.json"        },
+	Route { prefix: "/bttv/3/cached/frankerfacez/emotes/global", status: 200, fixture: "emotes/ffz_global.json" },
+	Route { prefix: "/bttv/3/cached/frankerfacez/users/twitch/", status: 200, fixture: "emotes/ffz_channel.json" },
+	Route { prefix: "/adamcy/v1/channel/",            status: 200, fixture: "emotes/adamcy_channel.json"      },
+	// other APIs
+	Route { prefix: "/wttr/Atlantis",                 status: 200, fixture: "wttr/empty.json"                 },
+	Route { prefix: "/wttr/Down",                     status: 503, fixture: "wttr/empty.json"                 },
+	Route { prefix: "/wttr/",                         status: 200, fixture: "wttr/london.json"                },
+	Route { prefix: "/wikipedia/w/api.php",           status: 200, fixture: "wikipedia/rust.json"             },
+	Route { prefix: "/dictionary/api/v2/entries/en/asdfgh", status: 404, fixture: "dictionary/not_found.json" },
+	Route { prefix: "/dictionary/api/v2/entries/en/", status: 200, fixture: "dictionary/hello.json"           },
+	Route { prefix: "/urban/v0/define?term=spam",     status: 429, fixture: "urban/empty.json"                },
+	Route { prefix: "/urban/v0/define?term=asdfgh",   status: 200, fixture: "urban/empty.json"                },
+	Route { prefix: "/urban/v0/define",               status: 200, fixture: "urban/yeet.json"                 },
+	Route { prefix: "/pastebin/api/api_post.php",     status: 200, fixture: "pastebin/paste.txt"              },
+	Route { prefix: "/reddit/r/",                     status: 200, fixture: "reddit/top.json"                 },
+	Route { prefix: "/ipgeolocation/timezone",        status: 200, fixture: "ipgeolocation/london.json"       },
+	Route { prefix: "/devotionalium/api/v2",          status: 200, fixture: "devotionalium/verses.json"       },
+	Route { prefix: "/opentdb/api.php?amount=1&category=32", status: 200, fixture: "opentdb/empty.json"       },
+	Route { prefix: "/opentdb/api.php?amount=1&type=boolean", status: 200, fixture: "opentdb/boolean.json"    },
+	Route { prefix: "/opentdb/api.php",               status: 200, fixture: "opentdb/multiple.json"           },
+	Route { prefix: "/wolframalpha/v2/query?input=asdfgh", status: 200, fixture: "wolframalpha/no_pods.json"  },
+	Route { prefix: "/wolframalpha/v2/query",         status: 200, fixture: "wolframalpha/query.json"         },
+	Route { prefix: "/inspirobot/api",                status: 200, fixture: "inspirobot/generate.txt"         },
+	Route { prefix: "/unavailable/",                  status: 503, fixture: "wttr/empty.json"                 },
+	Route { prefix: "/github/repos/",                 status: 200, fixture: "github/repo.json"                },
+];
+
+pub struct MockServer {
+	pub url:  String,
+	requests: Mutex<Vec<String>>,
+}
+
+impl MockServer {
+	// all of the requested paths (with queries) starting with `prefix`
+	pub fn requests_to(&self, prefix: &str) -> Vec<String> {
+		self.requests
+			.lock()
+			.unwrap()
+			.iter()
+			.filter(|r| r.starts_with(prefix))
+			.cloned()
+			.collect()
+	}
+}
+
+static SERVER: OnceLock<MockServer> = OnceLock::new();
+
+// start the server (only once per test binary) and point all of the APIs at it
+pub fn server() -> &'static MockServer {
+	SERVER.get_or_init(|| {
+		let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+		let url = format!("http://{}", listener.local_addr().unwrap());
+
+		// the server gets its own runtime, so that it outlives
+		// the runtimes of the individual tests
+		std::thread::spawn(move || {
+			tokio::runtime::Builder::new_current_thread()
+				.enable_all()
+				.build()
+				.unwrap()
+				.block_on(async move {
+					let make_service = make_service_fn(|_| async {
+						Ok::<_, Infallible>(service_fn(respond))
+					});
+
+					Server::from_tcp(listener)
+						.unwrap()
+						.serve(make_service)
+						.await
+						.unwrap();
+				});
+		});
+
+		api::set_api_urls(ApiUrls {
+			twitch_helix:     format!("{url}/helix"),
+			twitch_tmi:       format!("{url}/tmi"),
+			seventv:          format!("{url}/7tv"),
+			bttv:             format!("{url}/bttv"),
+			channel_emotes:   format!("{url}/adamcy"),
+			wttr:             format!("{url}/wttr"),
+			wikipedia:        format!("{url}/wikipedia"),
+			dictionary:       format!("{url}/dictionary"),
+			urban_dictionary: format!("{url}/urban"),
+			pastebin:         format!("{url}/pastebin"),
+			reddit:           format!("{url}/reddit"),
+			ipgeolocation:    format!("{url}/ipgeolocation"),
+			devotionalium:    format!("{url}/devotionalium"),
+			opentdb:          format!("{url}/opentdb"),
+			wolframalpha:     format!("{url}/wolframalpha"),
+			inspirobot:       format!("{url}/inspirobot"),
+		});
+
+		std::env::set_var("PASTEBIN_API_KEY",      "test");
+		std::env::set_var("IPGEOLOCATION_API_KEY", "test");
+		std::env::set_var("WOLFRAMALPHA_APPID",    "test");
+
+		MockServer {
+			url,
+			requests: Mutex::new(vec![]),
+		}
+	})
+}
+
+async fn respond(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+	let path = req
+		.uri()
+		.path_and_query()
+		.map(|p| p.as_str().to_owned())
+		.unwrap_or_default();
+
+	if let Some(server) = SERVER.get() {
+		server.requests.lock().unwrap().push(path.clone());
+	}
+
+	let res = match ROUTES.iter().find(|r| path.starts_with(r.prefix)) {
+		Some(route) => {
+			let fixture = std::fs::read(format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), route.fixture))
+				.unwrap();
+
+			Response::builder()
+				.status(route.status)
+				// so that the rate limited requests get retried right away
+				.header("Retry-After", "0")
+				.body(Body::from(fixture))
+				.unwrap()
+		},
+		None => Response::builder()
+			.status(404)
+			.body(Body::empty())
+			.unwrap(),
+	};
+
+	Ok(res)
+}
+
+pub const CHANNEL_ID: i32 = 22484632;
+
+// everything a command needs to run, against the mock server
+// and an in-memory database
+pub struct Bot {
+	pub state: Arc<AppState>,
+}
+
+impl Bot {
+	pub async fn new() -> Self {
+		let server = server();
+
+		let pool = SqlitePoolOptions::new()
+			.max_connections(1)
+			.connect("sqlite::memory:")
+			.await
+			.unwrap();
+		db::init_db(&pool).await.unwrap();
+		db::try_create_tables_for_channel(&pool, CHANNEL_ID).await.unwrap();
+
+		let config = Config {
+			channels:                vec!["forsen".into()],
+			disregarded_users:       vec![],
+			commands_reference_path: "https://example.com/commands".into(),
+			github_repo_api_path:    Some(format!("{}/github/repos/dynamo58/twitch-bot", server.url)),
+			index_markov:            false,
+			track_offliners:         false,
+			prefix:                  '$',
+			api_urls:                Default::default(),
+			logging:                 Default::default(),
+			markov:                  Default::default(),
+			trivia:                  Default::default(),
+			server:                  ServerConfig {
+				dashboard:  true,
+				mod_tokens: vec![ModToken {
+					name:     "forsen".into(),
+					token:    "secret".into(),
+					channels: vec!["forsen".into()],
+				}],
+				api:        true,
+				api_keys:   vec![ApiKey {
+					name: "overlay".into(),
+					key:  "key".into(),
+				}],
+				..Default::default()
+			},
+		};
+
+		let auth = TwitchAuth {
+			client_id: "test".into(),
+			oauth:     "test".into(),
+			nick:      "test".into(),
+		};
+
+		// the client never connects, since nothing gets sent through it
+		let (_, client) = TwitchIRCClient::<SecureTCPTransport, StaticLoginCredentials>::new(ClientConfig::default());
+		let (log_writer, _) = db::LogWriter::spawn(pool.clone());
+
+		let emote_cache = EmoteCache {
+			channels: HashMap::new(),
+			globals:  vec![],
+		};
+
+		Self {
+			state: Arc::new(AppState::new(
+				config, auth, pool, client, log_writer, emote_cache,
+				HashMap::from([("forsen".to_owned(), CHANNEL_ID)]),
+			)),
+		}
+	}
+
+	// a chat message as a command, sent by the broadcaster;
+	// it gets ran as a pipe, so that its output is returned instead of sent
+	pub fn command(&self, message: &str) -> CommandSource {
+		let mut args: Vec<String> = message.split(' ').map(|a| a.to_owned()).collect();
+		let cmd = args.remove(0)[1..].to_owned();
+
+		CommandSource {
+			cmd,
+			args,
+			sender: Sender {
+				id:       22484632,
+				name:     "forsen".into(),
+				statuses: vec![TwitchBadge::Broadcaster],
+			},
+			channel: Channel {
+				id:   CHANNEL_ID,
+				name: "forsen".into(),
+			},
+			timestamp: Utc::now(),
+			is_pipe:   true,
+		}
+	}
+
+	// run a chat message as a command and return what the bot would say
+	pub async fn run(&self, message: &str) -> Option<String> {
+		handle_command(&self.state, self.command(message)).await
+	}
+}
@@ -0,0 +1,673 @@
+use twitch_bot::{db, markov, trivia, EmoteCache, HookMatchType, MessageHook, TriviaGameInfo, TriviaRound, TwitchBadge};
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use chrono::Utc;
+use sqlx::sqlite::{SqlitePool, SqlitePoolOptions};
+use twitch_irc::message::{IRCMessage, PrivmsgMessage, ServerMessage};
+
+const CHANNEL_ID: i32 = 22484632;
+
+async fn pool() -> SqlitePool {
+	let pool = SqlitePoolOptions::new()
+		.max_connections(1)
+		.connect("sqlite::memory:")
+		.await
+		.unwrap();
+
+	db::init_db(&pool).await.unwrap();
+	db::try_create_tables_for_channel(&pool, CHANNEL_ID).await.unwrap();
+
+	pool
+}
+
+fn privmsg(text: &str) -> PrivmsgMessage {
+	let raw = format!(
+		"@badge-info=;badges=;color=;display-name=Forsen;emotes=;id=1;room-id={CHANNEL_ID};tmi-sent-ts=1650000000000;user-id=22484632 \
+		:forsen!forsen@forsen.tmi.twitch.tv PRIVMSG #forsen :{text}"
+	);
+
+	match ServerMessage::try_from(IRCMessage::parse(&raw).unwrap()).unwrap() {
+		ServerMessage::Privmsg(privmsg) => privmsg,
+		_                               => unreachable!(),
+	}
+}
+
+async fn count(pool: &SqlitePool, table: &str) -> i64 {
+	sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {table}"))
+		.fetch_one(pool)
+		.await
+		.unwrap()
+}
+
+#[tokio::test]
+async fn queued_logs_are_written_when_the_writer_closes() {
+	let pool = pool().await;
+	let (writer, handle) = db::LogWriter::spawn(pool.clone());
+
+	for text in ["forsen1", "forsen2", "forsen3"] {
+		writer.log(&privmsg(text));
+	}
+
+	drop(writer);
+	handle.await.unwrap();
+
+	assert_eq!(count(&pool, &format!("CHANNEL_{CHANNEL_ID}")).await, 3);
+}
+
+#[tokio::test]
+async fn bad_entries_dont_lose_the_batch() {
+	let pool = pool().await;
+
+	let entry = |channel_id: &str| db::LogEntry::MarkovPair {
+		channel_id: channel_id.into(),
+		word:       "forsen".into(),
+		succ:       "LULW".into(),
+	};
+
+	// there are no tables for channel 1
+	db::write_log_batch(&pool, &[entry("1"), entry(&CHANNEL_ID.to_string())]).await.unwrap();
+
+	assert_eq!(count(&pool, &format!("CHANNEL_{CHANNEL_ID}_MARKOV")).await, 1);
+}
+
+#[tokio::test]
+async fn flush_writes_everything_queued() {
+	let pool = pool().await;
+	let (writer, _) = db::LogWriter::spawn(pool.clone());
+
+	writer.log(&privmsg("forsen"));
+	writer.flush().await;
+
+	assert_eq!(count(&pool, &format!("CHANNEL_{CHANNEL_ID}")).await, 1);
+}
+
+#[tokio::test]
+async fn trivia_games_survive_a_restart() {
+	let pool = pool().await;
+
+	let mut game = TriviaGameInfo::new(
+		"Is forsen a streamer?".into(),
+		"True".into(),
+		vec!["False".into()],
+		"easy".into(),
+	);
+	game.round = TriviaRound {
+		options: vec!["true".into(), "false".into()],
+		current: 3,
+		length:  10,
+		scores:  vec![("forsen".into(), 4)],
+	};
+	game.hinted = true;
+
+	db::save_trivia_game(&pool, &CHANNEL_ID.to_string(), &game).await.unwrap();
+
+	let saved = db::take_saved_trivia_games(&pool).await.unwrap();
+	assert_eq!(saved.len(), 1);
+	assert_eq!(saved[0].0, CHANNEL_ID.to_string());
+	assert_eq!(saved[0].1.wrong_answers, vec!["False"]);
+	assert_eq!(saved[0].1.round, game.round);
+	// there's the whole time for answering again
+	assert!(!saved[0].1.hinted);
+
+	// the games are only picked up once
+	assert!(db::take_saved_trivia_games(&pool).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn markov_pairs_are_counted() {
+	let pool = pool().await;
+
+	let entry = |succ: &str| db::LogEntry::MarkovPair {
+		channel_id: CHANNEL_ID.to_string(),
+		word:       "forsen".into(),
+		succ:       succ.into(),
+	};
+
+	db::write_log_batch(&pool, &[entry("LULW"), entry("LULW"), entry("forsenE")]).await.unwrap();
+	db::write_log_batch(&pool, &[entry("LULW")]).await.unwrap();
+
+	let counts: Vec<(String, i64)> = sqlx::query_as(&format!(
+		"SELECT succ, count FROM CHANNEL_{CHANNEL_ID}_MARKOV ORDER BY succ"
+	))
+		.fetch_all(&pool)
+		.await
+		.unwrap();
+
+	assert_eq!(counts, vec![("LULW".into(), 3), ("forsenE".into(), 1)]);
+}
+
+#[tokio::test]
+async fn old_markov_tables_get_compacted() {
+	let pool = SqlitePoolOptions::new()
+		.max_connections(1)
+		.connect("sqlite::memory:")
+		.await
+		.unwrap();
+
+	// the way the table used to look, with a row per occurence
+	sqlx::query(&format!(
+		"CREATE TABLE CHANNEL_{CHANNEL_ID}_MARKOV (id INTEGER PRIMARY KEY, word TEXT NOT NULL, succ TEXT NOT NULL);
+		INSERT INTO CHANNEL_{CHANNEL_ID}_MARKOV (word, succ) VALUES ('forsen', 'LULW'), ('forsen', 'LULW'), ('forsen', 'forsenE');"
+	))
+		.execute(&pool)
+		.await
+		.unwrap();
+
+	db::init_db(&pool).await.unwrap();
+	db::try_create_tables_for_channel(&pool, CHANNEL_ID).await.unwrap();
+	// the migration only runs once
+	db::try_create_tables_for_channel(&pool, CHANNEL_ID).await.unwrap();
+
+	let counts: Vec<(String, i64)> = sqlx::query_as(&format!(
+		"SELECT succ, count FROM CHANNEL_{CHANNEL_ID}_MARKOV ORDER BY succ"
+	))
+		.fetch_all(&pool)
+		.await
+		.unwrap();
+
+	assert_eq!(counts, vec![("LULW".into(), 2), ("forsenE".into(), 1)]);
+}
+
+#[tokio::test]
+async fn old_trivia_games_get_rounds() {
+	let pool = SqlitePoolOptions::new()
+		.max_connections(1)
+		.connect("sqlite::memory:")
+		.await
+		.unwrap();
+
+	// a game saved before there were rounds
+	sqlx::query(
+		"CREATE TABLE ongoing_trivia_games (channel_id TEXT PRIMARY KEY, question TEXT NOT NULL, correct_answer TEXT NOT NULL, wrong_answers TEXT NOT NULL);
+		INSERT INTO ongoing_trivia_games VALUES ('1', 'Is forsen a streamer?', 'True', '[\"False\"]');"
+	)
+		.execute(&pool)
+		.await
+		.unwrap();
+
+	db::init_db(&pool).await.unwrap();
+	db::init_db(&pool).await.unwrap();
+
+	let saved = db::take_saved_trivia_games(&pool).await.unwrap();
+	assert_eq!(saved[0].1.difficulty, "medium");
+	assert_eq!(saved[0].1.round, TriviaRound::default());
+}
+
+#[tokio::test]
+async fn hooks_survive_a_restart() {
+	let pool = pool().await;
+
+	let mut hook = MessageHook::new("greeting".into(), HookMatchType::Word, "hi".into(), "hello".into()).unwrap();
+	hook.cooldown_s = 30;
+	hook.users      = vec!["forsen".into(), "nymn".into()];
+	hook.badges     = vec![TwitchBadge::Subscriber, TwitchBadge::Vip];
+	db::set_hook(&pool, CHANNEL_ID, &hook).await.unwrap();
+
+	let regex = MessageHook::new("laugh".into(), HookMatchType::Regex, "^(LUL|KEKW)+$".into(), "xD".into()).unwrap();
+	db::set_hook(&pool, CHANNEL_ID, &regex).await.unwrap();
+	assert!(db::set_hook_enabled(&pool, CHANNEL_ID, "laugh", false).await.unwrap());
+	assert!(!db::set_hook_enabled(&pool, CHANNEL_ID, "nothing", false).await.unwrap());
+
+	let hooks = db::get_channel_hooks(&pool, CHANNEL_ID).await.unwrap();
+	assert_eq!(hooks.len(), 2);
+	assert_eq!(hooks[0].name, "greeting");
+	assert_eq!(hooks[0].cooldown_s, 30);
+	assert_eq!(hooks[0].users, vec!["forsen", "nymn"]);
+	assert_eq!(hooks[0].badges, vec![TwitchBadge::Subscriber, TwitchBadge::Vip]);
+	assert!(hooks[0].matches("oh HI there"));
+	assert!(!hooks[1].enabled);
+	assert!(hooks[1].matches("LULKEKW"));
+
+	db::count_hook_fire(&pool, CHANNEL_ID, "laugh").await.unwrap();
+	db::count_hook_fire(&pool, CHANNEL_ID, "laugh").await.unwrap();
+	db::set_hook(&pool, CHANNEL_ID, &regex).await.unwrap();
+	assert_eq!(db::get_channel_hooks(&pool, CHANNEL_ID).await.unwrap()[1].count, 2);
+
+	assert!(db::remove_hook(&pool, CHANNEL_ID, "laugh").await.unwrap());
+	assert!(!db::remove_hook(&pool, CHANNEL_ID, "laugh").await.unwrap());
+	assert_eq!(db::get_channel_hooks(&pool, CHANNEL_ID).await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn old_hooks_get_migrated() {
+	let pool = SqlitePoolOptions::new()
+		.max_connections(1)
+		.connect("sqlite::memory:")
+		.await
+		.unwrap();
+
+	// a hook saved before there were cooldowns and filters
+	sqlx::query(&format!(
+		"CREATE TABLE CHANNEL_{CHANNEL_ID}_HOOKS (id INTEGER PRIMARY KEY, name TEXT UNIQUE, type TEXT NOT NULL, capture_string TEXT NOT NULL, content TEXT NOT NULL);
+		INSERT INTO CHANNEL_{CHANNEL_ID}_HOOKS (name, type, capture_string, content) VALUES ('greeting', 'substr', 'forsenE', 'hi');"
+	))
+		.execute(&pool)
+		.await
+		.unwrap();
+
+	db::init_db(&pool).await.unwrap();
+	db::try_create_tables_for_channel(&pool, CHANNEL_ID).await.unwrap();
+	db::try_create_tables_for_channel(&pool, CHANNEL_ID).await.unwrap();
+
+	let hooks = db::get_channel_hooks(&pool, CHANNEL_ID).await.unwrap();
+	assert_eq!(hooks.len(), 1);
+	assert!(hooks[0].enabled);
+	assert_eq!(hooks[0].cooldown_s, 0);
+	assert_eq!(hooks[0].count, 0);
+	assert!(hooks[0].matches("forsenE forsenE"));
+}
+
+#[tokio::test]
+async fn old_commands_get_migrated() {
+	let pool = SqlitePoolOptions::new()
+		.max_connections(1)
+		.connect("sqlite::memory:")
+		.await
+		.unwrap();
+
+	// a command saved before there were usage counts and limits
+	sqlx::query(&format!(
+		"CREATE TABLE CHANNEL_{CHANNEL_ID}_COMMANDS (id INTEGER PRIMARY KEY, name TEXT UNIQUE, type TEXT NOT NULL, expression TEXT NOT NULL, metadata INTEGER DEFAULT 0);
+		INSERT INTO CHANNEL_{CHANNEL_ID}_COMMANDS (name, type, expression, metadata) VALUES ('hello', 'paste', 'Hello!', 5);"
+	))
+		.execute(&pool)
+		.await
+		.unwrap();
+
+	db::init_db(&pool).await.unwrap();
+	db::try_create_tables_for_channel(&pool, CHANNEL_ID).await.unwrap();
+	db::try_create_tables_for_channel(&pool, CHANNEL_ID).await.unwrap();
+
+	let cmd = db::get_channel_cmd(&pool, CHANNEL_ID, "hello").await.unwrap().unwrap();
+	assert_eq!(cmd.uses, 5);
+	assert_eq!(cmd.creator, "");
+	assert_eq!(cmd.created_at, None);
+	assert_eq!(cmd.permission, "everyone");
+
+	db::count_cmd_use(&pool, CHANNEL_ID, "hello", Utc::now()).await.unwrap();
+	let cmd = db::get_channel_cmd(&pool, CHANNEL_ID, "hello").await.unwrap().unwrap();
+	assert_eq!((cmd.uses, cmd.metadata), (6, 5));
+	assert!(cmd.last_used.is_some());
+}
+
+#[tokio::test]
+async fn markov_rebuilds_take_turns_and_keep_new_messages() {
+	let pool = pool().await;
+	let log = |text: &'static str| {
+		let pool = pool.clone();
+		async move {
+			sqlx::query(&format!(
+				"INSERT INTO CHANNEL_{CHANNEL_ID} (sender_id, sender_nick, badges, timestamp, message) VALUES (1, 'forsen', '', '2022-04-20 12:00:00', ?1)"
+			))
+				.bind(text)
+				.execute(&pool)
+				.await
+				.unwrap();
+		}
+	};
+
+	log("old").await;
+
+	assert!(db::start_markov_rebuild(&pool, CHANNEL_ID).await.unwrap());
+	assert!(!db::start_markov_rebuild(&pool, CHANNEL_ID).await.unwrap());
+	db::write_markov_rebuild_batch(&pool, CHANNEL_ID, &HashMap::from([(("x".into(), "old".into()), 1)])).await.unwrap();
+
+	// a message (and its pair in the old index) coming in during the rebuild
+	log("new").await;
+	sqlx::query(&format!("INSERT INTO CHANNEL_{CHANNEL_ID}_MARKOV (word, succ) VALUES ('x', 'new')")).execute(&pool).await.unwrap();
+
+	db::finish_markov_rebuild(&pool, CHANNEL_ID, 1, |messages| {
+		messages
+			.iter()
+			.map(|m| (("x".to_owned(), m.message.clone().unwrap()), 1))
+			.collect()
+	}).await.unwrap();
+
+	let pairs: Vec<(String, String, i64)> = sqlx::query_as(&format!("SELECT word, succ, count FROM CHANNEL_{CHANNEL_ID}_MARKOV ORDER BY succ"))
+		.fetch_all(&pool)
+		.await
+		.unwrap();
+	assert_eq!(pairs, vec![("x".into(), "new".into(), 1), ("x".into(), "old".into(), 1)]);
+
+	// a rebuild that stopped checking in doesn't hold up the next one
+	assert!(db::start_markov_rebuild(&pool, CHANNEL_ID).await.unwrap());
+	sqlx::query("UPDATE markov_rebuilds SET heartbeat='2022-04-20T12:00:00Z'").execute(&pool).await.unwrap();
+	assert!(db::start_markov_rebuild(&pool, CHANNEL_ID).await.unwrap());
+	db::abort_markov_rebuild(&pool, CHANNEL_ID).await.unwrap();
+	assert!(db::start_markov_rebuild(&pool, CHANNEL_ID).await.unwrap());
+}
+
+#[tokio::test]
+async fn user_messages_skip_empty_rows() {
+	let pool = pool().await;
+
+	sqlx::query(&format!(
+		"INSERT INTO CHANNEL_{CHANNEL_ID} (sender_id, sender_nick, badges, timestamp, message) VALUES (1, 'forsen', '', '2022-04-20 12:00:00', 'forsenE');
+		INSERT INTO CHANNEL_{CHANNEL_ID} (sender_id, sender_nick, badges, timestamp, message) VALUES (1, 'forsen', '', '2022-04-20 12:00:01', NULL);"
+	))
+		.execute(&pool)
+		.await
+		.unwrap();
+
+	assert_eq!(db::get_user_messages(&pool, CHANNEL_ID, 1, 10).await.unwrap(), vec!["forsenE"]);
+}
+
+#[tokio::test]
+async fn copies_are_looked_up_by_hash() {
+	let pool = pool().await;
+
+	let plan: Vec<(i64, i64, i64, String)> = sqlx::query_as(&format!(
+		"EXPLAIN QUERY PLAN SELECT EXISTS (SELECT 1 FROM CHANNEL_{CHANNEL_ID} WHERE message_hash=?1 AND message=?2 COLLATE NOCASE);"
+	))
+		.bind(db::message_hash("forsenE"))
+		.bind("forsenE")
+		.fetch_all(&pool)
+		.await
+		.unwrap();
+
+	assert!(plan.iter().any(|row| row.3.contains(&format!("USING INDEX CHANNEL_{CHANNEL_ID}_MESSAGE_HASH"))), "{plan:?}");
+
+	let (writer, handle) = db::LogWriter::spawn(pool.clone());
+	writer.log(&privmsg("forsenE"));
+	drop(writer);
+	handle.await.unwrap();
+
+	assert_eq!(db::message_hash("FORSENE"), db::message_hash("forsenE"));
+	assert!(db::message_was_logged(&pool, CHANNEL_ID, "FORSENE").await.unwrap());
+	assert!(!db::message_was_logged(&pool, CHANNEL_ID, "forsenE forsenE").await.unwrap());
+}
+
+#[tokio::test]
+async fn old_logs_get_hashed() {
+	let pool = SqlitePoolOptions::new()
+		.max_connections(1)
+		.connect("sqlite::memory:")
+		.await
+		.unwrap();
+
+	// a log from before messages were hashed, with the index on their whole text
+	sqlx::query(&format!(
+		"CREATE TABLE CHANNEL_{CHANNEL_ID} (id INTEGER PRIMARY KEY, sender_id INTEGER NOT NULL, sender_nick TEXT NOT NULL, badges TEXT, timestamp TEXT NOT NULL, message TEXT);
+		CREATE INDEX CHANNEL_{CHANNEL_ID}_MESSAGE ON CHANNEL_{CHANNEL_ID} (message COLLATE NOCASE);
+		INSERT INTO CHANNEL_{CHANNEL_ID} (sender_id, sender_nick, badges, timestamp, message) VALUES (1, 'forsen', '', '2022-04-20 12:00:00', 'forsenE');
+		INSERT INTO CHANNEL_{CHANNEL_ID} (sender_id, sender_nick, badges, timestamp, message) VALUES (1, 'forsen', '', '2022-04-20 12:00:01', NULL);"
+	))
+		.execute(&pool)
+		.await
+		.unwrap();
+
+	db::init_db(&pool).await.unwrap();
+	db::try_create_tables_for_channel(&pool, CHANNEL_ID).await.unwrap();
+	db::try_create_tables_for_channel(&pool, CHANNEL_ID).await.unwrap();
+
+	assert!(db::message_was_logged(&pool, CHANNEL_ID, "FORSENE").await.unwrap());
+
+	let old_index: Vec<(String,)> = sqlx::query_as(&format!("SELECT name FROM sqlite_master WHERE name='CHANNEL_{CHANNEL_ID}_MESSAGE'"))
+		.fetch_all(&pool)
+		.await
+		.unwrap();
+	assert!(old_index.is_empty());
+}
+
+#[tokio::test]
+async fn trivia_scores_are_ranked() {
+	let pool = pool().await;
+
+	for (user_id, user_name, points) in [(1, "forsen", 3), (2, "xqc", 1), (1, "forsen", 1), (3, "nymn", 1)] {
+		db::add_trivia_points(&pool, CHANNEL_ID, user_id, user_name, points).await.unwrap();
+	}
+	// points in other channels don't count
+	db::add_trivia_points(&pool, 1, 2, "xqc", 100).await.unwrap();
+
+	let top = db::get_trivia_leaderboard(&pool, CHANNEL_ID, 2).await.unwrap();
+	assert_eq!(top.iter().map(|s| (s.user_name.as_str(), s.points, s.rank)).collect::<Vec<_>>(), vec![("forsen", 4, 1), ("nymn", 1, 2)]);
+
+	let score = db::get_trivia_score(&pool, CHANNEL_ID, 2).await.unwrap().unwrap();
+	assert_eq!(score, db::TriviaScore { user_name: "xqc".into(), points: 1, answers: 1, rank: 2 });
+	assert_eq!(db::get_trivia_score(&pool, CHANNEL_ID, 4).await.unwrap(), None);
+}
+
+#[test]
+fn trivia_questions_are_read_from_files() {
+	let questions = trivia::read_questions(Path::new("tests/fixtures/trivia/questions.json")).unwrap();
+	assert_eq!(questions.len(), 2);
+	// (the entities get decoded)
+	assert_eq!(questions[0].category, "Entertainment: Cartoon & Animations");
+	assert_eq!(questions[0].question, "What is the name of the dog in \"Scooby-Doo\"?");
+
+	let questions = trivia::read_questions(Path::new("tests/fixtures/trivia/questions.csv")).unwrap();
+	assert_eq!(questions[0].incorrect_answers, vec!["Oslo", "Helsinki", "Copenhagen"]);
+	assert_eq!(questions[1].category, "");
+	assert_eq!(questions[1].type_field, "boolean");
+
+	assert!(trivia::read_questions(Path::new("tests/fixtures/trivia/invalid.csv")).is_err());
+}
+
+#[tokio::test]
+async fn local_trivia_questions() {
+	let pool = pool().await;
+
+	let questions = trivia::read_questions(Path::new("tests/fixtures/trivia/questions.json")).unwrap();
+	assert_eq!(trivia::import_questions(&pool, &questions).await.unwrap(), 2);
+	// the same questions aren't added twice
+	assert_eq!(trivia::import_questions(&pool, &questions).await.unwrap(), 0);
+
+	let q = db::get_random_trivia_question(&pool, CHANNEL_ID, false, Some("Science & Nature"), None, None).await.unwrap().unwrap();
+	assert_eq!(q.correct_answer, "True");
+	assert_eq!(q.wrong_answers, "[\"False\"]");
+
+	let none = db::get_random_trivia_question(&pool, CHANNEL_ID, false, Some("Science & Nature"), Some("easy"), None).await.unwrap();
+	assert!(none.is_none());
+
+	// the imported questions are nobody's own
+	assert!(db::get_random_trivia_question(&pool, CHANNEL_ID, true, None, None, None).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn markov_successors_are_weighted_by_count() {
+	let pool = pool().await;
+
+	sqlx::query(&format!(
+		"INSERT INTO CHANNEL_{CHANNEL_ID}_MARKOV (word, succ, count) VALUES ('forsen', 'LULW', 99), ('forsen', 'forsenE', 1)"
+	))
+		.execute(&pool)
+		.await
+		.unwrap();
+
+	let mut lulws = 0;
+	for _ in 0..50 {
+		// (the lookup doesn't care about the case)
+		match db::get_rand_markov_succ(&pool, &[CHANNEL_ID], "FORSEN").await.unwrap().as_deref() {
+			Some("LULW")    => lulws += 1,
+			Some("forsenE") => (),
+			other           => panic!("unexpected successor {other:?}"),
+		}
+	}
+
+	assert!(lulws > 40);
+	assert_eq!(db::get_rand_markov_succ(&pool, &[CHANNEL_ID], "nothing").await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn markov_indexes_can_be_merged() {
+	let pool = pool().await;
+	let other_id = 71092938;
+	db::try_create_tables_for_channel(&pool, other_id).await.unwrap();
+
+	for (channel_id, succ) in [(CHANNEL_ID, "forsenE"), (other_id, "xqcL")] {
+		sqlx::query(&format!("INSERT INTO CHANNEL_{channel_id}_MARKOV (word, succ) VALUES ('hi', ?1)"))
+			.bind(succ)
+			.execute(&pool)
+			.await
+			.unwrap();
+	}
+
+	let mut seen = vec![];
+	for _ in 0..50 {
+		seen.push(db::get_rand_markov_succ(&pool, &[CHANNEL_ID, other_id], "hi").await.unwrap().unwrap());
+	}
+
+	assert!(seen.iter().any(|s| s == "forsenE") && seen.iter().any(|s| s == "xqcL"));
+	assert_eq!(db::get_rand_markov_succ(&pool, &[], "hi").await.unwrap(), None);
+}
+
+#[test]
+fn markov_transitions_go_from_start_to_end() {
+	let tokens = ["forsen", "is", "live"].map(String::from);
+
+	let pairs = markov::transitions(&tokens, 2);
+	let pair = |state: &str, succ: &str| (state.to_owned(), succ.to_owned());
+
+	assert_eq!(pairs, vec![
+		pair(markov::START, "forsen"),
+		pair("forsen", "is"),
+		pair(&format!("{} forsen", markov::START), "is"),
+		pair("is", "live"),
+		pair("forsen is", "live"),
+		pair("live", markov::END),
+		pair("is live", markov::END),
+	]);
+
+	assert!(markov::transitions(&[], 2).is_empty());
+}
+
+#[test]
+fn markov_tokens_skip_links_and_punctuation() {
+	let emote_cache = EmoteCache { channels: HashMap::new(), globals: vec![] };
+
+	let tokens = markov::tokens(&privmsg("Look at  https://forsen.tv, \"NOW\"!"), &emote_cache);
+
+	assert_eq!(tokens, vec!["look", "at", "now"]);
+}
+
+#[tokio::test]
+async fn markov_chains_end_on_their_own() {
+	let pool = pool().await;
+	let (writer, _) = db::LogWriter::spawn(pool.clone());
+	let emote_cache = EmoteCache { channels: HashMap::new(), globals: vec![] };
+
+	writer.log_with_markov(&emote_cache, &privmsg("forsen is live"), 2);
+	writer.log_markov(&emote_cache, &privmsg("xqc is live now"), 2);
+	writer.flush().await;
+
+	let mut req = markov::MarkovRequest::new(&Default::default());
+	req.min_words = 1;
+	req.allow_copies = true;
+
+	for _ in 0..20 {
+		let text = markov::generate(&pool, &markov::Corpus::Index(vec![CHANNEL_ID]), 2, &req).await.unwrap().unwrap();
+		assert!(["forsen is live", "xqc is live now", "forsen is live now", "xqc is live"].contains(&text.as_str()), "{text}");
+	}
+
+	// the only way to say something new is to mix the two
+	req.allow_copies = false;
+	for _ in 0..20 {
+		if let Some(text) = markov::generate(&pool, &markov::Corpus::Index(vec![CHANNEL_ID]), 2, &req).await.unwrap() {
+			assert_ne!(text, "forsen is live");
+		}
+	}
+
+	req.seed = Some("nothing".into());
+	assert_eq!(markov::generate(&pool, &markov::Corpus::Index(vec![CHANNEL_ID]), 2, &req).await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn markov_user_models() {
+	let pool = pool().await;
+	let emote_cache = EmoteCache { channels: HashMap::new(), globals: vec!["forsenE".into()] };
+
+	let messages = ["forsenE is live", "FORSENE is LIVE"]
+		.map(|m| markov::text_tokens(m, "forsen", &emote_cache));
+	assert_eq!(messages[0], vec!["forsenE", "is", "live"]);
+	let model = markov::MarkovModel::build(&messages, 2);
+
+	let mut req = markov::MarkovRequest::new(&Default::default());
+	req.allow_copies = true;
+
+	let text = markov::generate(&pool, &markov::Corpus::Model(CHANNEL_ID, &model), 2, &req).await.unwrap();
+	assert!(matches!(text.as_deref(), Some("forsenE is live" | "forsene is live")), "{text:?}");
+
+	req.seed = Some("nothing".into());
+	let empty = markov::MarkovModel::default();
+	assert_eq!(markov::generate(&pool, &markov::Corpus::Model(CHANNEL_ID, &empty), 2, &req).await.unwrap(), None);
+}
+
+#[test]
+fn markov_options() {
+	let args = |s: &str| s.split(' ').map(String::from).collect::<Vec<_>>();
+	let config = markov::MarkovConfig::default();
+
+	let req = markov::MarkovRequest::from_args(&args("forsen 5 min:2 copies:yes"), &config).unwrap();
+	assert_eq!(req.seed.as_deref(), Some("forsen"));
+	assert_eq!((req.min_words, req.max_words, req.allow_copies), (2, 5, true));
+
+	// the minimum can't be over the maximum
+	let req = markov::MarkovRequest::from_args(&args("min:50 max:10"), &config).unwrap();
+	assert_eq!((req.seed, req.min_words, req.max_words), (None, 10, 10));
+
+	let req = markov::MarkovRequest::from_args(&args("user:@Forsen channel:#xqcow"), &config).unwrap();
+	assert_eq!((req.user.as_deref(), req.channel.as_deref()), (Some("forsen"), Some("xqcow")));
+
+	assert!(markov::MarkovRequest::from_args(&args("forsen many"), &config).is_err());
+}
+
+#[tokio::test]
+async fn user_name_changes() {
+	let pool = pool().await;
+	let day = |d: u32| chrono::TimeZone::ymd(&Utc, 2022, 4, d).and_hms(12, 0, 0);
+
+	// the first time a user is seen, they didn't change anything
+	assert_eq!(db::update_user_name(&pool, 1, "xqcow", day(1)).await.unwrap(), None);
+	assert_eq!(db::update_user_name(&pool, 1, "xqcow", day(2)).await.unwrap(), None);
+	assert!(db::get_name_changes(&pool, 1).await.unwrap().is_empty());
+
+	assert_eq!(db::update_user_name(&pool, 1, "xqc", day(3)).await.unwrap().as_deref(), Some("xqcow"));
+	let changes = db::get_name_changes(&pool, 1).await.unwrap();
+	assert_eq!(changes.len(), 1);
+	assert_eq!((changes[0].old_name.as_str(), changes[0].new_name.as_str(), changes[0].timestamp), ("xqcow", "xqc", day(3)));
+
+	// the old name still leads to the user, by their current one
+	assert_eq!(db::user_from_known_name(&pool, "XQCOW").await.unwrap(), Some((1, "xqc".into())));
+	assert_eq!(db::user_from_known_name(&pool, "xqc").await.unwrap(), Some((1, "xqc".into())));
+	assert_eq!(db::user_from_known_name(&pool, "nymn").await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn user_names_are_picked_up_from_the_logs() {
+	let pool = pool().await;
+
+	// (the logs of a channel from before the names were tracked)
+	sqlx::query(
+		"CREATE TABLE CHANNEL_1 (id INTEGER PRIMARY KEY, sender_id INTEGER NOT NULL, sender_nick TEXT NOT NULL, \
+		badges TEXT, timestamp TEXT NOT NULL, message TEXT)"
+	).execute(&pool).await.unwrap();
+	let logged = [
+		(1, "xqcOW", "2022-04-01 12:00:00"),
+		(2, "NymN",  "2022-04-01 12:00:01"),
+		(1, "xqcow", "2022-04-02 12:00:00"),
+		(1, "xQc",   "2022-04-03 12:00:00"),
+		(2, "ニム",   "2022-04-04 12:00:00"),
+	];
+	for (id, nick, timestamp) in logged {
+		sqlx::query("INSERT INTO CHANNEL_1 (sender_id, sender_nick, timestamp, message) VALUES (?, ?, ?, 'hi')")
+			.bind(id).bind(nick).bind(timestamp)
+			.execute(&pool).await.unwrap();
+	}
+
+	db::try_create_tables_for_channel(&pool, 1).await.unwrap();
+	// (only once)
+	db::try_create_tables_for_channel(&pool, 1).await.unwrap();
+
+	let changes = db::get_name_changes(&pool, 1).await.unwrap();
+	assert_eq!(changes.len(), 1);
+	assert_eq!((changes[0].old_name.as_str(), changes[0].new_name.as_str()), ("xqcow", "xqc"));
+	// (a display name that isn't the login doesn't count as a change)
+	assert!(db::get_name_changes(&pool, 2).await.unwrap().is_empty());
+
+	assert_eq!(db::user_from_known_name(&pool, "xqcow").await.unwrap(), Some((1, "xqc".into())));
+	assert_eq!(db::user_from_known_name(&pool, "nymn").await.unwrap(), Some((2, "nymn".into())));
+	// and a user seen already is only told apart by an actual change
+	assert_eq!(db::update_user_name(&pool, 1, "xqc", Utc::now()).await.unwrap(), None);
+}
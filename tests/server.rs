@@ -0,0 +1,301 @@
+mod common;
+
+use common::{Bot, CHANNEL_ID};
+use twitch_bot::{db, server};
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+
+fn serve(bot: &Bot) -> SocketAddr {
+	let (addr, _) = server::spawn(bot.state.clone(), "127.0.0.1:0".parse().unwrap()).unwrap();
+
+	addr
+}
+
+async fn get(addr: SocketAddr, path: &str) -> Value {
+	let res = reqwest::get(format!("http://{addr}{path}")).await.unwrap();
+	assert_eq!(res.status(), StatusCode::OK, "GET {path}");
+
+	res.json().await.unwrap()
+}
+
+// —————————————————————————————————————————
+//               Metrics
+// —————————————————————————————————————————
+
+#[tokio::test]
+async fn metrics() {
+	let bot = Bot::new().await;
+	bot.run("$ping").await.unwrap();
+	bot.run("$weather Down").await.unwrap();
+
+	let addr = serve(&bot);
+	let res = reqwest::get(format!("http://{addr}/metrics")).await.unwrap();
+	assert!(res.status().is_success());
+
+	let body = res.text().await.unwrap();
+	assert!(body.contains("twitch_bot_commands_executed_total{command=\"ping\"}"));
+	assert!(body.contains("twitch_bot_command_duration_seconds_bucket{command=\"weather\""));
+	// the failed attempts against the mock server
+	assert!(body.contains("twitch_bot_api_errors_total{host=\"127.0.0.1\",kind=\"503\"}"));
+}
+
+#[tokio::test]
+async fn metrics_can_be_turned_off() {
+	let mut bot = Bot::new().await;
+	Arc::get_mut(&mut bot.state).unwrap().config.server.metrics = false;
+
+	let addr = serve(&bot);
+	let res = reqwest::get(format!("http://{addr}/metrics")).await.unwrap();
+
+	assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+// —————————————————————————————————————————
+//               Dashboard
+// —————————————————————————————————————————
+
+#[tokio::test]
+async fn dashboard_page() {
+	let bot = Bot::new().await;
+	let addr = serve(&bot);
+
+	let res = reqwest::get(format!("http://{addr}/")).await.unwrap();
+	assert!(res.text().await.unwrap().contains("<title>twitch-bot dashboard</title>"));
+
+	assert_eq!(get(addr, "/dashboard/channels").await, json!([{ "id": CHANNEL_ID, "name": "forsen" }]));
+
+	let res = reqwest::get(format!("http://{addr}/dashboard/channels/xqcow/commands")).await.unwrap();
+	assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn paginated_logs() {
+	let bot = Bot::new().await;
+	for i in 0..5 {
+		sqlx::query(&format!(
+			"INSERT INTO CHANNEL_{CHANNEL_ID} (sender_id, sender_nick, badges, timestamp, message) VALUES (1, 'a', '', '2022-04-20 12:00:0{i}', 'message {i}')"
+		))
+			.execute(&bot.state.pool)
+			.await
+			.unwrap();
+	}
+
+	let addr = serve(&bot);
+
+	let page = get(addr, "/dashboard/channels/forsen/logs?limit=3").await;
+	let messages = page["items"].as_array().unwrap();
+	assert_eq!(messages.len(), 3);
+	assert_eq!(messages[0]["message"], "message 4");
+
+	let next = page["next"].as_i64().unwrap();
+	let page = get(addr, &format!("/dashboard/channels/forsen/logs?limit=3&before={next}")).await;
+	assert_eq!(page["items"].as_array().unwrap().len(), 2);
+	assert_eq!(page["next"], Value::Null);
+}
+
+#[tokio::test]
+async fn command_history() {
+	let bot = Bot::new().await;
+	let cmd = bot.command("$ping");
+	db::log_command(&bot.state.pool, &cmd, Duration::from_millis(20), "pong").await.unwrap();
+
+	let addr = serve(&bot);
+	let page = get(addr, "/dashboard/command_history").await;
+
+	assert_eq!(page["items"][0]["command"], "ping");
+	assert_eq!(page["items"][0]["output"], "pong");
+}
+
+#[tokio::test]
+async fn editing_requires_a_mod_token() {
+	let bot = Bot::new().await;
+	let addr = serve(&bot);
+	let client = reqwest::Client::new();
+	let url = format!("http://{addr}/dashboard/channels/forsen/commands/hello");
+	let body = json!({ "type": "paste", "expression": "Hello!" });
+
+	let res = client.put(&url).json(&body).send().await.unwrap();
+	assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+	let res = client.put(&url).bearer_auth("wrong").json(&body).send().await.unwrap();
+	assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+	// (a token as long as the right one, and one that only starts like it)
+	for token in ["secreT", "secretsecret"] {
+		let res = client.put(&url).bearer_auth(token).json(&body).send().await.unwrap();
+		assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+	}
+
+	let huge = json!({ "type": "paste", "expression": "a".repeat(100_000) });
+	let res = client.put(&url).bearer_auth("secret").json(&huge).send().await.unwrap();
+	assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+	let res = client.put(&url).bearer_auth("secret").json(&body).send().await.unwrap();
+	assert_eq!(res.status(), StatusCode::OK);
+
+	assert_eq!(get(addr, "/dashboard/channels/forsen/commands").await[0]["expression"], "Hello!");
+	assert_eq!(get(addr, "/dashboard/channels/forsen/commands").await[0]["creator"], "forsen");
+	assert_eq!(bot.run("$hello").await.unwrap(), "Hello!");
+
+	let res = client.delete(&url).bearer_auth("secret").send().await.unwrap();
+	assert_eq!(res.status(), StatusCode::OK);
+	assert_eq!(get(addr, "/dashboard/channels/forsen/commands").await, json!([]));
+}
+
+#[tokio::test]
+async fn command_names_are_checked() {
+	let bot = Bot::new().await;
+	let addr = serve(&bot);
+	let client = reqwest::Client::new();
+	let body = json!({ "type": "paste", "expression": "Hello!" });
+
+	// (only a space, with a space, with the prefix, and a built-in command)
+	for name in ["%20", "a%20b", "%24a", "ping", "PING"] {
+		let url = format!("http://{addr}/dashboard/channels/forsen/commands/{name}");
+		let res = client.put(&url).bearer_auth("secret").json(&body).send().await.unwrap();
+		assert_eq!(res.status(), StatusCode::BAD_REQUEST, "{name}");
+	}
+	assert_eq!(get(addr, "/dashboard/channels/forsen/commands").await, json!([]));
+
+	let url = format!("http://{addr}/dashboard/channels/forsen/commands/Hello");
+	let res = client.put(&url).bearer_auth("secret").json(&body).send().await.unwrap();
+	assert_eq!(res.status(), StatusCode::OK);
+	assert_eq!(get(addr, "/dashboard/channels/forsen/commands").await[0]["name"], "hello");
+	assert_eq!(bot.run("$hello").await.unwrap(), "Hello!");
+}
+
+#[tokio::test]
+async fn editing_hooks() {
+	let bot = Bot::new().await;
+	let addr = serve(&bot);
+	let client = reqwest::Client::new();
+	let url = format!("http://{addr}/dashboard/channels/forsen/hooks/greeting");
+
+	for content in ["hi", "hello"] {
+		let body = json!({ "type": "substr", "capture_string": "forsenE", "content": content });
+		let res = client.put(&url).bearer_auth("secret").json(&body).send().await.unwrap();
+		assert_eq!(res.status(), StatusCode::OK);
+	}
+
+	// the second one replaced the first one, also in the running bot
+	let hooks = bot.state.channel(&CHANNEL_ID.to_string()).lock().await.hooks.clone();
+	assert_eq!(hooks.len(), 1);
+	assert_eq!(hooks[0].content, "hello");
+
+	let res = client.delete(&url).bearer_auth("secret").send().await.unwrap();
+	assert_eq!(res.status(), StatusCode::OK);
+	assert!(bot.state.channel(&CHANNEL_ID.to_string()).lock().await.hooks.is_empty());
+}
+
+// —————————————————————————————————————————
+//               JSON API
+// —————————————————————————————————————————
+
+async fn api_get(addr: SocketAddr, path: &str) -> (StatusCode, Value) {
+	let res = reqwest::Client::new()
+		.get(format!("http://{addr}/api/v1{path}"))
+		.header("X-Api-Key", "key")
+		.send()
+		.await
+		.unwrap();
+
+	(res.status(), res.json().await.unwrap())
+}
+
+async fn log_messages(bot: &Bot, sender_id: i32, messages: &[&str]) {
+	for (i, message) in messages.iter().enumerate() {
+		sqlx::query(&format!(
+			"INSERT INTO CHANNEL_{CHANNEL_ID} (sender_id, sender_nick, badges, timestamp, message) VALUES (?1, 'a', '', '2022-04-20 12:00:0{i}', ?2)"
+		))
+			.bind(sender_id)
+			.bind(message)
+			.execute(&bot.state.pool)
+			.await
+			.unwrap();
+	}
+}
+
+#[tokio::test]
+async fn api_requires_a_key() {
+	let bot = Bot::new().await;
+	let addr = serve(&bot);
+	let url = format!("http://{addr}/api/v1/users/forsen/last_seen");
+
+	let res = reqwest::get(&url).await.unwrap();
+	assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+	let res = reqwest::Client::new().get(&url).header("X-Api-Key", "wrong").send().await.unwrap();
+	assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn api_user_data() {
+	let bot = Bot::new().await;
+	log_messages(&bot, CHANNEL_ID, &["first", "second", "third"]).await;
+	let addr = serve(&bot);
+
+	let (status, res) = api_get(addr, "/users/forsen/last_seen").await;
+	assert_eq!(status, StatusCode::OK);
+	assert_eq!(res["channel"]["name"], "forsen");
+	assert_eq!(res["timestamp"], "2022-04-20T12:00:02Z");
+
+	let (_, res) = api_get(addr, "/channels/forsen/users/forsen/first_message").await;
+	assert_eq!(res["message"], "first");
+	// the same as what the chat command says
+	assert_eq!(bot.run("$first").await.unwrap(), "first");
+
+	let (_, res) = api_get(addr, "/channels/forsen/users/forsen/offline_time").await;
+	assert_eq!(res["seconds"], 0);
+
+	let (_, page) = api_get(addr, "/channels/forsen/users/forsen/messages?limit=2").await;
+	assert_eq!(page["items"][0]["message"], "third");
+	let (_, page) = api_get(addr, &format!("/channels/forsen/users/forsen/messages?limit=2&before={}", page["next"])).await;
+	assert_eq!(page["items"][0]["message"], "first");
+	assert_eq!(page["next"], Value::Null);
+
+	let (status, _) = api_get(addr, "/channels/xqcow/users/forsen/first_message").await;
+	assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn api_chatstats() {
+	let bot = Bot::new().await;
+	log_messages(&bot, 1, &["a", "b", "c"]).await;
+	log_messages(&bot, 2, &["a", "b"]).await;
+	log_messages(&bot, 3, &["a"]).await;
+	let addr = serve(&bot);
+
+	let (_, page) = api_get(addr, "/channels/forsen/chatstats?limit=2").await;
+	assert_eq!(page["items"][0]["user_id"], 1);
+	assert_eq!(page["items"][1]["messages"], 2);
+
+	let (_, page) = api_get(addr, &format!("/channels/forsen/chatstats?limit=2&offset={}", page["next"])).await;
+	assert_eq!(page["items"][0]["rank"], 3);
+	assert_eq!(page["items"][0]["user_id"], 3);
+	assert_eq!(page["next"], Value::Null);
+}
+
+#[tokio::test]
+async fn api_markov() {
+	let bot = Bot::new().await;
+	sqlx::query(&format!("INSERT INTO CHANNEL_{CHANNEL_ID}_MARKOV (word, succ) VALUES ('forsen', 'forsenE')"))
+		.execute(&bot.state.pool)
+		.await
+		.unwrap();
+	let addr = serve(&bot);
+
+	let (_, res) = api_get(addr, "/channels/forsen/markov?seed=forsen&words=2&min_words=1").await;
+	assert_eq!(res["text"], "forsen forsenE");
+
+	// the chain is shorter than asked for
+	let (status, _) = api_get(addr, "/channels/forsen/markov?seed=forsen&min_words=3").await;
+	assert_eq!(status, StatusCode::NOT_FOUND);
+
+	let (status, _) = api_get(addr, "/channels/forsen/markov?seed=nothing").await;
+	assert_eq!(status, StatusCode::NOT_FOUND);
+}